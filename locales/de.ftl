@@ -0,0 +1,199 @@
+## General
+
+help =
+    Ich verfolge dein Verkehrsmittel in deiner Nähe und benachrichtige dich mit seiner aktuellen Position.
+    Diese Befehle werden unterstützt:
+
+    /help — Zeigt das Hilfemenü mit allen Befehlen
+    /start — Beginne, dein Verkehrsmittel zu verfolgen.
+    /cancel — Bricht die Verfolgung ab.
+    /products — Wähle, welche Verkehrsmittel (Bus, Tram, ...) du sehen möchtest.
+    /language — Wähle die Sprache des Bots: de, en oder auto.
+    /settings — Ändere Update-Intervalle, Knöpfe pro Zeile, Sprache, Ruhezeiten, Gehgeschwindigkeit und gespeicherten Ort.
+    /mydata — Erhalte alle über dich gespeicherten Daten als JSON-Datei.
+    /forget — Löscht alle über dich gespeicherten Daten und beendet deine Verfolgungen.
+
+    In Gruppen:
+    /board — Zeigt die Abfahrten an der Haltestelle der Gruppe, jeder kann ein Verkehrsmittel verfolgen.
+    /setstop — Legt die Haltestelle der Gruppe fest, z. B. /setstop Alexanderplatz (nur Admins).
+    /clearstop — Entfernt die Haltestelle der Gruppe (nur Admins).
+invalid-message = Ich kann diese Nachricht nicht verarbeiten. Tippe /help, um die Bedienung zu sehen.
+cooldown = ⏳ Bitte etwas langsamer! Versuch es in { $seconds } { $seconds ->
+    [one] Sekunde
+   *[other] Sekunden
+} noch einmal.
+cancelled = 🚫 Abgebrochen! Mit /start kannst du von vorne beginnen.
+minutes-unit = { $minutes ->
+    [one] Minute
+   *[other] Minuten
+}
+
+## Address
+
+ask-city =
+    🔰 Lass uns ein Verkehrsmittel verfolgen 🚌🚇!
+
+    In welcher Stadt wohnst du?
+missing-city = ❌ Bitte schick mir die Stadt, in der du wohnst, damit ich loslegen kann.
+ask-address =
+    Super, du wohnst also in <b>{ $city }</b> 🏙!
+
+    Wie lautet deine <b>Adresse</b>, damit ich nach Haltestellen in der Nähe suchen kann?
+    Du kannst mir stattdessen auch einen <b>Standort</b> 📍 schicken!
+missing-address = ❌ Bitte schick mir deine Adresse, damit ich loslegen kann.
+ask-new-address = Ok, schick mir die neue <b>Adresse</b> oder einen <b>Standort</b> 📍.
+last-address =
+    Ich habe deine zuletzt genutzte Adresse gefunden:
+    <b>{ $addr }, { $city } 📍</b>
+
+    Wähle jetzt die Haltestelle, an der du ein Verkehrsmittel verfolgen möchtest 👀.
+
+    Hier sind die Haltestellen in der Nähe:
+last-area =
+    Ich habe die Gegend deines zuletzt genutzten Standorts in <b>{ $city } 📍</b> gefunden.
+
+    Wähle jetzt die Haltestelle, an der du ein Verkehrsmittel verfolgen möchtest 👀.
+
+    Hier sind die Haltestellen in der Nähe:
+last-stop =
+    Ich habe deine zuletzt genutzte Haltestelle gefunden: <b>{ $stop } 🚏</b>
+
+    Wähle sie, um ihre Abfahrten zu sehen, oder ändere deine Adresse, um andere Haltestellen zu sehen:
+address-saved =
+    Danke! Deine Adresse ist also:
+    <b>{ $addr }, { $city } 📍</b>
+
+    Wähle jetzt die Haltestelle, an der du ein Verkehrsmittel verfolgen möchtest 👀.
+
+    Hier sind die Haltestellen in der Nähe:
+button-change-address = << Adresse ändern
+button-station = { $name } · { $distance } · 🚶 { $minutes } Min. · { $products }
+button-more-stations = 🔍 Weitere Haltestellen
+
+## Boards
+
+board-title-departures = 🚏 Abfahrten an der gewählten Haltestelle (+ Min. Verspätung): <b>{ $stop }</b>
+board-title-arrivals = 🚏 Ankünfte an der gewählten Haltestelle (+ Min. Verspätung): <b>{ $stop }</b>
+board-from = 🕒 Ab <b>{ $time }</b>
+board-departure = { $product } <b>{ $name }</b> nach <b>{ $direction }</b>
+board-arrival = { $product } <b>{ $name }</b> von <b>{ $direction }</b>
+board-more = … und { $count } weitere, die du mit <b>Später »</b> siehst.
+cancelled-mark = fällt aus
+select-transit = Wähle ein Verkehrsmittel oder schick mir eine Uhrzeit wie <b>17:30</b>, um spätere Verbindungen zu sehen:
+invalid-time = ❌ Bitte wähle ein Verkehrsmittel oder schick mir eine Uhrzeit wie <b>17:30</b>.
+no-departures = 😟 Leider wurden zurzeit keine Abfahrten an dieser Haltestelle gefunden.
+no-arrivals = 😟 Leider wurden zurzeit keine Ankünfte an dieser Haltestelle gefunden.
+no-departures-start = 😟 Leider wurden zurzeit keine Abfahrten an dieser Haltestelle gefunden. Bitte beginne mit /start von vorne!
+button-arrivals = ⇄ Ankünfte
+button-departures = ⇄ Abfahrten
+button-earlier = « Früher
+button-later = Später »
+
+## Tracking
+
+ask-minutes =
+    Wähle, nach wie vielen <b>Minuten</b> ich dich jeweils informieren soll, oder wie viele Minuten bevor dein Verkehrsmittel ankommt.
+    Du kannst mir auch ein Intervall wie <b>4</b> oder Minuten wie <b>10, 5, 2</b> schicken:
+invalid-plan = ❌ Bitte schick mir ein Intervall von 1 bis { $interval } Minuten wie <b>4</b> oder bis zu { $count } Zeitpunkte von 1 bis { $minutes } Minuten bevor dein Verkehrsmittel ankommt wie <b>10, 5, 2</b>.
+plan-info = Ich melde mich <b>{ $minutes }</b> Minuten bevor dein Verkehrsmittel ankommt!
+button-plan = 🔔 { $minutes } Min. vorher
+too-many-trackings = ❌ Du verfolgst bereits { $max } Verkehrsmittel, mehr gehen nicht gleichzeitig. Brich zuerst eines mit /cancel ab.
+timer-info = Ich melde mich alle <b>{ $minutes }</b> { minutes-unit }!
+walk-info = Du brauchst zu Fuß etwa <b>{ $minutes }</b> { minutes-unit } bis <b>{ $stop }</b> 🚶, ich sage dir, wann du losgehen musst!
+leave-now = 🚶 Geh jetzt los! Dein Verkehrsmittel: <b>{ $transit }</b> 🚌 erreicht <b>{ $stop }</b> in <b>{ $minutes }</b> { minutes-unit }!
+countdown = 🔔 Dein Verkehrsmittel: <b>{ $transit }</b> 🚌 kommt in <b>{ $minutes }</b> { minutes-unit } ⌛!
+leave-in = Geh in <b>{ $minutes }</b> { minutes-unit } los 🚶
+arriving-now = 🔔 Dein Verkehrsmittel: <b>{ $transit }</b> 🚌 sollte jetzt ankommen!
+on-time = ⏱ Dein Verkehrsmittel: <b>{ $transit }</b> 🚌 ist wieder pünktlich!
+delayed = ⏱ Dein Verkehrsmittel: <b>{ $transit }</b> 🚌 hat <b>{ $minutes }</b> { minutes-unit } Verspätung!
+early = ⏱ Dein Verkehrsmittel: <b>{ $transit }</b> 🚌 ist <b>{ $minutes }</b> { minutes-unit } zu früh!
+position-info = Aktuelle Position des Verkehrsmittels:
+departing = 🔔 Dein Verkehrsmittel: <b>{ $transit }</b> 🚌 fährt von <b>{ $stop }</b> ab!
+arriving = 🔔 Dein Verkehrsmittel: <b>{ $transit }</b> 🚌 kommt in <b>{ $stop }</b> an!
+transit-cancelled = 🚫 Dein Verkehrsmittel: <b>{ $transit }</b> 🚌 fällt aus! Mit /start kannst du von vorne beginnen.
+tracking-stopped = ⏹ Der Bot startet neu, daher wurde die Verfolgung deines Verkehrsmittels: <b>{ $transit }</b> 🚌 beendet. Starte sie mit /start erneut.
+closer-stop = 🚶 Von deinem Standort aus ist <b>{ $stop }</b> die nächste Haltestelle, an der du dein Verkehrsmittel: <b>{ $transit }</b> 🚌 noch erreichst, { $distance } oder etwa <b>{ $minutes }</b> { minutes-unit } zu Fuß. Es ist um <b>{ $time }</b> dort.
+button-trip = 🛤 Fahrtverlauf
+button-cancel = << Abbrechen
+
+## Trips
+
+trip-title = 🛤 Fahrtverlauf von <b>{ $name }</b> nach <b>{ $direction }</b>:
+no-trip = 😟 Leider gibt es keine Informationen zum Fahrtverlauf dieses Verkehrsmittels.
+
+## Products
+
+products-select = Wähle, welche <b>Verkehrsmittel</b> du sehen möchtest, und drücke <b>Fertig</b>, wenn du fertig bist:
+products-saved = ✅ Deine Verkehrsmittel wurden gespeichert! Mit /start kannst du loslegen.
+products-keep-one = Lass mindestens ein Verkehrsmittel an, sonst werden keine Verbindungen gefunden.
+button-done = Fertig
+product-bus = Bus
+product-tram = Tram
+product-subway = U-Bahn
+product-suburban = S-Bahn
+product-regional = Regionalzug
+product-national = Fernzug
+
+## Language
+
+language-usage = Schick <b>/language de</b> für Deutsch, <b>/language en</b> für Englisch oder <b>/language auto</b>, um die Sprache deiner Telegram-App zu verwenden.
+language-saved = ✅ Ab jetzt spreche ich Deutsch mit dir!
+
+## Settings
+
+settings-title = ⚙️ Deine <b>Einstellungen</b>, drücke einen Knopf, um sie zu ändern, und <b>Fertig</b>, wenn du fertig bist:
+settings-saved = ✅ Deine Einstellungen wurden gespeichert! Mit /start kannst du loslegen.
+settings-intervals = ⏱ Update-Intervalle: { $intervals } Min.
+settings-columns = ⌨️ Knöpfe pro Zeile: { $columns }
+settings-language = 🌐 Sprache: { $language }
+settings-quiet-hours = 🌙 Ruhezeiten: { $hours }
+settings-walking-speed = 🚶 Gehgeschwindigkeit: { $speed } km/h
+settings-privacy = 🔒 Gespeicherter Ort: { $privacy }
+privacy-off = Adresse
+privacy-rounded = gerundet
+privacy-stop-only = nur Haltestelle
+language-auto = Telegram-App
+language-en = English
+language-de = Deutsch
+quiet-hours-off = aus
+
+## Stored data
+
+private-only = Dieser Befehl funktioniert nur im privaten Chat mit mir.
+no-data = Über dich sind keine Daten gespeichert.
+my-data = 📄 Alle über dich gespeicherten Daten.
+forgotten = ✅ Alle über dich gespeicherten Daten wurden gelöscht und deine Verfolgungen beendet. Mit /start kannst du von vorne beginnen.
+
+## Groups
+
+group-only = Dieser Befehl funktioniert nur in Gruppen.
+admin-only = ❌ Nur die Admins dieser Gruppe können ihre Haltestelle ändern.
+set-stop-usage = Schick mir den Namen der Haltestelle, z. B. <b>/setstop Alexanderplatz</b>.
+stop-not-found = 😟 Leider wurde keine Haltestelle mit diesem Namen gefunden.
+group-stop-saved = ✅ Die Haltestelle dieser Gruppe ist jetzt <b>{ $stop }</b>! Mit /board sieht jeder ihre Abfahrten.
+group-stop-cleared = ✅ Die Haltestelle dieser Gruppe wurde entfernt.
+no-group-stop = Diese Gruppe hat noch keine Haltestelle, ein Admin kann sie mit /setstop festlegen.
+select-group-transit = Wähle ein Verkehrsmittel, um es zu verfolgen, jeder bekommt seinen eigenen Countdown:
+
+## Operators
+
+admin-stats =
+    📊 <b>Statistik</b>
+    Nutzer: <b>{ $users }</b>, Gruppen: <b>{ $groups }</b>
+    Aktive Verfolgungen: <b>{ $trackings }</b>
+    Transport-API: { $transport-requests } Anfragen, { $transport-errors } % Fehler
+    LocationIQ-API: { $locationiq-requests } Anfragen, { $locationiq-errors } % Fehler
+admin-broadcast-usage = Schick mir die Nachricht für alle Nutzer, z. B. <b>/broadcast Neue Haltestellen sind verfügbar!</b>
+admin-broadcast-started = 📣 Sende die Nachricht an { $count } Nutzer...
+admin-broadcast-done = 📣 Die Nachricht wurde an { $sent } von { $count } Nutzern gesendet.
+admin-trackings = <b>{ $count }</b> aktive Verfolgungen, drück eine, um sie abzubrechen:
+admin-no-trackings = Es gibt keine aktiven Verfolgungen.
+button-cancel-tracking = ✖ Chat { $chat }, Nutzer { $user }
+admin-tracking-cancelled = ✅ Die Verfolgung wurde abgebrochen.
+admin-tracking-gone = Diese Verfolgung ist bereits beendet.
+cancelled-by-admin = 🚫 Deine Verfolgung wurde von den Betreibern des Bots abgebrochen. Mit /start kannst du von vorne beginnen.
+admin-user-usage = Schick mir die ID des Nutzers, z. B. <b>/user 12345</b>.
+admin-user = Gespeicherte Daten von <b>{ $id }</b>:
+admin-user-not-found = Für diesen Nutzer sind keine Daten gespeichert.
+admin-user-deleted = ✅ Die Daten des Nutzers wurden gelöscht.
+button-delete-user = 🗑 Löschen
@@ -0,0 +1,199 @@
+## General
+
+help =
+    I track your transit in your area and send you notifications with current location.
+    These commands are supported:
+
+    /help — Display help menu showing the commands list
+    /start — Start tracking your transit.
+    /cancel — Cancel the tracking.
+    /products — Select which transit products (bus, tram, ...) you want to see.
+    /language — Choose the language of the bot: de, en or auto.
+    /settings — Change your update intervals, buttons per row, language, quiet hours, walking speed and stored location.
+    /mydata — Get all data stored about you as a JSON file.
+    /forget — Delete all data stored about you and stop your trackings.
+
+    In groups:
+    /board — Show the departures of the group's stop, everyone can track a transit.
+    /setstop — Set the stop of the group, e.g. /setstop Alexanderplatz (admins only).
+    /clearstop — Remove the stop of the group (admins only).
+invalid-message = Unable to handle the message. Type /help to see the usage.
+cooldown = ⏳ Slow down a little, please! Try again in { $seconds } { $seconds ->
+    [one] second
+   *[other] seconds
+}.
+cancelled = 🚫 Cancelled! You can start over using /start.
+minutes-unit = { $minutes ->
+    [one] minute
+   *[other] minutes
+}
+
+## Address
+
+ask-city =
+    🔰 Let's start tracking a transit 🚌🚇!
+
+    What city do you live in?
+missing-city = ❌ Please, send me your city that you live in, so i can start tracking.
+ask-address =
+    Awesome so you live in <b>{ $city }</b> 🏙!
+
+    What is your locations <b>street address</b> so i can search for nearby transit stops?
+    You can also send me a <b>location</b> 📍 instead!
+missing-address = ❌ Please, send me your address, so i can start tracking.
+ask-new-address = Ok, send me the new <b>street address</b> or a <b>location</b> 📍.
+last-address =
+    I found your last used address:
+    <b>{ $addr }, { $city } 📍</b>
+
+    Now please select which transit station you want to track 👀.
+
+    Here are the nearby transit stations:
+last-area =
+    I found the area of your last used location in <b>{ $city } 📍</b>
+
+    Now please select which transit station you want to track 👀.
+
+    Here are the nearby transit stations:
+last-stop =
+    I found your last used stop: <b>{ $stop } 🚏</b>
+
+    Select it to see its departures, or change your address to see other stations:
+address-saved =
+    Thank you! So your address is:
+    <b>{ $addr }, { $city } 📍</b>
+
+    Now please select which transit station you want to track 👀.
+
+    Here are the nearby transit stations:
+button-change-address = << Change address
+button-station = { $name } · { $distance } · 🚶 { $minutes } min · { $products }
+button-more-stations = 🔍 More stations
+
+## Boards
+
+board-title-departures = 🚏 Departures from selected station (+ mins delay): <b>{ $stop }</b>
+board-title-arrivals = 🚏 Arrivals at selected station (+ mins delay): <b>{ $stop }</b>
+board-from = 🕒 From <b>{ $time }</b>
+board-departure = { $product } <b>{ $name }</b> to <b>{ $direction }</b>
+board-arrival = { $product } <b>{ $name }</b> from <b>{ $direction }</b>
+board-more = … and { $count } more, see them with <b>Later »</b>.
+cancelled-mark = cancelled
+select-transit = Select a transit, or send me a time like <b>17:30</b> to see later transits:
+invalid-time = ❌ Please, select a transit or send me a time like <b>17:30</b>.
+no-departures = 😟 Unfortunately, no transit departures were found from this station at this time.
+no-arrivals = 😟 Unfortunately, no transit arrivals were found at this station at this time.
+no-departures-start = 😟 Unfortunately, no transit departures were found from this station at this time. Please /start over!
+button-arrivals = ⇄ Arrivals
+button-departures = ⇄ Departures
+button-earlier = « Earlier
+button-later = Later »
+
+## Tracking
+
+ask-minutes =
+    Select how many <b>minutes</b> between each update, or at which minutes before the transit arrives I should notify you.
+    You can also send me an interval like <b>4</b> or minutes like <b>10, 5, 2</b>:
+invalid-plan = ❌ Please, send me an interval from 1 to { $interval } minutes like <b>4</b>, or up to { $count } times from 1 to { $minutes } minutes before the transit arrives like <b>10, 5, 2</b>.
+plan-info = I will notify you <b>{ $minutes }</b> minutes before the transit arrives!
+button-plan = 🔔 { $minutes } min before
+too-many-trackings = ❌ You already track { $max } transits, the most at the same time. Cancel one of them first with /cancel.
+timer-info = The timer is going to update every <b>{ $minutes }</b> { minutes-unit }!
+walk-info = It takes about <b>{ $minutes }</b> { minutes-unit } to walk to <b>{ $stop }</b> 🚶, I will tell you when to leave!
+leave-now = 🚶 Leave now! Your transit: <b>{ $transit }</b> 🚌 reaches <b>{ $stop }</b> in <b>{ $minutes }</b> { minutes-unit }!
+countdown = 🔔 Your transit: <b>{ $transit }</b> 🚌 arrives in <b>{ $minutes }</b> { minutes-unit } ⌛!
+leave-in = Leave in <b>{ $minutes }</b> { minutes-unit } 🚶
+arriving-now = 🔔 Your transit: <b>{ $transit }</b> 🚌 should arrive now!
+on-time = ⏱ Your transit: <b>{ $transit }</b> 🚌 is on time again!
+delayed = ⏱ Your transit: <b>{ $transit }</b> 🚌 is delayed by <b>{ $minutes }</b> { minutes-unit }!
+early = ⏱ Your transit: <b>{ $transit }</b> 🚌 is <b>{ $minutes }</b> { minutes-unit } early!
+position-info = Current position of the transit:
+departing = 🔔 Your transit: <b>{ $transit }</b> 🚌 is departuring from <b>{ $stop }</b>!
+arriving = 🔔 Your transit: <b>{ $transit }</b> 🚌 is arriving at <b>{ $stop }</b>!
+transit-cancelled = 🚫 Your transit: <b>{ $transit }</b> 🚌 has been cancelled! You can start over using /start.
+tracking-stopped = ⏹ The bot is restarting, so the tracking of your transit: <b>{ $transit }</b> 🚌 has ended. Start it again using /start.
+closer-stop = 🚶 From where you are now, <b>{ $stop }</b> is the nearest stop to catch your transit: <b>{ $transit }</b> 🚌, { $distance } or about <b>{ $minutes }</b> { minutes-unit } on foot. It is there at <b>{ $time }</b>.
+button-trip = 🛤 Trip
+button-cancel = << Cancel
+
+## Trips
+
+trip-title = 🛤 Trip of <b>{ $name }</b> to <b>{ $direction }</b>:
+no-trip = 😟 Unfortunately, no trip information is available for this transit.
+
+## Products
+
+products-select = Select which <b>transit products</b> you want to see, press <b>Done</b> when finished:
+products-saved = ✅ Saved your transit products! You can start tracking using /start.
+products-keep-one = Keep at least one product, otherwise no transits can be found.
+button-done = Done
+product-bus = Bus
+product-tram = Tram
+product-subway = Subway
+product-suburban = Suburban
+product-regional = Regional
+product-national = National
+
+## Language
+
+language-usage = Send <b>/language de</b> for German, <b>/language en</b> for English or <b>/language auto</b> to use the language of your Telegram app.
+language-saved = ✅ I will talk to you in English from now on!
+
+## Settings
+
+settings-title = ⚙️ Your <b>settings</b>, press a button to change it and <b>Done</b> when finished:
+settings-saved = ✅ Saved your settings! You can start tracking using /start.
+settings-intervals = ⏱ Update intervals: { $intervals } min
+settings-columns = ⌨️ Buttons per row: { $columns }
+settings-language = 🌐 Language: { $language }
+settings-quiet-hours = 🌙 Quiet hours: { $hours }
+settings-walking-speed = 🚶 Walking speed: { $speed } km/h
+settings-privacy = 🔒 Stored location: { $privacy }
+privacy-off = address
+privacy-rounded = rounded
+privacy-stop-only = stop only
+language-auto = Telegram app
+language-en = English
+language-de = Deutsch
+quiet-hours-off = off
+
+## Stored data
+
+private-only = This command only works in the private chat with me.
+no-data = No data is stored about you.
+my-data = 📄 All data stored about you.
+forgotten = ✅ Deleted all data stored about you and stopped your trackings. You can start over using /start.
+
+## Groups
+
+group-only = This command only works in group chats.
+admin-only = ❌ Only the admins of this group can change its stop.
+set-stop-usage = Send me the name of the stop, e.g. <b>/setstop Alexanderplatz</b>.
+stop-not-found = 😟 Unfortunately, no stop was found with this name.
+group-stop-saved = ✅ The stop of this group is now <b>{ $stop }</b>! Everyone can see its departures with /board.
+group-stop-cleared = ✅ Removed the stop of this group.
+no-group-stop = This group has no stop yet, an admin can set one with /setstop.
+select-group-transit = Select a transit to track it, everyone gets their own countdown:
+
+## Operators
+
+admin-stats =
+    📊 <b>Statistics</b>
+    Users: <b>{ $users }</b>, groups: <b>{ $groups }</b>
+    Active trackings: <b>{ $trackings }</b>
+    Transport API: { $transport-requests } requests, { $transport-errors } % errors
+    LocationIQ API: { $locationiq-requests } requests, { $locationiq-errors } % errors
+admin-broadcast-usage = Send me the message for all users, e.g. <b>/broadcast New stations are available!</b>
+admin-broadcast-started = 📣 Sending the message to { $count } users...
+admin-broadcast-done = 📣 Sent the message to { $sent } of { $count } users.
+admin-trackings = <b>{ $count }</b> active trackings, press one to cancel it:
+admin-no-trackings = There are no active trackings.
+button-cancel-tracking = ✖ Chat { $chat }, user { $user }
+admin-tracking-cancelled = ✅ Cancelled the tracking.
+admin-tracking-gone = This tracking has already ended.
+cancelled-by-admin = 🚫 Your tracking was cancelled by the operators of the bot. You can start over using /start.
+admin-user-usage = Send me the id of the user, e.g. <b>/user 12345</b>.
+admin-user = Stored data of <b>{ $id }</b>:
+admin-user-not-found = No data is stored for this user.
+admin-user-deleted = ✅ Deleted the data of the user.
+button-delete-user = 🗑 Delete
@@ -1,224 +1,496 @@
-use crate::structs::*;
+use crate::{config, structs::*};
 
 use std::{
-    error::Error
+    collections::HashMap,
+    error::Error,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
 };
 use reqwest::header::{ACCEPT, CONTENT_TYPE, USER_AGENT};
 
-//////////////////////////////////////////////////////////
-// API calls
-//////////////////////////////////////////////////////////
-pub async fn fetch_geocode(
-    addr: String,
-    city: String,
-) -> Result<(String, String), Box<dyn Error + Send + Sync>> {
-    let api_token = std::env::var("LOCATIONIQ_TOKEN").expect("LOCATIONIQ_TOKEN must be set.");
-
-    let url =
-        format!("https://eu1.locationiq.com/v1/search?key={}&q={}, {}&format=json", api_token, addr, city);
-
-    let client = reqwest::Client::new();
-    let resp = client
-        .get(url)
-        .header(CONTENT_TYPE, "application/json")
-        .header(ACCEPT, "application/json")
-        .header(USER_AGENT, "reqwest/0.11.13")
-        .send()
-        .await?
-        .text()
-        .await?;
-
-    if let Ok(json) = serde_json::from_str::<serde_json::Value>(&resp) {
-        let lat = &json[0]["lat"].as_str().unwrap();
-        let lon = &json[0]["lon"].as_str().unwrap();
-        return Ok((lat.to_string(), lon.to_string()));
-    }
-    Err("Fetching geocode!")?
+/// Number of map tiles above which the cache is cleared
+const MAX_CACHED_TILES: usize = 512;
+
+/// Images of map tiles by zoom, x and y.
+type TileCache = HashMap<(u32, u32, u32), Vec<u8>>;
+
+/// Client for the transport.rest and LocationIQ APIs, and the tile server of the maps.
+#[derive(Clone, Debug)]
+pub struct Api {
+    client: reqwest::Client,
+    /// Base URL of the transport.rest API, e.g. `https://v5.db.transport.rest`
+    pub transport_url: String,
+    /// Base URL of the LocationIQ API, e.g. `https://eu1.locationiq.com/v1`
+    pub locationiq_url: String,
+    pub locationiq_token: String,
+    /// URL of the map tiles, e.g. `https://tile.openstreetmap.org/{z}/{x}/{y}.png`,
+    /// maps are schematic without it
+    pub tile_url: Option<String>,
+    /// Requests and errors of both APIs since the start, shared by all clones
+    pub stats: Arc<ApiStats>,
+    /// Fetched map tiles, shared by all clones
+    tiles: Arc<Mutex<TileCache>>,
+}
+
+/// Requests made to each API, shown to the operators with /stats.
+#[derive(Debug, Default)]
+pub struct ApiStats {
+    pub transport: RequestStats,
+    pub locationiq: RequestStats,
 }
 
-pub async fn fetch_address(lat: String, lon: String) -> Result<String, Box<dyn Error + Send + Sync>> {
-    let api_token = std::env::var("LOCATIONIQ_TOKEN").expect("LOCATIONIQ_TOKEN must be set.");
-    let url =
-        format!("https://eu1.locationiq.com/v1/reverse?key={}&lat={}&lon={}&format=json", api_token, lat, lon);
-
-    let client = reqwest::Client::new();
-    let resp = client
-        .get(url)
-        .header(CONTENT_TYPE, "application/json")
-        .header(ACCEPT, "application/json")
-        .header(USER_AGENT, "reqwest/0.11.13")
-        .send()
-        .await?
-        .text()
-        .await?;
-
-    let json: serde_json::Value = serde_json::from_str(&resp).unwrap();
-
-    let street = json["address"]["road"].as_str().unwrap();
-    let mut res = format!("{}", street);
-    if let Some(house_number) = json["address"]["house_number"].as_str() {
-        res = format!("{} {}", res, house_number);
-    };
-    let _city = json["address"]["city"].as_str().unwrap();
-    
-    Ok(res)
+/// Number of requests to an API and how many of them failed.
+#[derive(Debug, Default)]
+pub struct RequestStats {
+    requests: AtomicU64,
+    errors: AtomicU64,
 }
 
-pub async fn get_nearby_stations(
-    lat: String,
-    lon: String,
-) -> Result<Vec<Station>, Box<dyn Error + Send + Sync>> {
-    let url = format!("https://v5.db.transport.rest/stops/nearby?latitude={}&longitude={}", lat, lon);
+impl RequestStats {
+    fn record(&self, success: bool) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        if !success {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn requests(&self) -> u64 {
+        self.requests.load(Ordering::Relaxed)
+    }
 
-    let mut stations_value: Vec<serde_json::Value> = vec![];
+    pub fn errors(&self) -> u64 {
+        self.errors.load(Ordering::Relaxed)
+    }
 
-    let response = reqwest::get(url).await?.text().await?;
-    let json: serde_json::Value = serde_json::from_str(&response).unwrap();
+    /// Percentage of failed requests, 0 if there were no requests yet.
+    pub fn error_rate(&self) -> f64 {
+        match self.requests() {
+            0 => 0.0,
+            n => self.errors() as f64 * 100.0 / n as f64,
+        }
+    }
+}
 
-    // Change using iterators
-    let mut i = 0;
-    while json[i].is_object() {
-        stations_value.push(json[i].to_owned());
-        i += 1;
+impl Api {
+    pub fn new(transport_url: String, locationiq_url: String, locationiq_token: String) -> Self {
+        Api {
+            client: reqwest::Client::new(),
+            transport_url: transport_url.trim_end_matches('/').to_string(),
+            locationiq_url: locationiq_url.trim_end_matches('/').to_string(),
+            locationiq_token,
+            tile_url: None,
+            stats: Arc::new(ApiStats::default()),
+            tiles: Arc::new(Mutex::new(HashMap::new())),
+        }
     }
 
-    let mut stations: Vec<Station> = vec![];
-    for s in stations_value.iter() {
-        let station_name = s.get("name").unwrap().as_str().unwrap().to_string();
-        let station_name = station_name.splitn(2, ",").next().unwrap().to_string();
-        let station = Station {
-            id: s.get("id").unwrap().as_str().unwrap().to_string(),
-            name: station_name,
-            location: Location {
-                lat: s
-                    .get("location")
-                    .unwrap()
-                    .get("latitude")
-                    .unwrap()
-                    .to_string(),
-                lon: s
-                    .get("location")
-                    .unwrap()
-                    .get("longitude")
-                    .unwrap()
-                    .to_string(),
-            },
-            distance: s.get("distance").unwrap().as_i64().unwrap(),
-        };
-        stations.push(station);
+    /// Creates the client with the base URLs and token of the configuration.
+    pub fn from_env() -> Self {
+        let locationiq_token =
+            std::env::var("LOCATIONIQ_TOKEN").expect("LOCATIONIQ_TOKEN must be set.");
+        Api {
+            tile_url: config::map_tile_url(),
+            ..Api::new(
+                config::transport_api_url(),
+                config::locationiq_api_url(),
+                locationiq_token,
+            )
+        }
     }
 
-    Ok(stations)
+    /// Sends a request and reads the response, counting it as failed if the request fails or
+    /// the API answers with an error status.
+    async fn fetch(
+        &self,
+        request: reqwest::RequestBuilder,
+        stats: &RequestStats,
+    ) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let response = match request.send().await {
+            Ok(r) => r,
+            Err(e) => {
+                stats.record(false);
+                return Err(e)?;
+            }
+        };
+        let success = response.status().is_success();
+        let text = response.text().await;
+        stats.record(success && text.is_ok());
+        Ok(text?)
+    }
 }
 
-pub async fn get_departures(
-    stop_id: String,
-) -> Result<Vec<TransitDeparture>, Box<dyn Error + Send + Sync>> {
-    let url = format!(
-        "https://v5.db.transport.rest/stops/{}/departures",
-        // "https://v5.db.transport.rest/stops/{}/departures?when=today 9am", // for debugging
-        stop_id
-    );
-
-    let client = reqwest::Client::new();
-    let resp = client
-        .get(url)
-        .header(CONTENT_TYPE, "application/json")
-        .header(ACCEPT, "application/json")
-        .header(USER_AGENT, "reqwest/0.11.13")
-        .send()
-        .await?
-        .text()
-        .await?;
-
-    let mut departures_values: Vec<serde_json::Value> = vec![];
-
-    let json: serde_json::Value = serde_json::from_str(&resp).unwrap();
-
-    // Change using iterators
-    let mut i = 0;
-    while json[i].is_object() {
-        departures_values.push(json[i].to_owned());
-        i += 1;
+//////////////////////////////////////////////////////////
+// API calls
+//////////////////////////////////////////////////////////
+impl Api {
+    pub async fn fetch_geocode(
+        &self,
+        addr: String,
+        city: String,
+    ) -> Result<(String, String), Box<dyn Error + Send + Sync>> {
+        let url = format!(
+            "{}/search?key={}&q={}, {}&format=json",
+            self.locationiq_url, self.locationiq_token, addr, city
+        );
+
+        let request = self
+            .client
+            .get(url)
+            .header(CONTENT_TYPE, "application/json")
+            .header(ACCEPT, "application/json")
+            .header(USER_AGENT, "reqwest/0.11.13");
+        let resp = self.fetch(request, &self.stats.locationiq).await?;
+
+        if let Ok(json) = serde_json::from_str::<serde_json::Value>(&resp) {
+            if let (Some(lat), Some(lon)) = (json[0]["lat"].as_str(), json[0]["lon"].as_str()) {
+                return Ok((lat.to_string(), lon.to_string()));
+            }
+        }
+        Err("Fetching geocode!")?
     }
 
-    let mut transits: Vec<TransitDeparture> = vec![];
-    for d in departures_values.iter() {
-        let transit_name = d
-            .get("line")
-            .unwrap()
-            .get("name")
-            .unwrap()
-            .as_str()
-            .unwrap()
-            .to_string();
-        let transit_planned = d.get("plannedWhen").unwrap().as_str().unwrap().to_string();
-        let transit_delay = match d.get("delay") {
-            Some(i) => i.as_i64(),
-            None => None,
+    pub async fn fetch_address(
+        &self,
+        lat: String,
+        lon: String,
+    ) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let url = format!(
+            "{}/reverse?key={}&lat={}&lon={}&format=json",
+            self.locationiq_url, self.locationiq_token, lat, lon
+        );
+
+        let request = self
+            .client
+            .get(url)
+            .header(CONTENT_TYPE, "application/json")
+            .header(ACCEPT, "application/json")
+            .header(USER_AGENT, "reqwest/0.11.13");
+        let resp = self.fetch(request, &self.stats.locationiq).await?;
+
+        let json: serde_json::Value = serde_json::from_str(&resp).unwrap();
+
+        let street = json["address"]["road"].as_str().unwrap();
+        let mut res = street.to_string();
+        if let Some(house_number) = json["address"]["house_number"].as_str() {
+            res = format!("{} {}", res, house_number);
         };
-        let transit_direction = d.get("direction").unwrap().as_str().unwrap().to_string();
+        let _city = json["address"]["city"].as_str().unwrap();
 
-        let transit_destination = Station {
-            id: d
-                .get("destination")
-                .unwrap()
-                .get("id")
-                .unwrap()
-                .as_str()
-                .unwrap()
-                .to_string(),
-            name: d
-                .get("destination")
+        Ok(res)
+    }
+
+    /// Fetches the stations near a location that serve at least one of the given products.
+    pub async fn get_nearby_stations(
+        &self,
+        lat: String,
+        lon: String,
+        products: &Products,
+        query: &NearbyQuery,
+    ) -> Result<Vec<Station>, Box<dyn Error + Send + Sync>> {
+        let url = format!(
+            "{}/stops/nearby?latitude={}&longitude={}{}",
+            self.transport_url,
+            lat,
+            lon,
+            query.query()
+        );
+
+        let mut stations_value: Vec<serde_json::Value> = vec![];
+
+        let response = self.fetch(self.client.get(url), &self.stats.transport).await?;
+        let json: serde_json::Value = serde_json::from_str(&response)?;
+
+        // Change using iterators
+        let mut i = 0;
+        while json[i].is_object() {
+            stations_value.push(json[i].to_owned());
+            i += 1;
+        }
+
+        let mut stations: Vec<Station> = vec![];
+        for s in stations_value.iter() {
+            // Use the full name, cutting it at the first comma would shorten `Berlin, Alexanderplatz/Dircksenstr.` to `Berlin`
+            let station_name = s.get("name").unwrap().as_str().unwrap().to_string();
+            let station = Station {
+                id: s.get("id").unwrap().as_str().unwrap().to_string(),
+                name: station_name,
+                location: Location {
+                    lat: s
+                        .get("location")
+                        .unwrap()
+                        .get("latitude")
+                        .unwrap()
+                        .to_string(),
+                    lon: s
+                        .get("location")
+                        .unwrap()
+                        .get("longitude")
+                        .unwrap()
+                        .to_string(),
+                },
+                distance: s.get("distance").unwrap().as_i64().unwrap(),
+                products: s.get("products").map(Products::from_json).unwrap_or_default(),
+            };
+
+            // The nearby endpoint has no product parameters, so filter by the served products
+            if s.get("products").is_some_and(|p| products.serves(p)) {
+                stations.push(station);
+            }
+        }
+
+        Ok(stations)
+    }
+
+    /// Searches the stations matching a name like `Alexanderplatz`, the best match first.
+    pub async fn search_stations(
+        &self,
+        query: &str,
+    ) -> Result<Vec<Station>, Box<dyn Error + Send + Sync>> {
+        let mut url = reqwest::Url::parse(&format!("{}/locations", self.transport_url))?;
+        url.query_pairs_mut()
+            .append_pair("query", query)
+            .append_pair("results", "5")
+            .append_pair("addresses", "false")
+            .append_pair("poi", "false");
+
+        let response = self.fetch(self.client.get(url), &self.stats.transport).await?;
+        let json: Vec<serde_json::Value> = serde_json::from_str(&response)?;
+
+        Ok(json
+            .iter()
+            .filter(|s| matches!(s["type"].as_str(), Some("stop") | Some("station")))
+            .filter_map(parse_station)
+            .collect())
+    }
+
+    /// Fetches the departures or arrivals board of a stop in the given time window,
+    /// only including the given products.
+    pub async fn get_board(
+        &self,
+        stop_id: String,
+        kind: BoardKind,
+        products: &Products,
+        query: &BoardQuery,
+    ) -> Result<Vec<TransitDeparture>, Box<dyn Error + Send + Sync>> {
+        let url = format!(
+            "{}/stops/{}/{}?{}{}",
+            self.transport_url,
+            stop_id,
+            kind.endpoint(),
+            products.query(),
+            query.query()
+        );
+
+        let request = self
+            .client
+            .get(url)
+            .header(CONTENT_TYPE, "application/json")
+            .header(ACCEPT, "application/json")
+            .header(USER_AGENT, "reqwest/0.11.13");
+        let resp = self.fetch(request, &self.stats.transport).await?;
+
+        let mut departures_values: Vec<serde_json::Value> = vec![];
+
+        let json: serde_json::Value = serde_json::from_str(&resp)?;
+
+        // Change using iterators
+        let mut i = 0;
+        while json[i].is_object() {
+            departures_values.push(json[i].to_owned());
+            i += 1;
+        }
+
+        let mut transits: Vec<TransitDeparture> = vec![];
+        for d in departures_values.iter() {
+            let transit_name = d
+                .get("line")
                 .unwrap()
                 .get("name")
                 .unwrap()
                 .as_str()
                 .unwrap()
-                .to_string(),
-            location: Location {
-                lat: d
-                    .get("destination")
-                    .unwrap()
-                    .get("location")
-                    .unwrap()
-                    .get("latitude")
-                    .unwrap()
-                    .as_f64()
-                    .unwrap()
-                    .to_string(),
-                lon: d
-                    .get("destination")
-                    .unwrap()
-                    .get("location")
-                    .unwrap()
-                    .get("longitude")
-                    .unwrap()
-                    .as_f64()
-                    .unwrap()
-                    .to_string(),
-            },
-            distance: -1, // -1 means undefined
-        };
+                .to_string();
+            let transit_product = d
+                .get("line")
+                .unwrap()
+                .get("product")
+                .and_then(|p| p.as_str())
+                .unwrap_or_default()
+                .to_string();
+            let transit_planned = d.get("plannedWhen").unwrap().as_str().unwrap().to_string();
+            let transit_when = d.get("when").and_then(|w| w.as_str()).map(|w| w.to_string());
+            let transit_delay = match d.get("delay") {
+                Some(i) => i.as_i64(),
+                None => None,
+            };
 
-        let transit_curr_position = match d.get("currentTripPosition") {
-            Some(v) => Some(Location {
-                lat: v.get("latitude").unwrap().as_f64().unwrap().to_string(),
-                lon: v.get("longitude").unwrap().as_f64().unwrap().to_string(),
-            }),
-            None => None,
-        };
+            // Arrivals come with the origin of the transit instead of its direction
+            let transit_direction = match kind {
+                BoardKind::Departures => d.get("direction"),
+                BoardKind::Arrivals => d.get("provenance"),
+            }
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+            .to_string();
+
+            let transit_curr_position = d
+                .get("currentTripPosition")
+                .filter(|v| v.is_object())
+                .map(|v| Location {
+                    lat: v.get("latitude").unwrap().as_f64().unwrap().to_string(),
+                    lon: v.get("longitude").unwrap().as_f64().unwrap().to_string(),
+                });
 
-        let transit = TransitDeparture {
-            stop_id: stop_id.clone(),
-            planned: transit_planned,
-            delay: transit_delay,
-            direction: transit_direction,
-            name: transit_name,
-            destination: transit_destination,
-            curr_position: transit_curr_position,
+            let transit_trip_id = d
+                .get("tripId")
+                .and_then(|t| t.as_str())
+                .unwrap_or_default()
+                .to_string();
+
+            let transit = TransitDeparture {
+                stop_id: stop_id.clone(),
+                trip_id: transit_trip_id,
+                kind,
+                planned: transit_planned,
+                when: transit_when,
+                delay: transit_delay,
+                direction: transit_direction,
+                name: transit_name,
+                product: transit_product,
+                origin: d.get("origin").and_then(parse_station),
+                destination: d.get("destination").and_then(parse_station),
+                curr_position: transit_curr_position,
+                cancelled: d.get("cancelled").and_then(|c| c.as_bool()).unwrap_or(false),
+            };
+            transits.push(transit);
+        }
+        Ok(transits)
+    }
+
+    /// Fetches a trip with all of its stopovers.
+    pub async fn get_trip(
+        &self,
+        trip_id: String,
+        line_name: String,
+    ) -> Result<Trip, Box<dyn Error + Send + Sync>> {
+        // Trip ids can contain characters like `#` that have to be encoded
+        let mut url = reqwest::Url::parse(&format!("{}/trips/", self.transport_url))?;
+        url.path_segments_mut()
+            .map_err(|_| "Invalid trips url!")?
+            .pop_if_empty()
+            .push(&trip_id);
+        url.query_pairs_mut()
+            .append_pair("lineName", &line_name)
+            .append_pair("stopovers", "true");
+
+        let request = self
+            .client
+            .get(url)
+            .header(CONTENT_TYPE, "application/json")
+            .header(ACCEPT, "application/json")
+            .header(USER_AGENT, "reqwest/0.11.13");
+        let resp = self.fetch(request, &self.stats.transport).await?;
+
+        let json: serde_json::Value = serde_json::from_str(&resp)?;
+        let json = json.get("trip").unwrap_or(&json);
+        if !json.is_object() {
+            Err("Fetching trip!")?
+        }
+
+        let opt_string = |v: &serde_json::Value, key: &str| {
+            v.get(key).and_then(|x| x.as_str()).map(|x| x.to_string())
         };
-        transits.push(transit);
+
+        let stopovers = json
+            .get("stopovers")
+            .and_then(|s| s.as_array())
+            .map(|s| {
+                s.iter()
+                    .filter_map(|s| {
+                        Some(Stopover {
+                            stop: parse_station(s.get("stop")?)?,
+                            planned_arrival: opt_string(s, "plannedArrival"),
+                            arrival: opt_string(s, "arrival"),
+                            arrival_delay: s.get("arrivalDelay").and_then(|x| x.as_i64()),
+                            planned_departure: opt_string(s, "plannedDeparture"),
+                            departure: opt_string(s, "departure"),
+                            departure_delay: s.get("departureDelay").and_then(|x| x.as_i64()),
+                            cancelled: s.get("cancelled").and_then(|x| x.as_bool()).unwrap_or(false),
+                        })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let curr_position = json
+            .get("currentLocation")
+            .filter(|v| v.is_object())
+            .and_then(|v| {
+                Some(Location {
+                    lat: v.get("latitude")?.as_f64()?.to_string(),
+                    lon: v.get("longitude")?.as_f64()?.to_string(),
+                })
+            });
+
+        Ok(Trip {
+            id: opt_string(json, "id").unwrap_or(trip_id),
+            name: json
+                .get("line")
+                .and_then(|l| opt_string(l, "name"))
+                .unwrap_or(line_name),
+            direction: opt_string(json, "direction").unwrap_or_default(),
+            curr_position,
+            stopovers,
+        })
     }
-    Ok(transits)
-}
\ No newline at end of file
+}
+
+impl Api {
+    /// Fetches a map tile as image, tiles are cached since they hardly change.
+    pub async fn get_tile(&self, zoom: u32, x: u32, y: u32) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+        if let Some(tile) = self.tiles.lock().unwrap().get(&(zoom, x, y)) {
+            return Ok(tile.clone());
+        }
+        let url = self
+            .tile_url
+            .as_ref()
+            .ok_or("No tile server!")?
+            .replace("{z}", &zoom.to_string())
+            .replace("{x}", &x.to_string())
+            .replace("{y}", &y.to_string());
+
+        // Tile servers like the one of OpenStreetMap require the name of the app
+        let response = self
+            .client
+            .get(url)
+            .header(USER_AGENT, concat!("wimt-bot/", env!("CARGO_PKG_VERSION")))
+            .send()
+            .await?
+            .error_for_status()?;
+        let tile = response.bytes().await?.to_vec();
+
+        let mut tiles = self.tiles.lock().unwrap();
+        if tiles.len() >= MAX_CACHED_TILES {
+            tiles.clear();
+        }
+        tiles.insert((zoom, x, y), tile.clone());
+        Ok(tile)
+    }
+}
+
+/// Parses a stop of a departure or arrival, `None` if the stop is missing.
+fn parse_station(s: &serde_json::Value) -> Option<Station> {
+    let location = s.get("location")?;
+    Some(Station {
+        id: s.get("id")?.as_str()?.to_string(),
+        name: s.get("name")?.as_str()?.to_string(),
+        location: Location {
+            lat: location.get("latitude")?.as_f64()?.to_string(),
+            lon: location.get("longitude")?.as_f64()?.to_string(),
+        },
+        distance: -1, // -1 means undefined
+        products: s.get("products").map(Products::from_json).unwrap_or_default(),
+    })
+}
@@ -1 +1,139 @@
-pub const USER_DATA_PATH: &str = "./userdata.json";
\ No newline at end of file
+//////////////////////////////////////////////////////////
+// Configuration
+//////////////////////////////////////////////////////////
+pub const USER_DATA_PATH: &str = "./userdata.json";
+
+/// Walking speed in km/h used when no `WALKING_SPEED` is set.
+pub const DEFAULT_WALKING_SPEED: f64 = 4.5;
+
+/// Walking speed in km/h used to estimate the walking time from the user's address to a stop.
+pub fn walking_speed() -> f64 {
+    std::env::var("WALKING_SPEED")
+        .ok()
+        .and_then(|s| s.parse::<f64>().ok())
+        .filter(|s| *s > 0.0)
+        .unwrap_or(DEFAULT_WALKING_SPEED)
+}
+
+/// Length in minutes of the board time windows used when no `BOARD_DURATION` is set.
+pub const DEFAULT_BOARD_DURATION: i64 = 30;
+
+/// Length in minutes of the time window of departure and arrival boards.
+pub fn board_duration() -> i64 {
+    parse_board_duration(std::env::var("BOARD_DURATION").ok().as_deref())
+}
+
+/// Parses a `BOARD_DURATION`, the default if it is not a positive number.
+pub fn parse_board_duration(value: Option<&str>) -> i64 {
+    value
+        .and_then(|s| s.parse::<i64>().ok())
+        .filter(|s| *s > 0)
+        .unwrap_or(DEFAULT_BOARD_DURATION)
+}
+
+/// Maximum number of transits shown on a board, the API default if not set.
+pub fn board_results() -> Option<usize> {
+    parse_board_results(std::env::var("BOARD_RESULTS").ok().as_deref())
+}
+
+/// Parses a `BOARD_RESULTS`, `None` if it is not a positive number.
+pub fn parse_board_results(value: Option<&str>) -> Option<usize> {
+    value.and_then(|s| s.parse::<usize>().ok()).filter(|r| *r > 0)
+}
+
+/// Search for the stations near the address of a user: within `NEARBY_RADIUS` meters, 1000 by
+/// default, and at most `NEARBY_RESULTS` stations, 8 by default.
+pub fn nearby_query() -> crate::structs::NearbyQuery {
+    let default = crate::structs::NearbyQuery::default();
+    let radius = std::env::var("NEARBY_RADIUS")
+        .ok()
+        .and_then(|s| s.parse::<u32>().ok())
+        .filter(|r| *r > 0)
+        .unwrap_or(default.radius);
+    let results = std::env::var("NEARBY_RESULTS")
+        .ok()
+        .and_then(|s| s.parse::<usize>().ok())
+        .filter(|r| *r > 0)
+        .unwrap_or(default.results)
+        .min(crate::structs::NearbyQuery::MAX_RESULTS);
+    crate::structs::NearbyQuery { radius, results }
+}
+
+/// Base URL of the transport.rest API, the Deutsche Bahn one by default.
+pub fn transport_api_url() -> String {
+    std::env::var("TRANSPORT_API_URL").unwrap_or_else(|_| "https://v5.db.transport.rest".to_string())
+}
+
+/// Base URL of the LocationIQ API.
+pub fn locationiq_api_url() -> String {
+    std::env::var("LOCATIONIQ_API_URL").unwrap_or_else(|_| "https://eu1.locationiq.com/v1".to_string())
+}
+
+/// URL of the tiles of the maps of the vehicles with `{z}`, `{x}` and `{y}` placeholders, e.g.
+/// `https://tile.openstreetmap.org/{z}/{x}/{y}.png`. The maps are schematic if not set.
+pub fn map_tile_url() -> Option<String> {
+    std::env::var("MAP_TILE_URL").ok().filter(|s| !s.is_empty())
+}
+
+/// Attribution of the map tiles shown below the maps.
+pub fn map_attribution() -> String {
+    std::env::var("MAP_ATTRIBUTION").unwrap_or_else(|_| "© OpenStreetMap contributors".to_string())
+}
+
+/// Timezone of the stations of the transport API, used to show all times.
+/// Europe/Berlin by default, set `TRANSPORT_TIMEZONE` together with `TRANSPORT_API_URL`
+/// for providers in other timezones.
+pub fn timezone() -> chrono_tz::Tz {
+    std::env::var("TRANSPORT_TIMEZONE")
+        .ok()
+        .and_then(|s| s.parse::<chrono_tz::Tz>().ok())
+        .unwrap_or(chrono_tz::Europe::Berlin)
+}
+
+/// User ids of the operators of the bot, who can use the commands like /stats.
+/// Set `ADMIN_IDS` as a comma separated list, e.g. `12345,67890`.
+pub fn admin_ids() -> std::collections::HashSet<teloxide::types::UserId> {
+    std::env::var("ADMIN_IDS")
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|s| s.trim().parse::<u64>().ok())
+        .map(teloxide::types::UserId)
+        .collect()
+}
+
+/// Days after which the address and location of inactive users are deleted, set with
+/// `RETENTION_DAYS`. They are kept forever if not set.
+pub fn retention() -> Option<chrono::Duration> {
+    std::env::var("RETENTION_DAYS")
+        .ok()
+        .and_then(|s| s.parse::<i64>().ok())
+        .filter(|d| *d > 0)
+        .map(chrono::Duration::days)
+}
+
+/// Updates per minute each user can send before they have to wait, 30 by default.
+pub fn user_rate_limit() -> u32 {
+    std::env::var("USER_RATE_LIMIT")
+        .ok()
+        .and_then(|s| s.parse::<u32>().ok())
+        .filter(|s| *s > 0)
+        .unwrap_or(30)
+}
+
+/// Updates per minute of all users together, 600 by default.
+pub fn global_rate_limit() -> u32 {
+    std::env::var("GLOBAL_RATE_LIMIT")
+        .ok()
+        .and_then(|s| s.parse::<u32>().ok())
+        .filter(|s| *s > 0)
+        .unwrap_or(600)
+}
+
+/// Maximum number of transits a user can track at the same time, in all chats together.
+pub fn max_trackings() -> usize {
+    std::env::var("MAX_TRACKINGS")
+        .ok()
+        .and_then(|s| s.parse::<usize>().ok())
+        .filter(|s| *s > 0)
+        .unwrap_or(3)
+}
@@ -0,0 +1,239 @@
+use chrono::{DateTime, Duration, Utc};
+use chrono_tz::Tz;
+use teloxide::utils::html::escape;
+
+use crate::{config, i18n::Lang, structs::*};
+
+//////////////////////////////////////////////////////////
+// Formatting
+//////////////////////////////////////////////////////////
+/// Maximum length of a Telegram message, in UTF-16 code units like Telegram counts them
+pub const MESSAGE_LIMIT: usize = 4096;
+/// Pages of a board sent at most, the remaining transits are left to the next board
+const MAX_BOARD_PAGES: usize = 3;
+/// Characters of a name from the APIs above which it is cut
+const MAX_NAME_CHARS: usize = 48;
+/// Delay in minutes from which a transit is marked as late instead of slightly delayed
+const LATE_MINUTES: i64 = 5;
+
+/// Name from the APIs for HTML messages, cut if it is too long and escaped.
+pub fn escape_name(name: &str) -> String {
+    if name.chars().count() <= MAX_NAME_CHARS {
+        return escape(name);
+    }
+    let cut: String = name.chars().take(MAX_NAME_CHARS - 1).collect();
+    escape(&format!("{}…", cut.trim_end()))
+}
+
+/// Emoji of a product as named by the API.
+pub fn product_emoji(product: &str) -> &'static str {
+    match product {
+        "bus" => "🚌",
+        "tram" => "🚋",
+        "subway" => "🚇",
+        "suburban" => "🚈",
+        "regional" | "regionalExp" => "🚆",
+        "national" | "nationalExpress" => "🚄",
+        "ferry" => "⛴",
+        "taxi" => "🚕",
+        _ => "🚍",
+    }
+}
+
+/// Distance in meters as shown to the users, e.g. `120 m` or `1.2 km`.
+pub fn format_distance(meters: i64) -> String {
+    match meters {
+        m if m < 1000 => format!("{} m", m),
+        m => format!("{:.1} km", m as f64 / 1000.0),
+    }
+}
+
+/// Started minutes of a walk, so that nobody is told to walk 0 minutes.
+pub fn walking_minutes(time: Duration) -> i64 {
+    (time.num_seconds() + 59) / 60
+}
+
+/// Label of a station found near the user with its distance, the time to walk there and the
+/// emoji of the products it serves. Stations with an unknown distance only have their name.
+pub fn format_station(station: &Station, walking_speed: f64, lang: Lang) -> String {
+    let walking_time = match station.walking_time(walking_speed) {
+        Some(time) => time,
+        None => return station.name.clone(),
+    };
+    let products: String = Products::NAMES
+        .iter()
+        .filter(|&&name| station.products.get(name))
+        .map(|&name| product_emoji(name))
+        .collect();
+    let args = [
+        ("name", station.name.as_str().into()),
+        ("distance", format_distance(station.distance).into()),
+        ("minutes", walking_minutes(walking_time).into()),
+        ("products", products.into()),
+    ];
+    lang.tr_args("button-station", &args).trim_end().to_string()
+}
+
+/// Length of a text as Telegram counts it.
+fn message_len(text: &str) -> usize {
+    text.encode_utf16().count()
+}
+
+/// Time of a date of the API in the timezone of the stations, e.g. `16:04`.
+pub fn format_time(date: &str, tz: Tz) -> String {
+    DateTime::parse_from_rfc3339(date)
+        .map(|t| t.with_timezone(&tz).format("%H:%M").to_string())
+        .unwrap_or_default()
+}
+
+/// Formats the departures or arrivals of a board as pages that each fit into a message, every
+/// page starts with the title. If there are more transits than fit on `MAX_BOARD_PAGES` pages,
+/// the last one tells how many are left.
+pub fn format_board(
+    stop: &str,
+    board: BoardKind,
+    query: &BoardQuery,
+    departures: &[TransitDeparture],
+    lang: Lang,
+) -> Vec<String> {
+    let tz = config::timezone();
+    let title = match board {
+        BoardKind::Departures => "board-title-departures",
+        BoardKind::Arrivals => "board-title-arrivals",
+    };
+    let mut title = lang.tr_args(title, &[("stop", escape_name(stop).into())]);
+    if let Some(when) = query.when {
+        let when = when.with_timezone(&tz).format("%d.%m. %H:%M").to_string();
+        title = format!("{}\n{}", title, lang.tr_args("board-from", &[("time", when.into())]));
+    }
+
+    let lines: Vec<String> = departures
+        .iter()
+        .map(|dep| format_departure(dep, board, tz, lang))
+        .collect();
+    paginate(&format!("{}\n", title), &lines, |count| {
+        lang.tr_args("board-more", &[("count", count.into())])
+    })
+}
+
+/// Line of a board: whether the transit is on time, the planned time, the real-time and delay
+/// if they differ, and the transit with the emoji of its product.
+fn format_departure(dep: &TransitDeparture, board: BoardKind, tz: Tz, lang: Lang) -> String {
+    let time = format_time(&dep.planned, tz);
+    let delay = dep.delay.map(|d| d / 60);
+    let status = match delay {
+        _ if dep.cancelled => "❌",
+        None => "⚪",
+        Some(d) if d <= 0 => "🟢",
+        Some(d) if d < LATE_MINUTES => "🟡",
+        Some(_) => "🔴",
+    };
+
+    // Show the real-time departure or arrival next to the planned one
+    let actual = match &dep.when {
+        _ if dep.cancelled => format!(" ({})", lang.tr("cancelled-mark")),
+        Some(when) if format_time(when, tz) != time => {
+            format!(" → <b>{}</b>", format_time(when, tz))
+        }
+        _ => String::new(),
+    };
+    let delay = match delay {
+        Some(d) if d > 0 && !dep.cancelled => format!(" (+{})", d),
+        Some(d) if d < 0 && !dep.cancelled => format!(" ({})", d),
+        _ => String::new(),
+    };
+
+    let line = match board {
+        BoardKind::Departures => "board-departure",
+        BoardKind::Arrivals => "board-arrival",
+    };
+    let args = [
+        ("product", product_emoji(&dep.product).into()),
+        ("name", escape_name(&dep.name).into()),
+        ("direction", escape_name(&dep.direction).into()),
+    ];
+    // The times are monospaced so that the transits line up
+    format!("{} <code>{}</code>{}{} {}", status, time, actual, delay, lang.tr_args(line, &args))
+}
+
+/// Splits the lines into pages below the message limit, each starting with the title. Lines are
+/// never split, those beyond `MAX_BOARD_PAGES` are replaced by the note `more` with their number.
+fn paginate(title: &str, lines: &[String], more: impl Fn(usize) -> String) -> Vec<String> {
+    let mut pages = vec![];
+    let mut page = title.to_string();
+    for (i, line) in lines.iter().enumerate() {
+        // The last page keeps room for the note as long as lines are left after this one
+        let last_page = pages.len() + 1 >= MAX_BOARD_PAGES;
+        let reserved = match lines.len() - i - 1 {
+            left if last_page && left > 0 => message_len(&more(left)) + 1,
+            _ => 0,
+        };
+        if message_len(&page) + message_len(line) + 1 + reserved > MESSAGE_LIMIT {
+            if last_page {
+                page = format!("{}\n{}", page, more(lines.len() - i));
+                break;
+            }
+            pages.push(std::mem::replace(&mut page, title.to_string()));
+        }
+        page = format!("{}\n{}", page, line);
+    }
+    pages.push(page);
+    pages
+}
+
+/// Formats all stopovers of a trip, highlighting the stop of the user and
+/// marking where the transit currently is.
+pub fn format_trip(trip: &Trip, stop_id: &str, now: DateTime<Utc>, lang: Lang) -> String {
+    let tz = config::timezone();
+    let passed = |s: &Stopover| {
+        s.when()
+            .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+            .map(|t| t <= now)
+            .unwrap_or(false)
+    };
+
+    // The transit is between the last passed and the next stopover
+    let position = trip.stopovers.iter().rposition(passed);
+
+    let mut info = format!(
+        "{}\n",
+        lang.tr_args(
+            "trip-title",
+            &[
+                ("name", escape_name(&trip.name).into()),
+                ("direction", escape_name(&trip.direction).into())
+            ]
+        )
+    );
+    for (i, stopover) in trip.stopovers.iter().enumerate() {
+        let mark = match position {
+            Some(p) if i <= p => "▪️",
+            _ => "▫️",
+        };
+        let planned = stopover.planned().map(|t| format_time(t, tz)).unwrap_or_default();
+        let actual = match stopover.when().map(|t| format_time(t, tz)) {
+            Some(when) if when != planned => format!(" → {}", when),
+            _ => String::new(),
+        };
+        let delay = match stopover.delay().map(|d| d / 60) {
+            Some(d) if d > 0 => format!(" (+{})", d),
+            Some(d) if d < 0 => format!(" ({})", d),
+            _ => String::new(),
+        };
+        let stop_name = escape_name(&stopover.stop.name);
+        let name = if stopover.cancelled {
+            format!("<s>{}</s> ({})", stop_name, lang.tr("cancelled-mark"))
+        } else if stopover.stop.id == stop_id {
+            format!("<b>📍 {}</b>", stop_name)
+        } else {
+            stop_name
+        };
+
+        info = format!("{}\n{} {}{}{} {}", info, mark, planned, actual, delay, name);
+
+        if position == Some(i) && i + 1 < trip.stopovers.len() {
+            info = format!("{}\n      🚌", info);
+        }
+    }
+    info
+}
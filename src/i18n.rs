@@ -0,0 +1,97 @@
+use std::sync::OnceLock;
+
+use fluent_bundle::{concurrent::FluentBundle, FluentArgs, FluentResource, FluentValue};
+use serde::{Deserialize, Serialize};
+use unic_langid::LanguageIdentifier;
+
+//////////////////////////////////////////////////////////
+// Localization
+//////////////////////////////////////////////////////////
+/// Message catalogs in the Fluent format.
+pub const EN_CATALOG: &str = include_str!("../locales/en.ftl");
+pub const DE_CATALOG: &str = include_str!("../locales/de.ftl");
+
+type Bundle = FluentBundle<FluentResource>;
+
+/// Language of the messages sent to a user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Lang {
+    #[default]
+    En,
+    De,
+}
+
+impl Lang {
+    /// Language of a Telegram `language_code` like `de` or `de-AT`, English if not supported.
+    pub fn from_code(code: Option<&str>) -> Lang {
+        let code = code.unwrap_or_default().to_lowercase();
+        match code.split(['-', '_']).next() {
+            Some("de") => Lang::De,
+            _ => Lang::En,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Lang::En => "en",
+            Lang::De => "de",
+        }
+    }
+
+    /// Message of the catalog without arguments.
+    pub fn tr(&self, id: &str) -> String {
+        self.tr_args(id, &[])
+    }
+
+    /// Message of the catalog with the given arguments, the id itself if the message is missing.
+    pub fn tr_args(&self, id: &str, args: &[(&str, FluentValue)]) -> String {
+        let bundle = bundle(*self);
+        let pattern = match bundle.get_message(id).and_then(|m| m.value()) {
+            Some(p) => p,
+            None => {
+                log::warn!("Missing message {} in the {} catalog", id, self.code());
+                return id.to_string();
+            }
+        };
+
+        let mut fluent_args = FluentArgs::new();
+        for (name, value) in args {
+            fluent_args.set(*name, value.clone());
+        }
+        let mut errors = vec![];
+        let message = bundle.format_pattern(pattern, Some(&fluent_args), &mut errors);
+        for e in errors {
+            log::warn!("Unable to format message {}: {}", id, e);
+        }
+        message.to_string()
+    }
+}
+
+/// Bundle of the language, parsed once.
+fn bundle(lang: Lang) -> &'static Bundle {
+    static BUNDLES: OnceLock<[Bundle; 2]> = OnceLock::new();
+    let bundles = BUNDLES.get_or_init(|| {
+        [
+            new_bundle(Lang::En, EN_CATALOG),
+            new_bundle(Lang::De, DE_CATALOG),
+        ]
+    });
+    match lang {
+        Lang::En => &bundles[0],
+        Lang::De => &bundles[1],
+    }
+}
+
+fn new_bundle(lang: Lang, catalog: &str) -> Bundle {
+    let langid: LanguageIdentifier = lang.code().parse().unwrap();
+    let mut bundle = FluentBundle::new_concurrent(vec![langid]);
+    // Telegram shows the Unicode isolation marks around arguments
+    bundle.set_use_isolating(false);
+
+    let resource = FluentResource::try_new(catalog.to_string()).expect("Invalid message catalog!");
+    bundle
+        .add_resource(resource)
+        .expect("Duplicate messages in the catalog!");
+    bundle
+}
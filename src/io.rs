@@ -1,54 +1,162 @@
-use std::fs;
+use std::{fs, path::PathBuf};
 
 use crate::*;
 
 //////////////////////////////////////////////////////////
 // I/O
 //////////////////////////////////////////////////////////
-pub fn store_user_data(user: UserData) -> Result<(), Box<dyn Error + Sync + Send>> {
-    let user_data_path = std::env::var("USER_DATA_PATH").expect("USER_DATA_PATH must be set.");
-    let path = Path::new(&user_data_path);
+/// JSON file storing the data of all users.
+#[derive(Clone, Debug)]
+pub struct Storage {
+    path: PathBuf,
+    /// Serializes the read-modify-write cycles on the file
+    lock: Arc<Mutex<()>>,
+}
 
-    let file_data = match fs::read_to_string(path) {
-        Ok(f) => f,
-        Err(_) => "".to_string()
-    };
+impl Storage {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Storage {
+            path: path.into(),
+            lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    /// Creates the storage at `USER_DATA_PATH`.
+    pub fn from_env() -> Self {
+        let user_data_path = std::env::var("USER_DATA_PATH").expect("USER_DATA_PATH must be set.");
+        Storage::new(user_data_path)
+    }
 
-    let mut users: Vec<UserData> = match serde_json::from_str(&file_data) {
-        Ok(u) => u,
-        Err(_) => vec![]
-    };
+    pub fn store_user_data(&self, user: UserData) -> Result<(), Box<dyn Error + Sync + Send>> {
+        let _guard = self.lock.lock().unwrap();
+        let path = Path::new(&self.path);
 
-    match users.iter_mut().find(|x| x.id == user.id) {
-        Some(u) => {
-            *u = user;
-        },
-        None => {
-            users.push(user)
+        let file_data = match fs::read_to_string(path) {
+            Ok(f) => f,
+            Err(_) => "".to_string()
+        };
+
+        let mut users: Vec<UserData> = serde_json::from_str(&file_data).unwrap_or_default();
+
+        match users.iter_mut().find(|x| x.id == user.id) {
+            Some(u) => {
+                *u = user;
+            },
+            None => {
+                users.push(user)
+            }
         }
+
+        let json = serde_json::to_string(&users)?;
+        fs::write(path, json.as_bytes())?;
+
+        Ok(())
     }
 
-    let json = serde_json::to_string(&users).unwrap();
-    fs::write(path, json.as_bytes()).unwrap();
+    pub fn get_user_data(&self, user_id: String) -> Result<UserData, Box<dyn Error + Sync + Send>> {
+        let _guard = self.lock.lock().unwrap();
+        let path = Path::new(&self.path);
 
-    Ok(())
-}
+        let mut file_data = String::new();
+        let mut file = match File::open(path) {
+            Ok(f) => f,
+            Err(e) => {return Err(e)?}
+        };
+        file.read_to_string(&mut file_data).expect("Unable to read to string");
 
-pub fn get_user_data(user_id: String) -> Result<UserData, Box<dyn Error + Sync + Send>> {
-    let user_data_path = std::env::var("USER_DATA_PATH").expect("USER_DATA_PATH must be set.");
-    let path = Path::new(&user_data_path);
+        let users: Vec<UserData> = serde_json::from_str(&file_data)?;
 
-    let mut file_data = String::new();
-    let mut file = match File::open(path) {
-        Ok(f) => f,
-        Err(e) => {return Err(e)?}
-    };
-    file.read_to_string(&mut file_data).expect("Unable to read to string");
-    
-    let users: Vec<UserData> = serde_json::from_str(&file_data)?;
+        match users.iter().find(|&u| u.id == user_id) {
+            Some(user) => Ok(user.to_owned()),
+            None => Err("User not found!")?
+        }
+    }
 
-    match users.iter().find(|&u| u.id == user_id) {
-        Some(user) => Ok(user.to_owned()),
-        None => Err("User not found!")?
+    /// Records of all users and groups, none if nothing was stored yet.
+    pub fn all_user_data(&self) -> Result<Vec<UserData>, Box<dyn Error + Sync + Send>> {
+        let _guard = self.lock.lock().unwrap();
+        let file_data = fs::read_to_string(&self.path).unwrap_or_default();
+        if file_data.is_empty() {
+            return Ok(vec![]);
+        }
+        Ok(serde_json::from_str(&file_data)?)
     }
-}
\ No newline at end of file
+
+    /// Deletes the record of a user, `false` if there was none.
+    pub fn delete_user_data(&self, user_id: &str) -> Result<bool, Box<dyn Error + Sync + Send>> {
+        let _guard = self.lock.lock().unwrap();
+        let path = Path::new(&self.path);
+
+        let file_data = fs::read_to_string(path).unwrap_or_default();
+        let mut users: Vec<UserData> = serde_json::from_str(&file_data).unwrap_or_default();
+
+        let len = users.len();
+        users.retain(|u| u.id != user_id);
+        if users.len() == len {
+            return Ok(false);
+        }
+
+        let json = serde_json::to_string(&users)?;
+        fs::write(path, json.as_bytes())?;
+        Ok(true)
+    }
+
+    /// Forgets the location of the users who weren't active within the retention period and
+    /// returns how many. Users stored before their activity was recorded count as active from
+    /// the first purge on.
+    pub fn purge_inactive_users(
+        &self,
+        now: DateTime<Utc>,
+        retention: Duration,
+    ) -> Result<usize, Box<dyn Error + Sync + Send>> {
+        let _guard = self.lock.lock().unwrap();
+        let path = Path::new(&self.path);
+
+        let file_data = fs::read_to_string(path).unwrap_or_default();
+        let mut users: Vec<UserData> = serde_json::from_str(&file_data).unwrap_or_default();
+
+        let mut purged = 0;
+        let mut changed = false;
+        // Groups are stored with their negative chat ids and have no location of a user
+        for user in users.iter_mut().filter(|u| !u.id.starts_with('-')) {
+            match user.last_active {
+                None => user.last_active = Some(now),
+                Some(active) if now - active > retention && user.has_location() => {
+                    user.forget_location();
+                    purged += 1;
+                }
+                _ => continue,
+            }
+            changed = true;
+        }
+        if !changed {
+            return Ok(0);
+        }
+
+        let json = serde_json::to_string(&users)?;
+        fs::write(path, json.as_bytes())?;
+        Ok(purged)
+    }
+
+    /// Records that the user was active, `false` if nothing is stored about them.
+    pub fn touch_user(
+        &self,
+        user_id: &str,
+        now: DateTime<Utc>,
+    ) -> Result<bool, Box<dyn Error + Sync + Send>> {
+        let _guard = self.lock.lock().unwrap();
+        let path = Path::new(&self.path);
+
+        let file_data = fs::read_to_string(path).unwrap_or_default();
+        let mut users: Vec<UserData> = serde_json::from_str(&file_data).unwrap_or_default();
+
+        match users.iter_mut().find(|u| u.id == user_id) {
+            Some(user) => user.last_active = Some(now),
+            None => return Ok(false),
+        }
+
+        let json = serde_json::to_string(&users)?;
+        fs::write(path, json.as_bytes())?;
+        Ok(true)
+    }
+}
@@ -0,0 +1,83 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, Utc};
+use teloxide::types::{ChatId, MessageId, UserId};
+
+use crate::structs::Location;
+
+//////////////////////////////////////////////////////////
+// Live locations
+//////////////////////////////////////////////////////////
+/// Meters a live location has to move before the stations are searched again
+pub const MIN_MOVE: i64 = 100;
+
+/// Live location shared by a user, as far as the bot has handled it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LiveLocation {
+    /// Where the stations were last searched from
+    pub location: Location,
+    /// Message with the keyboard of the stations near the location, if it was sent for it
+    pub keyboard: Option<MessageId>,
+    /// Stop last suggested to catch the tracked transit from
+    pub suggested: Option<String>,
+    /// When the location stops being updated
+    pub until: DateTime<Utc>,
+}
+
+/// Live locations shared by the users, keyed by the chat and the user like the trackings.
+#[derive(Clone, Debug, Default)]
+pub struct LiveLocations {
+    locations: Arc<Mutex<HashMap<(ChatId, UserId), LiveLocation>>>,
+}
+
+impl LiveLocations {
+    /// Starts following a live location, replacing the one the user shared before.
+    pub fn start(&self, key: (ChatId, UserId), live: LiveLocation, now: DateTime<Utc>) {
+        let mut locations = self.locations.lock().unwrap();
+        // Locations that ended without a last update are dropped here
+        locations.retain(|_, l| l.until >= now);
+        locations.insert(key, live);
+    }
+
+    pub fn get(&self, key: (ChatId, UserId)) -> Option<LiveLocation> {
+        self.locations.lock().unwrap().get(&key).cloned()
+    }
+
+    /// Takes the new position of a live location if it moved at least `MIN_MOVE` meters from
+    /// where it was last handled. `None` if it moved less or isn't followed (anymore).
+    pub fn moved(&self, key: (ChatId, UserId), location: Location, now: DateTime<Utc>) -> Option<LiveLocation> {
+        let mut locations = self.locations.lock().unwrap();
+        let live = locations.get_mut(&key)?;
+        if live.until < now {
+            locations.remove(&key);
+            return None;
+        }
+        match live.location.meters_to(&location) {
+            Some(meters) if meters >= MIN_MOVE => {
+                live.location = location;
+                Some(live.clone())
+            }
+            _ => None,
+        }
+    }
+
+    /// Remembers the stop suggested to the user, `false` if it is the one suggested last.
+    pub fn suggest(&self, key: (ChatId, UserId), stop_id: &str) -> bool {
+        let mut locations = self.locations.lock().unwrap();
+        match locations.get_mut(&key) {
+            Some(live) if live.suggested.as_deref() != Some(stop_id) => {
+                live.suggested = Some(stop_id.to_string());
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Stops following the live location of the user.
+    pub fn stop(&self, key: (ChatId, UserId)) {
+        self.locations.lock().unwrap().remove(&key);
+    }
+}
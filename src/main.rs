@@ -1,17 +1,36 @@
 pub mod api;
+pub mod config;
+pub mod formatting;
+pub mod i18n;
 pub mod io;
+pub mod live;
+pub mod map;
+pub mod notifier;
+pub mod sender;
 pub mod structs;
+pub mod throttle;
+pub mod tracker;
 #[cfg(test)]
 mod tests;
 
 use api::*;
-use chrono::{DateTime, Duration};
+use formatting::*;
+use chrono::{DateTime, Duration, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use i18n::*;
 use io::*;
+use live::*;
+use map::*;
+use notifier::*;
+use sender::*;
 use structs::*;
+use throttle::*;
+use tracker::*;
 
 use std::{
-    collections::HashMap,
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
     error::Error,
+    hash::{Hash, Hasher},
     fs::File,
     io::Read,
     path::Path,
@@ -21,14 +40,16 @@ use std::{
 use dotenv::dotenv;
 use dptree::{case, deps};
 use teloxide::{
-    dispatching::{dialogue, dialogue::InMemStorage},
+    dispatching::{dialogue::InMemStorage, UpdateHandler},
     dptree::endpoint,
     filter_command,
     payloads::SendMessageSetters,
     prelude::*,
     types::{
-        InlineKeyboardButton, InlineKeyboardMarkup, KeyboardButton, KeyboardMarkup, KeyboardRemove,
-        MessageCommon, MessageKind, ParseMode::Html, ReplyMarkup,
+        InlineKeyboardButton, InlineKeyboardMarkup, InlineQueryResult, InlineQueryResultArticle,
+        InputFile, InputMessageContent, InputMessageContentText, KeyboardButton, KeyboardMarkup,
+        KeyboardRemove, Me, ParseMode::Html, ReplyMarkup, UpdateKind,
+        User,
     },
     utils::command::BotCommands,
 };
@@ -36,7 +57,10 @@ use tokio::task::JoinHandle;
 
 type HandlerResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
 type MyDialogue = Dialogue<State, InMemStorage<State>>;
-type MyTasksMap = Arc<Mutex<HashMap<String, JoinHandle<HandlerResult>>>>;
+/// Trackings of each member of a chat, so members of a group don't cancel each other's ones.
+type MyTasksMap = Arc<Mutex<HashMap<(ChatId, UserId), JoinHandle<HandlerResult>>>>;
+/// User ids of the operators of the bot, read from `ADMIN_IDS` once at startup.
+type Admins = Arc<HashSet<UserId>>;
 
 #[derive(BotCommands, Clone)]
 #[command(
@@ -50,9 +74,39 @@ enum Command {
     Start,
     #[command(description = "Cancel the tracking.")]
     Cancel,
+    #[command(description = "Select which transit products (bus, tram, ...) you want to see.")]
+    Products,
+    #[command(description = "Choose the language of the bot: de, en or auto.")]
+    Language(String),
+    #[command(description = "Change your preferences like update intervals and quiet hours.")]
+    Settings,
+    #[command(description = "Get all data stored about you as a JSON file.")]
+    MyData,
+    #[command(description = "Delete all data stored about you and stop your trackings.")]
+    Forget,
+    #[command(description = "Show the departures of the group's stop, to track a transit each.")]
+    Board,
+    #[command(description = "Set the stop of the group, only for admins.")]
+    SetStop(String),
+    #[command(description = "Remove the stop of the group, only for admins.")]
+    ClearStop,
 }
 
-#[derive(Clone, Default)]
+/// Commands of the operators of the bot, whose user ids are set with `ADMIN_IDS`.
+#[derive(BotCommands, Clone)]
+#[command(rename_rule = "lowercase", description = "Operator commands:")]
+enum AdminCommand {
+    #[command(description = "Show the number of users, active trackings and API error rates.")]
+    Stats,
+    #[command(description = "Send a message to all stored users.")]
+    Broadcast(String),
+    #[command(description = "List the active trackings to cancel them.")]
+    Trackings,
+    #[command(description = "Show or delete the stored data of a user.")]
+    User(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Default)]
 enum State {
     #[default]
     Start,
@@ -64,6 +118,8 @@ enum State {
         city: String,
         addr: String,
         stations: Vec<Station>,
+        /// Search the stations were found with, `None` if they can't be searched again
+        nearby: Option<NearbyQuery>,
     },
     ReceiveTransit {
         city: String,
@@ -71,6 +127,8 @@ enum State {
         stations: Vec<Station>,
         stop: String,
         stop_id: String,
+        board: BoardKind,
+        query: BoardQuery,
     },
     ReceiveMinutes {
         city: String,
@@ -78,9 +136,21 @@ enum State {
         stations: Vec<Station>,
         stop: String,
         stop_id: String,
+        board: BoardKind,
+        query: BoardQuery,
+        /// Label of the transit for the messages, it is tracked by its trip
+        transit: String,
+        trip_id: String,
+    },
+    ReceiveCancel {
+        stop_id: String,
+        board: BoardKind,
+        query: BoardQuery,
         transit: String,
+        trip_id: String,
     },
-    ReceiveCancel,
+    ReceiveProducts,
+    ReceiveSettings,
 }
 
 #[tokio::main]
@@ -92,26 +162,151 @@ async fn main() -> Result<(), Box<dyn Error>> {
     dotenv().ok();
     let bot = Bot::from_env();
 
+    // Shared HashMap of JoinHandles of tasks to be able to cancel the timer.
+    let tasks: MyTasksMap = Arc::new(Mutex::new(HashMap::new()));
+    let storage = Storage::from_env();
+    // Trackings and broadcasts send their messages in the limits of Telegram
+    let queue = SendQueue::new(bot.clone(), SendLimits::default());
+
+    if let Some(retention) = config::retention() {
+        tokio::spawn(purge_inactive_users(storage.clone(), retention));
+    }
+
+    Dispatcher::builder(bot, schema())
+        .dependencies(deps![
+            InMemStorage::<State>::new(),
+            tasks.clone(),
+            Api::from_env(),
+            storage,
+            Throttle::from_env(),
+            queue.clone(),
+            LiveLocations::default(),
+            Admins::new(config::admin_ids())
+        ])
+        .enable_ctrlc_handler()
+        .build()
+        .dispatch()
+        .await;
+
+    shut_down(tasks, queue).await;
+    Ok(())
+}
+
+/// Time the trackings get on shutdown to tell their chats that they stopped
+const SHUTDOWN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Stops all trackings once the dispatcher stopped and waits for their last messages,
+/// trackings that take longer than `SHUTDOWN_TIMEOUT` are dropped.
+async fn shut_down(tasks: MyTasksMap, queue: SendQueue) {
+    queue.close();
+    let handles: Vec<_> = tasks.lock().unwrap().drain().collect();
+    log::info!("Stopping {} trackings ...", handles.iter().filter(|(_, h)| !h.is_finished()).count());
+
+    let deadline = tokio::time::Instant::now() + SHUTDOWN_TIMEOUT;
+    for ((chat_id, _), mut handle) in handles {
+        if tokio::time::timeout_at(deadline, &mut handle).await.is_err() {
+            log::warn!("Dropping the tracking of {} that didn't stop in time", chat_id);
+            handle.abort();
+        }
+    }
+}
+
+/// Time between the runs of the job purging the locations of inactive users
+const PURGE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+/// Forgets the locations of the users who weren't active within the retention period, every hour.
+async fn purge_inactive_users(storage: Storage, retention: Duration) {
+    let mut interval = tokio::time::interval(PURGE_INTERVAL);
+    loop {
+        interval.tick().await;
+        match storage.purge_inactive_users(Utc::now(), retention) {
+            Ok(0) => (),
+            Ok(n) => log::info!("Purged the locations of {} inactive users", n),
+            Err(e) => log::error!("Unable to purge the inactive users: {:?}", e),
+        }
+    }
+}
+
+/// Handler tree of all updates, dispatching them by command and dialogue state.
+fn schema() -> UpdateHandler<Box<dyn Error + Send + Sync + 'static>> {
     let command_handler = filter_command::<Command, _>()
         .branch(
             case![State::Start]
                 .branch(case![Command::Help].endpoint(help))
-                .branch(case![Command::Start].endpoint(start)),
+                .branch(case![Command::Start].endpoint(start))
+                .branch(case![Command::Products].endpoint(products))
+                .branch(case![Command::Language(code)].endpoint(language))
+                .branch(case![Command::Settings].endpoint(settings))
+                .branch(case![Command::MyData].endpoint(my_data))
+                .branch(case![Command::Board].endpoint(group_board))
+                .branch(case![Command::SetStop(name)].endpoint(set_stop))
+                .branch(case![Command::ClearStop].endpoint(clear_stop)),
         )
-        .branch(case![Command::Cancel].endpoint(cancel));
+        .branch(case![Command::Cancel].endpoint(cancel))
+        .branch(case![Command::Forget].endpoint(forget));
 
     let message_handler = Update::filter_message()
         .branch(command_handler)
         .branch(case![State::ReceiveCity].endpoint(receive_city))
         .branch(case![State::ReceiveAddress { city }].endpoint(receive_address))
+        .branch(
+            case![State::ReceiveTransit {
+                city,
+                addr,
+                stations,
+                stop,
+                stop_id,
+                board,
+                query,
+            }]
+            .endpoint(receive_board_time),
+        )
+        .branch(
+            case![State::ReceiveMinutes {
+                city,
+                addr,
+                stations,
+                stop,
+                stop_id,
+                board,
+                query,
+                transit,
+                trip_id,
+            }]
+            .branch(dptree::filter_map(PlanChoice::from_message).endpoint(receive_minutes))
+            .endpoint(invalid_plan),
+        )
+        // Live locations shared during a tracking, to find a closer stop
+        .branch(
+            dptree::filter(|msg: Message, state: State| {
+                matches!(state, State::ReceiveCancel { .. })
+                    && msg.location().is_some_and(|l| l.live_period.is_some())
+            })
+            .map(follow_live_location)
+            .endpoint(receive_live_location),
+        )
         .branch(endpoint(invalid_state));
 
+    // Live locations are updated by editing their message
+    let edited_message_handler = Update::filter_edited_message()
+        .filter(|msg: Message| msg.location().is_some())
+        .map(follow_live_location)
+        .endpoint(receive_live_location);
+
     let callback_query_handler = Update::filter_callback_query()
+        .branch(
+            case![State::Start]
+                .filter(|q: CallbackQuery| {
+                    q.data.as_deref().is_some_and(|d| d.starts_with(GROUP_BOARD))
+                })
+                .endpoint(receive_group_board),
+        )
         .branch(
             case![State::ReceiveStop {
                 city,
                 addr,
-                stations
+                stations,
+                nearby
             }]
             .endpoint(receive_stop),
         )
@@ -122,6 +317,8 @@ async fn main() -> Result<(), Box<dyn Error>> {
                 stations,
                 stop,
                 stop_id,
+                board,
+                query,
             }]
             .endpoint(receive_transit),
         )
@@ -132,31 +329,207 @@ async fn main() -> Result<(), Box<dyn Error>> {
                 stations,
                 stop,
                 stop_id,
+                board,
+                query,
                 transit,
+                trip_id,
             }]
+            .filter_map(PlanChoice::from_callback)
             .endpoint(receive_minutes),
         )
-        .branch(case![State::ReceiveCancel].endpoint(receive_cancel));
+        .branch(
+            case![State::ReceiveCancel {
+                stop_id,
+                board,
+                query,
+                transit,
+                trip_id
+            }]
+            .branch(
+                dptree::filter(|q: CallbackQuery| q.data.as_deref() == Some(TRIP))
+                    .endpoint(receive_trip),
+            )
+            .endpoint(receive_cancel),
+        )
+        .branch(case![State::ReceiveProducts].endpoint(receive_products))
+        .branch(case![State::ReceiveSettings].endpoint(receive_settings));
 
-    let dial = dialogue::enter::<Update, InMemStorage<State>, State, _>()
-        .branch(message_handler)
-        .branch(callback_query_handler);
+    // Inline queries are not part of any chat or dialogue
+    let inline_query_handler = Update::filter_inline_query().endpoint(inline_query);
 
-    // Shared HashMap of JoinHandles of tasks to be able to cancel the timer.
-    let tasks: Arc<Mutex<HashMap<String, JoinHandle<HandlerResult>>>> =
-        Arc::new(Mutex::new(HashMap::new()));
+    // Operators can use their commands in any state of their dialogue
+    let admin_handler = dptree::filter(|upd: Update, admins: Admins| {
+        upd.user().is_some_and(|u| admins.contains(&u.id))
+    })
+        .branch(
+            Update::filter_message().branch(
+                filter_command::<AdminCommand, _>()
+                    .branch(case![AdminCommand::Stats].endpoint(stats))
+                    .branch(case![AdminCommand::Broadcast(text)].endpoint(broadcast))
+                    .branch(case![AdminCommand::Trackings].endpoint(trackings))
+                    .branch(case![AdminCommand::User(id)].endpoint(user_info)),
+            ),
+        )
+        .branch(
+            Update::filter_callback_query()
+                .branch(dptree::filter_map(AdminCancel::from_callback).endpoint(receive_admin_cancel))
+                .branch(dptree::filter_map(AdminDelete::from_callback).endpoint(receive_admin_delete)),
+        );
+
+    // Like `dialogue::enter`, but with a dialogue for each member of a group
+    let dialogue_handler = dptree::filter_map(
+        |upd: Update, dialogues: Arc<InMemStorage<State>>| {
+            dialogue_key(&upd).map(|key| MyDialogue::new(dialogues, key))
+        },
+    )
+    .filter_map_async(|dialogue: MyDialogue| async move {
+        match dialogue.get_or_default().await {
+            Ok(state) => Some(state),
+            Err(e) => {
+                log::error!("Unable to get the dialogue state: {:?}", e);
+                None
+            }
+        }
+    })
+    .branch(message_handler)
+    .branch(edited_message_handler)
+    .branch(callback_query_handler);
+
+    // Updates beyond the budgets end here, before any API is called. Operators are never throttled
+    let throttle_handler = dptree::filter_async(is_for_bot)
+        .filter_map(|upd: Update, throttle: Throttle, admins: Admins| {
+            let user = upd.user()?;
+            match admins.contains(&user.id) {
+                true => None,
+                false => throttle.check(user.id).err(),
+            }
+        })
+        .endpoint(send_cooldown);
+
+    dptree::entry()
+        .branch(throttle_handler)
+        .inspect_async(mark_active)
+        .branch(inline_query_handler)
+        .branch(admin_handler)
+        .branch(dialogue_handler)
+}
 
-    Dispatcher::builder(bot, dial)
-        .dependencies(deps![InMemStorage::<State>::new(), tasks])
-        .enable_ctrlc_handler()
-        .build()
-        .dispatch()
-        .await;
+/// Key of the dialogue of an update, the chat itself in private chats. Members of a group have
+/// their own dialogues, keyed by a hash of the chat and the user above the range of chat ids.
+fn dialogue_key(upd: &Update) -> Option<ChatId> {
+    Some(member_dialogue_key(upd.chat()?.id, upd.user()?.id))
+}
+
+/// Key of the dialogue of a user in a chat, see `dialogue_key`.
+fn member_dialogue_key(chat_id: ChatId, user_id: UserId) -> ChatId {
+    if chat_id.is_user() {
+        return chat_id;
+    }
+    let mut hasher = DefaultHasher::new();
+    (chat_id, user_id).hash(&mut hasher);
+    ChatId((hasher.finish() >> 2) as i64 | 1 << 62)
+}
+
+/// Whether the bot handles the update, only these are charged to the throttle. In groups only
+/// the commands and the input of the members' dialogues are, the rest is chatting about other
+/// things. Edits are only handled for live locations, which are charged as they move.
+async fn is_for_bot(upd: Update, me: Me, dialogues: Arc<InMemStorage<State>>) -> bool {
+    let msg = match &upd.kind {
+        UpdateKind::Message(msg) => msg,
+        UpdateKind::CallbackQuery(_) | UpdateKind::InlineQuery(_) => return true,
+        _ => return false,
+    };
+    if msg.chat.is_private() {
+        return true;
+    }
+    let text = msg.text().unwrap_or_default();
+    if Command::parse(text, me.username()).is_ok() || AdminCommand::parse(text, me.username()).is_ok() {
+        return true;
+    }
+    let state = match dialogue_key(&upd) {
+        Some(key) => MyDialogue::new(dialogues, key).get().await.ok().flatten(),
+        None => None,
+    };
+    match state {
+        Some(State::ReceiveCancel { .. }) => msg.location().is_some(),
+        Some(State::Start) | None => false,
+        Some(_) => true,
+    }
+}
+
+/// Keeps the stored data of the users the bot handles an update of, see `purge_inactive_users`.
+async fn mark_active(upd: Update, me: Me, dialogues: Arc<InMemStorage<State>>, storage: Storage) {
+    let user_id = match upd.user() {
+        Some(user) => user.id,
+        None => return,
+    };
+    if !is_for_bot(upd, me, dialogues).await {
+        return;
+    }
+    if let Err(e) = storage.touch_user(&user_id.to_string(), Utc::now()) {
+        log::error!("Unable to record the activity of {}: {:?}", user_id, e);
+    }
+}
+
+/// Tells the user to wait before sending the next update, only once for each cooldown.
+async fn send_cooldown(bot: Bot, upd: Update, cooldown: Cooldown, storage: Storage) -> HandlerResult {
+    let lang = get_user_lang(&storage, upd.user());
+    // Rounded up, so it never says 0 seconds
+    let seconds = (cooldown.wait.as_millis() as i64 + 999) / 1000;
+    let text = lang.tr_args("cooldown", &[("seconds", seconds.into())]);
+    match upd.kind {
+        // The loading animation of the button keeps spinning unless the query is answered
+        UpdateKind::CallbackQuery(q) => {
+            bot.answer_callback_query(q.id).text(text).await?;
+        }
+        // Inline queries keep loading until they are answered, the empty answer lasts the cooldown
+        UpdateKind::InlineQuery(q) => {
+            bot.answer_inline_query(q.id, vec![])
+                .is_personal(true)
+                .cache_time(seconds as u32)
+                .await?;
+        }
+        // Groups are chatting about other things than the bot
+        UpdateKind::Message(msg) if cooldown.notify && msg.chat.is_private() => {
+            bot.send_message(msg.chat.id, text).await?;
+        }
+        _ => (),
+    }
+    Ok(())
+}
+
+async fn help(bot: Bot, msg: Message, storage: Storage) -> HandlerResult {
+    let lang = get_user_lang(&storage, msg.from());
+    bot.send_message(msg.chat.id, lang.tr("help")).await?;
     Ok(())
 }
 
-async fn help(bot: Bot, msg: Message) -> HandlerResult {
-    bot.send_message(msg.chat.id, Command::descriptions().to_string())
+/// Stores the language chosen by the user, `auto` uses the one of the Telegram app again.
+async fn language(bot: Bot, msg: Message, code: String, storage: Storage) -> HandlerResult {
+    let language = match code.trim().to_lowercase().as_str() {
+        "de" | "deutsch" => Some(Lang::De),
+        "en" | "english" => Some(Lang::En),
+        "auto" => None,
+        _ => {
+            let lang = get_user_lang(&storage, msg.from());
+            bot.send_message(msg.chat.id, lang.tr("language-usage"))
+                .parse_mode(Html)
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let user = match get_user_id(&msg) {
+        Some(user) => user,
+        None => return Ok(()),
+    };
+    let mut user_data = storage.get_user_data(user.clone()).unwrap_or_default();
+    user_data.id = user;
+    user_data.language = language;
+    storage.store_user_data(user_data)?;
+
+    let lang = get_user_lang(&storage, msg.from());
+    bot.send_message(msg.chat.id, lang.tr("language-saved"))
         .await?;
     Ok(())
 }
@@ -164,17 +537,28 @@ async fn help(bot: Bot, msg: Message) -> HandlerResult {
 //////////////////////////////////////////////////////////
 // State handlers
 //////////////////////////////////////////////////////////
-async fn invalid_state(bot: Bot, msg: Message) -> HandlerResult {
-    bot.send_message(
-        msg.chat.id,
-        "Unable to handle the message. Type /help to see the usage.",
-    )
-    .await?;
+async fn invalid_state(bot: Bot, msg: Message, storage: Storage) -> HandlerResult {
+    // Groups are chatting about other things than the bot
+    if !msg.chat.is_private() {
+        return Ok(());
+    }
+    let lang = get_user_lang(&storage, msg.from());
+    bot.send_message(msg.chat.id, lang.tr("invalid-message"))
+        .await?;
     Ok(())
 }
 
-async fn cancel(bot: Bot, dialogue: MyDialogue, msg: Message, tasks: MyTasksMap) -> HandlerResult {
-    let user = get_user_id(&msg);
+async fn cancel(
+    queue: SendQueue,
+    dialogue: MyDialogue,
+    msg: Message,
+    tasks: MyTasksMap,
+    storage: Storage,
+) -> HandlerResult {
+    let user = match msg.from() {
+        Some(user) => (msg.chat.id, user.id),
+        None => return Ok(()),
+    };
     {
         let mut t = tasks.lock().unwrap();
         if let Some(v) = t.get(&user) {
@@ -182,64 +566,77 @@ async fn cancel(bot: Bot, dialogue: MyDialogue, msg: Message, tasks: MyTasksMap)
             t.remove(&user);
         }
     }
-    bot.send_message(
-        msg.chat.id,
-        "🚫 Cancelled! You can start over using /start.",
-    )
-    .await?;
+    let lang = get_user_lang(&storage, msg.from());
+    let request = queue.bot().send_message(msg.chat.id, lang.tr("cancelled"));
+    queue.send(msg.chat.id, request).await?;
     dialogue.exit().await?;
     Ok(())
 }
 
-async fn start(bot: Bot, dialogue: MyDialogue, msg: Message) -> HandlerResult {
-    let user_id = match &msg.kind {
-        MessageKind::Common(MessageCommon { from, .. }) => Some(from.as_ref().unwrap().id),
-        _ => None,
+async fn start(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+    api: Api,
+    storage: Storage,
+) -> HandlerResult {
+    // Channel posts and messages on behalf of a chat have no user to start a dialogue with
+    let user = match msg.from() {
+        Some(user) => user,
+        None => return Ok(()),
     };
-
-    let UserId(id_num) = user_id.unwrap();
-    match get_user_data(id_num.to_string()) {
-        Ok(data) => {
-            let stations = get_nearby_stations(data.lat.clone(), data.lon.clone()).await?;
-            let mut stations_names = stations
-                .iter()
-                .map(|x| x.name.as_str())
-                .collect::<Vec<&str>>();
-
-            let mut kb_buttons: Vec<&str> = vec![];
-            kb_buttons.append(&mut stations_names);
-            kb_buttons.push("<< Change address");
-
-            let kb = make_inline_keyboard(kb_buttons, 2);
-            // Parse station names as keyboard buttons
-            // let kb = make_inline_keyboard(stations_names.clone(), 2);
-            bot.send_message(
-                msg.chat.id,
-                format!(
-                    "I found your last used address: \n<b>{}, {} 📍</b>\n
-Now please select which transit station you want to track 👀.\n
-Here are the nearby transit stations:",
-                    data.addr, data.city
+    let UserId(id_num) = user.id;
+    let prefs = get_user_prefs(&storage, user);
+    let lang = prefs.lang;
+    match storage.get_user_data(id_num.to_string()) {
+        // Users who only stored preferences have no address yet
+        Ok(data) if data.has_location() => {
+            storage.store_user_data(UserData {
+                last_active: Some(msg.date),
+                ..data.clone()
+            })?;
+
+            // Depending on the privacy mode only the coordinates or the stop are stored
+            let (stations, nearby, text) = match &data.stop {
+                Some(stop) if data.lat.is_empty() => (
+                    vec![stop.station()],
+                    None,
+                    lang.tr_args("last-stop", &[("stop", escape_name(&stop.name).into())]),
                 ),
-            )
-            .parse_mode(Html)
-            .reply_markup(kb)
-            .await?;
+                _ => {
+                    let nearby = config::nearby_query();
+                    let stations = api
+                        .get_nearby_stations(data.lat.clone(), data.lon.clone(), &data.products, &nearby)
+                        .await?;
+                    let text = match data.addr.is_empty() {
+                        true => lang.tr_args("last-area", &[("city", escape_name(&data.city).into())]),
+                        false => lang.tr_args(
+                            "last-address",
+                            &[("addr", escape_name(&data.addr).into()), ("city", escape_name(&data.city).into())],
+                        ),
+                    };
+                    (stations, Some(nearby), text)
+                }
+            };
+            let more = nearby.is_some_and(|n| n.more().is_some());
+            let kb = make_stations_keyboard(&stations, lang, &prefs.settings, more);
+            bot.send_message(msg.chat.id, text)
+                .parse_mode(Html)
+                .reply_markup(kb)
+                .await?;
             dialogue
                 .update(State::ReceiveStop {
                     city: data.city,
                     addr: data.addr,
                     stations,
+                    nearby,
                 })
                 .await?
         }
-        Err(_) => {
-            bot.send_message(
-                msg.chat.id,
-                format!("🔰 Let's start tracking a transit 🚌🚇!\n\nWhat city do you live in?"),
-            )
-            .reply_markup(KeyboardRemove::new())
-            .await?;
+        _ => {
+            bot.send_message(msg.chat.id, lang.tr("ask-city"))
+                .reply_markup(KeyboardRemove::new())
+                .await?;
             // println!("{:#?}", msg);
             dialogue.update(State::ReceiveCity).await?;
         }
@@ -247,24 +644,18 @@ Here are the nearby transit stations:",
     Ok(())
 }
 
-async fn receive_city(bot: Bot, dialogue: MyDialogue, msg: Message) -> HandlerResult {
+async fn receive_city(bot: Bot, dialogue: MyDialogue, msg: Message, storage: Storage) -> HandlerResult {
+    let lang = get_user_lang(&storage, msg.from());
     match msg.text().map(ToOwned::to_owned) {
         Some(city) => {
-            bot.send_message(
-                msg.chat.id,
-                format!("Awesome so you live in <b>{}</b> 🏙!
-\n\nWhat is your locations <b>street address</b> so i can search for nearby transit stops?
-You can also send me a <b>location</b> 📍 instead!", city),
-            ).parse_mode(Html)
-            .await?;
+            bot.send_message(msg.chat.id, lang.tr_args("ask-address", &[("city", escape_name(&city).into())]))
+                .parse_mode(Html)
+                .await?;
             dialogue.update(State::ReceiveAddress { city }).await?;
         }
         None => {
-            bot.send_message(
-                msg.chat.id,
-                "❌ Please, send me your city that you live in, so i can start tracking.",
-            )
-            .await?;
+            bot.send_message(msg.chat.id, lang.tr("missing-city"))
+                .await?;
         }
     }
     Ok(())
@@ -275,77 +666,86 @@ async fn receive_address(
     dialogue: MyDialogue,
     msg: Message,
     city: String,
+    api: Api,
+    storage: Storage,
+    live: LiveLocations,
 ) -> HandlerResult {
+    let lang = get_user_lang(&storage, msg.from());
     let location = msg.location();
     let address = msg.text().map(ToOwned::to_owned);
     if location.is_none() && address.is_none() {
-        bot.send_message(
-            msg.chat.id,
-            "❌ Please, send me your address, so i can start tracking.",
-        )
-        .await?;
+        bot.send_message(msg.chat.id, lang.tr("missing-address"))
+            .await?;
         return Ok(());
     }
     let addr;
 
     // Store info of user for next time
-    let user_id = match &msg.kind {
-        MessageKind::Common(MessageCommon { from, .. }) => Some(from.as_ref().unwrap().id),
-        _ => None,
+    let UserId(id_num) = match msg.from() {
+        Some(user) => user.id,
+        None => return Ok(()),
     };
-
-    let UserId(id_num) = user_id.unwrap();
+    let mut user_data = storage.get_user_data(id_num.to_string()).unwrap_or_default();
 
     // Get geocode if location was sent instead of an address
     let geocode = match location {
         Some(loc) => {
-            addr = fetch_address(loc.latitude.to_string(), loc.longitude.to_string()).await?;
+            addr = api.fetch_address(loc.latitude.to_string(), loc.longitude.to_string()).await?;
             (loc.latitude.to_string(), loc.longitude.to_string())
         },
         None => {
             addr = address.unwrap();
-            fetch_geocode(addr.clone(), city.clone()).await?
+            api.fetch_geocode(addr.clone(), city.clone()).await?
         }
     };
 
-    let stations = get_nearby_stations(geocode.0.clone(), geocode.1.clone()).await?;
-    let mut stations_names = stations
-        .iter()
-        .map(|x| x.name.as_str())
-        .collect::<Vec<&str>>();
-
-    let user_data = UserData {
-        id: id_num.to_string(),
-        city: city.clone(),
-        addr: addr.clone(),
-        lat: geocode.0,
-        lon: geocode.1,
-    };
-    store_user_data(user_data).unwrap();
+    let nearby = config::nearby_query();
+    let stations = api
+        .get_nearby_stations(geocode.0.clone(), geocode.1.clone(), &user_data.products, &nearby)
+        .await?;
 
-    let mut kb_buttons: Vec<&str> = vec![];
-    kb_buttons.append(&mut stations_names);
-    kb_buttons.push("<< Change address");
+    user_data.id = id_num.to_string();
+    user_data.city = city.clone();
+    user_data.addr = addr.clone();
+    user_data.lat = geocode.0;
+    user_data.lon = geocode.1;
+    user_data.last_active = Some(msg.date);
+    user_data.apply_privacy();
+    storage.store_user_data(user_data.clone())?;
+
+    let kb = make_stations_keyboard(&stations, lang, &user_data.settings, nearby.more().is_some());
+    let keyboard = bot
+        .send_message(
+            msg.chat.id,
+            lang.tr_args(
+                "address-saved",
+                &[("addr", escape_name(&addr).into()), ("city", escape_name(&city).into())],
+            ),
+        )
+        .parse_mode(Html)
+        .reply_markup(kb)
+        .await?;
 
-    let kb = make_inline_keyboard(kb_buttons, 2);
-    bot.send_message(
-        msg.chat.id,
-        format!(
-            "Thank you! So your address is: \n<b>{}, {} 📍</b>\n\n
-Now please select which transit station you want to track 👀.\n
-Here are the nearby transit stations:",
-            addr, city
-        ),
-    )
-    .parse_mode(Html)
-    .reply_markup(kb)
-    .await?;
+    // The stations of a live location are updated as the user moves, it is only kept in memory
+    if let Some((loc, period)) = location.and_then(|l| Some((l, l.live_period?))) {
+        let live_location = LiveLocation {
+            location: Location {
+                lat: loc.latitude.to_string(),
+                lon: loc.longitude.to_string(),
+            },
+            keyboard: Some(keyboard.id),
+            suggested: None,
+            until: msg.date + Duration::seconds(period as i64),
+        };
+        live.start((msg.chat.id, UserId(id_num)), live_location, msg.date);
+    }
 
     dialogue
         .update(State::ReceiveStop {
             city,
             addr,
             stations,
+            nearby: Some(nearby),
         })
         .await?;
     Ok(())
@@ -354,16 +754,21 @@ Here are the nearby transit stations:",
 async fn receive_stop(
     bot: Bot,
     dialogue: MyDialogue,
-    (city, addr, stations): (String, String, Vec<Station>),
+    (city, addr, stations, nearby): (String, String, Vec<Station>, Option<NearbyQuery>),
     q: CallbackQuery,
+    api: Api,
+    storage: Storage,
+    live: LiveLocations,
 ) -> HandlerResult {
     if let Some(stop) = &q.data {
+        let prefs = get_user_prefs(&storage, &q.from);
+        let lang = prefs.lang;
         let chat_id = q.message.as_ref().unwrap().chat.id;
         let message_id = q.message.as_ref().unwrap().id;
         let null_kb = InlineKeyboardMarkup::default();
 
-        if stop.starts_with("<<") {
-            bot.edit_message_text(chat_id, message_id, "Ok, send me the new <b>street address</b> or a <b>location</b> 📍.")
+        if stop == CHANGE_ADDRESS {
+            bot.edit_message_text(chat_id, message_id, lang.tr("ask-new-address"))
                 .parse_mode(Html)
                 .reply_markup(null_kb.clone())
                 .await?;
@@ -374,79 +779,82 @@ async fn receive_stop(
             return Ok(());
         };
 
+        // Show more stations farther away in place of the current ones
+        if stop == MORE_STATIONS {
+            let user_data = storage.get_user_data(q.from.id.to_string()).unwrap_or_default();
+            // Around where a live location is now, or else the stored address
+            let location = match live.get((chat_id, q.from.id)) {
+                Some(live_location) => live_location.location,
+                None => Location {
+                    lat: user_data.lat,
+                    lon: user_data.lon,
+                },
+            };
+            let more = match nearby.and_then(|n| n.more()) {
+                // Only the stop is stored in the privacy mode, with nothing to search around
+                Some(more) if !location.lat.is_empty() => more,
+                _ => return Ok(()),
+            };
+            let found = api
+                .get_nearby_stations(location.lat, location.lon, &user_data.products, &more)
+                .await?;
+            // The button is gone once there is nothing more to find
+            let has_more = found.len() > stations.len() && more.more().is_some();
+            let stations = if found.len() >= stations.len() { found } else { stations };
+            bot.edit_message_reply_markup(chat_id, message_id)
+                .reply_markup(make_stations_keyboard(&stations, lang, &prefs.settings, has_more))
+                .await?;
+            dialogue
+                .update(State::ReceiveStop {
+                    city,
+                    addr,
+                    stations,
+                    nearby: Some(more),
+                })
+                .await?;
+            return Ok(());
+        }
+
+        // Keyboards of earlier searches may still have buttons of other stations
+        let station = match stop.strip_prefix(STATION).and_then(|id| stations.iter().find(|s| s.id == id)) {
+            Some(station) => station.clone(),
+            None => return Ok(()),
+        };
+        let stop_id = station.id.to_owned();
+        let stop = &station.name;
+
         // Remove buttons from last send msg
         bot.edit_message_reply_markup(chat_id, message_id)
             .reply_markup(null_kb)
             .await?;
 
-        let stop_id = stations
-            .iter()
-            .find(|&x| x.name.starts_with(stop))
-            .unwrap()
-            .id
-            .to_owned();
-
-        let departures = get_departures(stop_id.clone()).await?;
+        // Instead of the address, only the stop is stored in the stop only privacy mode
+        if prefs.settings.privacy == Privacy::StopOnly {
+            let mut user_data = storage.get_user_data(q.from.id.to_string()).unwrap_or_default();
+            user_data.id = q.from.id.to_string();
+            user_data.stop = Some(SavedStop {
+                id: stop_id.clone(),
+                name: station.name.clone(),
+            });
+            storage.store_user_data(user_data)?;
+        }
 
-        if departures.len() == 0 {
-            bot.send_message(
-                dialogue.chat_id(),
-                format!("😟 Unfortunately, no transit departures were found from this station at this time. Please /start over!")
-            ).await?;
+        let products = get_user_products(&storage, q.from.id);
+        let query = BoardQuery {
+            duration: Some(config::board_duration()),
+            results: config::board_results(),
+            ..Default::default()
+        };
+        let departures =
+            fetch_board(&api, &stop_id, BoardKind::Departures, &products, &query).await?;
+        if !send_board(&bot, chat_id, stop, BoardKind::Departures, &query, &departures, &prefs).await? {
+            bot.send_message(chat_id, lang.tr("no-departures-start"))
+                .await?;
 
             dialogue.exit().await?;
             return Ok(());
         }
 
-        let mut dep_names: Vec<String> = vec![];
-
-        // Format departure info
-        let mut departure_info = String::new();
-        for dep in departures.iter() {
-            let time = chrono::DateTime::parse_from_rfc3339(&dep.planned)
-                .unwrap()
-                .time()
-                .format("%H:%M");
-
-            // Add delay in minutes between parenthesis in the infos (+delay)
-            let delay = match dep.delay {
-                None => format!(""),
-                Some(del) => {
-                    if del == 0 {
-                        format!("")
-                    } else {
-                        format!(" (+{})", del / 60)
-                    }
-                }
-            };
-
-            departure_info = format!(
-                "{}\n--------------------\n<b>{}</b>, to <b>{}</b> on <b>{}</b>{}",
-                departure_info, dep.name, dep.direction, time, delay
-            );
-
-            // Add direction to departure names for buttons
-            let dep_name = format!("{} ({})", dep.name, dep.direction);
-            dep_names.push(dep_name);
-        }
-        let kb = make_inline_keyboard(dep_names.iter().map(|x| x.as_ref()).collect(), 2);
-
-        // Output departure info
-        bot.send_message(
-            dialogue.chat_id(),
-            format!(
-                "🚏 Infos for selected station (+ mins delay): <b>{}</b>\n{}\n--------------------",
-                stop, departure_info
-            ),
-        )
-        .parse_mode(Html)
-        .await?;
-
-        // Send buttons for the transit departures
-        bot.send_message(dialogue.chat_id(), "Select a transit:")
-            .reply_markup(kb)
-            .await?;
-
         dialogue
             .update(State::ReceiveTransit {
                 city,
@@ -454,33 +862,232 @@ async fn receive_stop(
                 stations,
                 stop: stop.to_string(),
                 stop_id,
+                board: BoardKind::Departures,
+                query,
             })
             .await?;
     }
     Ok(())
 }
 
+/// Follows the live location of a message, `Some` when it was just shared or moved far enough
+/// to search the stations again. Only these moves are charged to the throttle.
+fn follow_live_location(
+    msg: Message,
+    live: LiveLocations,
+    throttle: Throttle,
+    admins: Admins,
+) -> Option<LiveLocation> {
+    let (loc, user) = (msg.location()?, msg.from()?);
+    let key = (msg.chat.id, user.id);
+    let location = Location {
+        lat: loc.latitude.to_string(),
+        lon: loc.longitude.to_string(),
+    };
+    let now = msg.edit_date().copied().unwrap_or(msg.date);
+
+    match (msg.edit_date(), loc.live_period) {
+        // A live location that was just shared
+        (None, Some(period)) => {
+            let live_location = LiveLocation {
+                location,
+                keyboard: None,
+                suggested: None,
+                until: msg.date + Duration::seconds(period as i64),
+            };
+            live.start(key, live_location.clone(), now);
+            Some(live_location)
+        }
+        // The user didn't send the moves, so throttled ones are left out without a cooldown
+        (Some(_), Some(_)) => {
+            let live_location = live.moved(key, location, now)?;
+            (admins.contains(&user.id) || throttle.check(user.id).is_ok()).then_some(live_location)
+        }
+        // The user stopped sharing the location
+        _ => {
+            live.stop(key);
+            None
+        }
+    }
+}
+
+/// Handles a live location shared by the user. The keyboard of the stations near it is updated
+/// as the user moves, and during a tracking the nearest stop that still catches the transit is
+/// suggested.
+async fn receive_live_location(
+    queue: SendQueue,
+    dialogue: MyDialogue,
+    msg: Message,
+    live_location: Option<LiveLocation>,
+    api: Api,
+    storage: Storage,
+    live: LiveLocations,
+) -> HandlerResult {
+    let (live_location, user) = match (live_location, msg.from()) {
+        (Some(live_location), Some(user)) => (live_location, user),
+        _ => return Ok(()),
+    };
+    let bot = queue.bot();
+    let key = (msg.chat.id, user.id);
+    let now = msg.edit_date().copied().unwrap_or(msg.date);
+    let state = dialogue.get_or_default().await?;
+
+    let prefs = get_user_prefs(&storage, user);
+    let products = get_user_products(&storage, user.id);
+    match state {
+        State::ReceiveStop {
+            city,
+            addr,
+            stations,
+            nearby: Some(nearby),
+        } => {
+            let keyboard = match live_location.keyboard {
+                Some(keyboard) => keyboard,
+                None => return Ok(()),
+            };
+            let Location { lat, lon } = live_location.location;
+            let found = api.get_nearby_stations(lat, lon, &products, &nearby).await?;
+            // Telegram refuses edits that don't change anything
+            if found != stations {
+                let kb = make_stations_keyboard(&found, prefs.lang, &prefs.settings, nearby.more().is_some());
+                bot.edit_message_reply_markup(msg.chat.id, keyboard)
+                    .reply_markup(kb)
+                    .await?;
+            }
+            dialogue
+                .update(State::ReceiveStop {
+                    city,
+                    addr,
+                    stations: found,
+                    nearby: Some(nearby),
+                })
+                .await?;
+        }
+        State::ReceiveCancel {
+            stop_id,
+            board,
+            query,
+            transit,
+            trip_id,
+        } => {
+            let source = BoardSource {
+                api,
+                stop_id: stop_id.clone(),
+                board,
+                products,
+                query,
+            };
+            let deps = source.departures().await?;
+            let trip = match find_transit(&deps, &trip_id) {
+                Some(dep) => source.trip(dep).await?,
+                None => return Ok(()),
+            };
+            let speed = prefs.settings.walking_speed.unwrap_or_else(config::walking_speed);
+            let stopover = match nearest_catchable_stop(&trip, &live_location.location, now, speed) {
+                Some(stopover) => stopover,
+                None => return Ok(()),
+            };
+            // Each stop is suggested once, being nearest to the tracked stop again only resets it
+            if !live.suggest(key, &stopover.stop.id) || stopover.stop.id == stop_id {
+                return Ok(());
+            }
+            let walk = stopover.stop.walking_time(speed).unwrap_or_else(Duration::zero);
+            let time = stopover
+                .when()
+                .map(|t| format_time(t, config::timezone()))
+                .unwrap_or_default();
+            let args = [
+                ("stop", escape_name(&stopover.stop.name).into()),
+                ("transit", teloxide::utils::html::escape(&transit).into()),
+                ("distance", format_distance(stopover.stop.distance).into()),
+                ("minutes", walking_minutes(walk).into()),
+                ("time", time.into()),
+            ];
+            let request = bot
+                .send_message(msg.chat.id, prefs.lang.tr_args("closer-stop", &args))
+                .parse_mode(Html);
+            queue.send(msg.chat.id, request).await?;
+        }
+        _ => (),
+    }
+    Ok(())
+}
+
 async fn receive_transit(
     bot: Bot,
     dialogue: MyDialogue,
-    (city, addr, stations, stop, stop_id): (String, String, Vec<Station>, String, String),
+    (city, addr, stations, stop, stop_id, board, query): (
+        String,
+        String,
+        Vec<Station>,
+        String,
+        String,
+        BoardKind,
+        BoardQuery,
+    ),
     q: CallbackQuery,
+    api: Api,
+    storage: Storage,
 ) -> HandlerResult {
     if let Some(transit) = &q.data {
-        let kb = make_inline_keyboard(vec!["1", "2", "3"], 3);
+        let prefs = get_user_prefs(&storage, &q.from);
+        let lang = prefs.lang;
+        let chat_id = q.message.as_ref().unwrap().chat.id;
+        // Switch between the departures and arrivals board or page through the time windows
+        let page = match transit.as_str() {
+            TOGGLE_BOARD => Some((board.toggle(), query)),
+            EARLIER => Some((board, query.earlier(q.message.as_ref().unwrap().date))),
+            LATER => Some((board, query.later(q.message.as_ref().unwrap().date))),
+            _ => None,
+        };
+        if let Some((board, query)) = page {
+            let products = get_user_products(&storage, q.from.id);
+            let departures = fetch_board(&api, &stop_id, board, &products, &query).await?;
+            if send_board(&bot, chat_id, &stop, board, &query, &departures, &prefs).await? {
+                bot.delete_message(chat_id, q.message.unwrap().id)
+                    .await?;
+            } else {
+                send_empty_board(&bot, chat_id, board, lang).await?;
+            }
+
+            dialogue
+                .update(State::ReceiveTransit {
+                    city,
+                    addr,
+                    stations,
+                    stop,
+                    stop_id,
+                    board,
+                    query,
+                })
+                .await?;
+            return Ok(());
+        }
+
+        // The buttons hold the trip of the transit, its label is taken from the board again
+        let trip_id = match transit.strip_prefix(TRANSIT) {
+            Some(trip_id) => trip_id.to_string(),
+            None => return Ok(()),
+        };
+        let products = get_user_products(&storage, q.from.id);
+        let departures = fetch_board(&api, &stop_id, board, &products, &query).await?;
+        let transit = match find_transit(&departures, &trip_id) {
+            Some(dep) => transit_label(dep),
+            // The transit left the board in the meantime
+            None => return Ok(()),
+        };
+
+        let kb = make_plans_keyboard(&prefs.settings.intervals, lang);
 
         // Delete last update message
-        bot.delete_message(dialogue.chat_id(), q.message.unwrap().id)
+        bot.delete_message(chat_id, q.message.unwrap().id)
             .await?;
 
         // Send buttons for the timer update rate
-        bot.send_message(
-            dialogue.chat_id(),
-            "Select how many <b>minutes</b> between each update:",
-        )
-        .parse_mode(Html)
-        .reply_markup(kb)
-        .await?;
+        bot.send_message(chat_id, lang.tr("ask-minutes"))
+            .parse_mode(Html)
+            .reply_markup(kb)
+            .await?;
 
         dialogue
             .update(State::ReceiveMinutes {
@@ -489,234 +1096,1249 @@ async fn receive_transit(
                 stations,
                 stop,
                 stop_id,
-                transit: transit.to_owned(),
+                board,
+                query,
+                transit,
+                trip_id,
             })
             .await?;
     }
     Ok(())
 }
 
-async fn receive_minutes(
+async fn receive_board_time(
     bot: Bot,
     dialogue: MyDialogue,
-    (_city, _addr, _stations, stop, stop_id, transit): (
+    (city, addr, stations, stop, stop_id, board, query): (
+        String,
+        String,
+        Vec<Station>,
+        String,
+        String,
+        BoardKind,
+        BoardQuery,
+    ),
+    msg: Message,
+    api: Api,
+    storage: Storage,
+) -> HandlerResult {
+    let time = msg
+        .text()
+        .and_then(|t| NaiveTime::parse_from_str(t.trim(), "%H:%M").ok());
+    let user = match msg.from() {
+        Some(user) => user,
+        None => return Ok(()),
+    };
+    let prefs = get_user_prefs(&storage, user);
+    let lang = prefs.lang;
+    let time = match time {
+        Some(t) => t,
+        None => {
+            bot.send_message(msg.chat.id, lang.tr("invalid-time"))
+                .parse_mode(Html)
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let when = next_occurrence(time, msg.date, config::timezone());
+    let query = BoardQuery {
+        when: Some(when),
+        ..query
+    };
+    let products = get_user_products(&storage, user.id);
+    let departures = fetch_board(&api, &stop_id, board, &products, &query).await?;
+    if !send_board(&bot, msg.chat.id, &stop, board, &query, &departures, &prefs).await? {
+        send_empty_board(&bot, msg.chat.id, board, lang).await?;
+    }
+
+    dialogue
+        .update(State::ReceiveTransit {
+            city,
+            addr,
+            stations,
+            stop,
+            stop_id,
+            board,
+            query,
+        })
+        .await?;
+    Ok(())
+}
+
+/// Notification plan chosen with a button or sent as text, with who chose it and when.
+#[derive(Clone, Debug)]
+struct PlanChoice {
+    plan: NotifyPlan,
+    chat_id: ChatId,
+    user: User,
+    date: DateTime<Utc>,
+    /// Message of the plan buttons, deleted once a plan was chosen
+    keyboard_msg: Option<Message>,
+}
+
+impl PlanChoice {
+    fn from_callback(q: CallbackQuery) -> Option<PlanChoice> {
+        let msg = q.message?;
+        Some(PlanChoice {
+            plan: NotifyPlan::parse(q.data.as_deref()?)?,
+            chat_id: msg.chat.id,
+            user: q.from,
+            date: msg.date,
+            keyboard_msg: Some(msg),
+        })
+    }
+
+    fn from_message(msg: Message) -> Option<PlanChoice> {
+        Some(PlanChoice {
+            plan: NotifyPlan::parse(msg.text()?)?,
+            chat_id: msg.chat.id,
+            user: msg.from()?.clone(),
+            date: msg.date,
+            keyboard_msg: None,
+        })
+    }
+}
+
+async fn receive_minutes(
+    dialogue: MyDialogue,
+    (_city, _addr, stations, stop, stop_id, board, query, transit, trip_id): (
         String,
         String,
         Vec<Station>,
         String,
         String,
+        BoardKind,
+        BoardQuery,
+        String,
         String,
     ),
+    choice: PlanChoice,
+    tasks: MyTasksMap,
+    api: Api,
+    storage: Storage,
+    queue: SendQueue,
+) -> HandlerResult {
+    let bot = queue.bot().clone();
+    let chat_id = choice.chat_id;
+    let prefs = get_user_prefs(&storage, &choice.user);
+    let lang = prefs.lang;
+
+    // Each tracking polls the transport API, so users can only have a few at once
+    let max_trackings = config::max_trackings();
+    let trackings = tasks
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|((c, u), t)| *u == choice.user.id && *c != chat_id && !t.is_finished())
+        .count();
+    if trackings >= max_trackings {
+        let request = bot.send_message(
+            chat_id,
+            lang.tr_args("too-many-trackings", &[("max", max_trackings.into())]),
+        );
+        queue.send(chat_id, request).await?;
+        dialogue.exit().await?;
+        return Ok(());
+    }
+
+    let products = get_user_products(&storage, choice.user.id);
+    // The board may have been moved to another time, the tracking follows the transit from now
+    let query = query.tracking(choice.date);
+    let tracking_state = State::ReceiveCancel {
+        stop_id: stop_id.clone(),
+        board,
+        query,
+        transit: transit.clone(),
+        trip_id: trip_id.clone(),
+    };
+
+    // Estimated time to walk from the user's address to the stop
+    let walk_time = stations
+        .iter()
+        .find(|&x| x.id == stop_id)
+        .and_then(|x| {
+            x.walking_time(prefs.settings.walking_speed.unwrap_or_else(config::walking_speed))
+        });
+
+    let walk_info = match walk_time {
+        Some(w) => format!(
+            "\n{}",
+            lang.tr_args(
+                "walk-info",
+                &[("minutes", w.num_minutes().into()), ("stop", escape_name(&stop).into())]
+            )
+        ),
+        None => String::new(),
+    };
+
+    let plan_info = match &choice.plan {
+        NotifyPlan::Every(interval) => lang.tr_args("timer-info", &[("minutes", (*interval).into())]),
+        NotifyPlan::At(_) => {
+            lang.tr_args("plan-info", &[("minutes", choice.plan.to_string().into())])
+        }
+    };
+    if let Some(keyboard_msg) = &choice.keyboard_msg {
+        queue.send(chat_id, bot.delete_message(chat_id, keyboard_msg.id)).await?;
+    }
+    let request = bot
+        .send_message(chat_id, format!("{}{}", plan_info, walk_info))
+        .parse_mode(Html);
+    queue.send(chat_id, request).await?;
+
+    let clock = TokioClock::starting_at(choice.date);
+    let tracker = Tracker::new(
+        clock.clone(),
+        BoardSource {
+            api: api.clone(),
+            stop_id: stop_id.clone(),
+            board,
+            products,
+            query,
+        },
+        trip_id,
+        choice.plan,
+        walk_time,
+    );
+    let mut notifier = TelegramNotifier::new(
+        queue,
+        dialogue.clone(),
+        chat_id,
+        stop,
+        transit.clone(),
+        board,
+        lang,
+    )
+    .with_quiet_hours(prefs.settings.quiet_hours, clock)
+    .with_map(api);
+    // Members of a group are mentioned, so they know which tracking a message belongs to
+    if !chat_id.is_user() {
+        notifier = notifier.for_member(&choice.user);
+    }
+
+    // Spawn task for repeated updates, the notifier sends the events of the tracker
+    let task: JoinHandle<HandlerResult> = tokio::spawn(async move {
+        let (events, receiver) = tokio::sync::mpsc::unbounded_channel();
+        let (tracked, notified) = tokio::join!(tracker.run(events), notifier.run(receiver));
+        tracked.and(notified)
+    });
+
+    // Insert Task-handle in the HashMap with the associated user
+    // Msg could be put in the hashmap, to edit from the cancel fn
+    {
+        let mut t = tasks.lock().unwrap();
+        t.insert((chat_id, choice.user.id), task);
+    }
+
+    dialogue.update(tracking_state).await?;
+    Ok(())
+}
+
+async fn invalid_plan(bot: Bot, msg: Message, storage: Storage) -> HandlerResult {
+    let lang = get_user_lang(&storage, msg.from());
+    bot.send_message(
+        msg.chat.id,
+        lang.tr_args(
+            "invalid-plan",
+            &[
+                ("interval", NotifyPlan::MAX_INTERVAL.into()),
+                ("count", NotifyPlan::MAX_THRESHOLDS.into()),
+                ("minutes", NotifyPlan::MAX_MINUTES.into()),
+            ],
+        ),
+    )
+    .parse_mode(Html)
+    .await?;
+    Ok(())
+}
+
+async fn receive_cancel(
+    queue: SendQueue,
+    dialogue: MyDialogue,
     q: CallbackQuery,
     tasks: MyTasksMap,
+    storage: Storage,
 ) -> HandlerResult {
-    if let Some(update_time) = &q.data {
-        let dial = dialogue.clone();
-        // Time between each tracking update
-        let mut update_time = update_time.parse::<i64>().unwrap();
-        let msg = q.message.clone().unwrap();
-        let mut msg_clone = msg.clone();
-        let mut curr_loc_msg: Option<Message> = None;
-        let mut loc_msg: Option<Message> = None;
-        let transit_clone = transit.clone();
-
-        bot.send_message(
-            dialogue.chat_id(),
-            format!(
-                "The timer is going to update every <b>{}</b> minute(s)!",
-                update_time
-            ),
-        )
+    // Ignore buttons of old messages like the ones of the intervals
+    if q.data.as_deref() != Some(CANCEL) {
+        return Ok(());
+    }
+    let chat_id = q.message.as_ref().unwrap().chat.id;
+    let user = (chat_id, q.from.id);
+    {
+        let mut t = tasks.lock().unwrap();
+        if let Some(v) = t.get(&user) {
+            v.abort();
+            t.remove(&user);
+        }
+    }
+    let bot = queue.bot();
+    let null_kb = InlineKeyboardMarkup::default();
+    let request = bot
+        .edit_message_reply_markup(chat_id, q.message.as_ref().unwrap().id)
+        .reply_markup(null_kb);
+    queue.send(chat_id, request).await?;
+
+    let lang = get_user_lang(&storage, Some(&q.from));
+    queue.send(chat_id, bot.send_message(chat_id, lang.tr("cancelled"))).await?;
+    dialogue.exit().await?;
+    Ok(())
+}
+
+/// Sends all stopovers of the tracked transit's trip.
+async fn receive_trip(
+    queue: SendQueue,
+    (stop_id, board, query, _transit, trip_id): (String, BoardKind, BoardQuery, String, String),
+    q: CallbackQuery,
+    api: Api,
+    storage: Storage,
+) -> HandlerResult {
+    let lang = get_user_lang(&storage, Some(&q.from));
+    let chat_id = q.message.as_ref().unwrap().chat.id;
+    let products = get_user_products(&storage, q.from.id);
+    let deps = api.get_board(stop_id.clone(), board, &products, &query).await?;
+
+    let trip = match find_transit(&deps, &trip_id) {
+        Some(dep) => api.get_trip(dep.trip_id.clone(), dep.name.clone()).await.ok(),
+        _ => None,
+    };
+
+    match trip {
+        Some(trip) => {
+            let now = q.message.as_ref().map(|m| m.date).unwrap_or_else(Utc::now);
+            let request = queue
+                .bot()
+                .send_message(chat_id, format_trip(&trip, &stop_id, now, lang))
+                .parse_mode(Html);
+            queue.send(chat_id, request).await?;
+        }
+        None => {
+            let request = queue.bot().send_message(chat_id, lang.tr("no-trip"));
+            queue.send(chat_id, request).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Next occurrence of a time of the day in the timezone of the stations, today or tomorrow.
+/// Times skipped by the start of daylight saving time are moved forward by an hour.
+fn next_occurrence(time: NaiveTime, now: DateTime<Utc>, tz: Tz) -> DateTime<Utc> {
+    let local_now = now.with_timezone(&tz);
+    let mut when = local_now.date_naive().and_time(time);
+    if when < local_now.naive_local() {
+        when += Duration::days(1);
+    }
+    tz.from_local_datetime(&when)
+        .earliest()
+        .or_else(|| tz.from_local_datetime(&(when + Duration::hours(1))).earliest())
+        .map(|w| w.with_timezone(&Utc))
+        .unwrap_or(now)
+}
+
+/// Fetches the departures or arrivals board of a stop with only the given products.
+async fn fetch_board(
+    api: &Api,
+    stop_id: &str,
+    board: BoardKind,
+    products: &Products,
+    query: &BoardQuery,
+) -> Result<Vec<TransitDeparture>, Box<dyn Error + Send + Sync>> {
+    let departures = api
+        .get_board(stop_id.to_string(), board, products, query)
+        .await?;
+    Ok(departures
+        .into_iter()
+        .filter(|x| products.contains(&x.product))
+        .collect())
+}
+
+/// Sends the departures or arrivals board of a stop with buttons to select a transit.
+/// Returns `false` if the board is empty.
+async fn send_board(
+    bot: &Bot,
+    chat_id: ChatId,
+    stop: &str,
+    board: BoardKind,
+    query: &BoardQuery,
+    departures: &[TransitDeparture],
+    prefs: &UserPrefs,
+) -> Result<bool, Box<dyn Error + Send + Sync>> {
+    if departures.is_empty() {
+        return Ok(false);
+    }
+    let lang = prefs.lang;
+
+    let transits = transit_buttons(departures, TRANSIT);
+
+    let toggle = match board {
+        BoardKind::Departures => lang.tr("button-arrivals"),
+        BoardKind::Arrivals => lang.tr("button-departures"),
+    };
+    let mut kb_buttons: Vec<(String, &str)> =
+        transits.iter().map(|(label, data)| (label.clone(), data.as_str())).collect();
+    kb_buttons.push((lang.tr("button-earlier"), EARLIER));
+    kb_buttons.push((lang.tr("button-later"), LATER));
+    kb_buttons.push((toggle, TOGGLE_BOARD));
+    let kb = make_callback_keyboard(kb_buttons, prefs.settings.columns);
+
+    // Output departure info, long boards are split over several messages
+    for page in format_board(stop, board, query, departures, lang) {
+        bot.send_message(chat_id, page).parse_mode(Html).await?;
+    }
+
+    // Send buttons for the transit departures
+    bot.send_message(chat_id, lang.tr("select-transit"))
         .parse_mode(Html)
+        .reply_markup(kb)
         .await?;
 
-        let mut time_now = msg_clone.date.clone() - Duration::minutes(update_time);
-        let mut interval_timer =
-            tokio::time::interval(Duration::minutes(update_time).to_std().unwrap());
-
-        // Spawn task for repeated updates
-        let task: JoinHandle<HandlerResult> = tokio::spawn(async move {
-            loop {
-                interval_timer.tick().await;
-                // Add the difference to the time that passed since timer's tick
-                time_now += Duration::minutes(update_time);
-
-                // Fetch the list of departuring transits from the stop (station)
-                let deps = get_departures(stop_id.clone()).await?;
-
-                // Get the departure of selected transit considering direction the user has selected
-                let dep = match deps.iter().find(|&x| {
-                    // Extract name and direction from button to compare transit departures
-                    let first_parent = transit_clone.find("(").unwrap();
-                    let last_parent = transit_clone.len();
-                    let direction = transit_clone[first_parent + 1..last_parent - 1].to_string();
-                    let transit_name = transit_clone[0..first_parent - 1].to_string();
-
-                    &x.name == &transit_name && &x.direction == &direction
-                }) {
-                    Some(d) => d,
-                    None => break,
-                };
-
-                // Parse planned departure time
-                let mut dep_time = DateTime::parse_from_rfc3339(&dep.planned).unwrap();
-
-                // Add the delay if exists
-                if let Some(del) = dep.delay {
-                    dep_time += Duration::seconds(del);
-                }
-                // Calculate duration till departure
-                let dur = dep_time.signed_duration_since(time_now);
-
-                // When below update time, update interval timer to the duration time
-                if update_time != 1 && dur.num_minutes() > 0 && dur.num_minutes() < update_time {
-                    update_time = dur.num_minutes();
-                    interval_timer =
-                        tokio::time::interval(Duration::minutes(update_time).to_std().unwrap());
-                    // Tick the first time which is instant
-                    interval_timer.tick().await;
-                }
+    Ok(true)
+}
 
-                // Stop if transit already departured
-                if time_now > dep_time {
-                    break;
-                }
+async fn products(bot: Bot, dialogue: MyDialogue, msg: Message, storage: Storage) -> HandlerResult {
+    let user = match get_user_id(&msg) {
+        Some(user) => user,
+        None => return Ok(()),
+    };
+    let products = storage.get_user_data(user).unwrap_or_default().products;
+    let lang = get_user_lang(&storage, msg.from());
 
-                let kb = make_inline_keyboard(vec!["<< Cancel"], 1);
+    bot.send_message(msg.chat.id, lang.tr("products-select"))
+        .parse_mode(Html)
+        .reply_markup(make_products_keyboard(&products, lang))
+        .await?;
+    dialogue.update(State::ReceiveProducts).await?;
+    Ok(())
+}
 
-                // Delete last message including the msg of the update coming from the previous iteration
-                bot.delete_message(dial.chat_id(), msg_clone.id).await?;
+async fn receive_products(
+    bot: Bot,
+    dialogue: MyDialogue,
+    q: CallbackQuery,
+    storage: Storage,
+) -> HandlerResult {
+    if let Some(product) = &q.data {
+        let chat_id = q.message.as_ref().unwrap().chat.id;
+        let message_id = q.message.as_ref().unwrap().id;
+        let user = q.from.id.to_string();
+        let mut user_data = storage.get_user_data(user.clone()).unwrap_or_default();
+        let lang = get_user_lang(&storage, Some(&q.from));
 
-                // Delete location messages if it exists
-                if let Some(m) = &curr_loc_msg {
-                    bot.delete_message(dial.chat_id(), m.id).await?;
-                }
-                if let Some(m) = &loc_msg {
-                    bot.delete_message(dial.chat_id(), m.id).await?;
-                }
+        if product == DONE {
+            bot.edit_message_text(chat_id, message_id, lang.tr("products-saved"))
+                .reply_markup(InlineKeyboardMarkup::default())
+                .await?;
+            dialogue.exit().await?;
+            return Ok(());
+        }
 
-                // Send location of transit if current position information is provided
-                if let Some(pos) = &dep.curr_position {
-                    // unwrap() since Location.lat is a string that contains always a number
-                    curr_loc_msg = Some(
-                        bot.send_message(dial.chat_id(), "Current position of the transit:")
-                            .await?,
-                    );
-                    loc_msg = Some(
-                        bot.send_location(
-                            dial.chat_id(),
-                            pos.lat.parse::<f64>().unwrap(),
-                            pos.lon.parse::<f64>().unwrap(),
-                        )
-                        .await?,
-                    );
-                }
+        // The callback data of the buttons are the product names
+        let enabled = !user_data.products.get(product);
+        user_data.products.set(product, enabled);
 
-                if dur.num_minutes() == 0 {
-                    // Update timer when duration below 1 minute
-                    update_time = 1;
-                    interval_timer =
-                        tokio::time::interval(Duration::minutes(update_time).to_std().unwrap());
-                    interval_timer.tick().await;
-
-                    msg_clone = bot
-                        .send_message(
-                            dial.chat_id(),
-                            format!(
-                                "🔔 Your transit: <b>{}</b> 🚌 should arrive now!",
-                                &transit_clone
-                            ),
-                        )
-                        .parse_mode(Html)
-                        .reply_markup(kb.clone())
-                        .await?;
-                } else {
-                    msg_clone = bot
-                        .send_message(
-                            dial.chat_id(),
-                            format!(
-                                "🔔 Your transit: <b>{}</b> 🚌 arrives in <b>{}</b> minutes ⌛!",
-                                &transit_clone,
-                                dur.num_minutes()
-                            ),
-                        )
-                        .parse_mode(Html)
-                        .reply_markup(kb)
-                        .await?;
+        // Keep at least one product, otherwise no transits would be found at all
+        if !user_data.products.intersects(&Products::default()) {
+            bot.answer_callback_query(q.id.clone())
+                .text(lang.tr("products-keep-one"))
+                .await?;
+            return Ok(());
+        }
+
+        user_data.id = user;
+        let kb = make_products_keyboard(&user_data.products, lang);
+        storage.store_user_data(user_data)?;
+
+        bot.edit_message_reply_markup(chat_id, message_id)
+            .reply_markup(kb)
+            .await?;
+    }
+    Ok(())
+}
+
+async fn settings(bot: Bot, dialogue: MyDialogue, msg: Message, storage: Storage) -> HandlerResult {
+    let user_data = match get_user_id(&msg) {
+        Some(user) => storage.get_user_data(user).unwrap_or_default(),
+        None => return Ok(()),
+    };
+    let lang = get_user_lang(&storage, msg.from());
+
+    bot.send_message(msg.chat.id, lang.tr("settings-title"))
+        .parse_mode(Html)
+        .reply_markup(make_settings_keyboard(&user_data, lang))
+        .await?;
+    dialogue.update(State::ReceiveSettings).await?;
+    Ok(())
+}
+
+async fn receive_settings(
+    bot: Bot,
+    dialogue: MyDialogue,
+    q: CallbackQuery,
+    storage: Storage,
+) -> HandlerResult {
+    if let Some(setting) = &q.data {
+        let chat_id = q.message.as_ref().unwrap().chat.id;
+        let message_id = q.message.as_ref().unwrap().id;
+        let user = q.from.id.to_string();
+        let mut user_data = storage.get_user_data(user.clone()).unwrap_or_default();
+
+        if setting == DONE {
+            let lang = get_user_lang(&storage, Some(&q.from));
+            bot.edit_message_text(chat_id, message_id, lang.tr("settings-saved"))
+                .reply_markup(InlineKeyboardMarkup::default())
+                .await?;
+            dialogue.exit().await?;
+            return Ok(());
+        }
+
+        // Every button switches its setting to the next choice
+        match setting.as_str() {
+            SETTINGS_INTERVALS => user_data.settings.next_intervals(),
+            SETTINGS_COLUMNS => user_data.settings.next_columns(),
+            SETTINGS_QUIET_HOURS => user_data.settings.next_quiet_hours(),
+            SETTINGS_WALKING_SPEED => user_data.settings.next_walking_speed(),
+            SETTINGS_PRIVACY => user_data.settings.next_privacy(),
+            SETTINGS_LANGUAGE => {
+                user_data.language = match user_data.language {
+                    None => Some(Lang::En),
+                    Some(Lang::En) => Some(Lang::De),
+                    Some(Lang::De) => None,
                 }
             }
+            _ => return Ok(()),
+        }
 
-            // Delete last update message
-            bot.delete_message(dial.chat_id(), msg_clone.id).await?;
+        user_data.id = user;
+        // A stricter privacy mode also applies to the stored address
+        user_data.apply_privacy();
+        storage.store_user_data(user_data.clone())?;
 
-            bot.send_message(
-                dial.chat_id(),
-                format!(
-                    "🔔 Your transit: <b>{}</b> 🚌 is departuring from <b>{}</b>!",
-                    transit_clone, stop
-                ),
-            )
+        // The language may have changed, so the whole menu is sent again
+        let lang = get_user_lang(&storage, Some(&q.from));
+        bot.edit_message_text(chat_id, message_id, lang.tr("settings-title"))
             .parse_mode(Html)
+            .reply_markup(make_settings_keyboard(&user_data, lang))
+            .await?;
+    }
+    Ok(())
+}
+
+//////////////////////////////////////////////////////////
+// Stored data
+//////////////////////////////////////////////////////////
+/// Sends everything stored about the user as a JSON document.
+async fn my_data(bot: Bot, msg: Message, storage: Storage) -> HandlerResult {
+    let lang = get_user_lang(&storage, msg.from());
+    if !msg.chat.is_private() {
+        bot.send_message(msg.chat.id, lang.tr("private-only"))
             .await?;
+        return Ok(());
+    }
 
-            dial.exit().await?;
+    let user_id = match msg.from() {
+        Some(user) => user.id,
+        None => return Ok(()),
+    };
+    let user_data = match storage.get_user_data(user_id.to_string()) {
+        Ok(u) => u,
+        Err(_) => {
+            bot.send_message(msg.chat.id, lang.tr("no-data"))
+                .await?;
             return Ok(());
-        });
+        }
+    };
+    let json = serde_json::to_vec_pretty(&user_data)?;
+    let file = InputFile::memory(json).file_name(format!("wimt-data-{}.json", user_id));
+    bot.send_document(msg.chat.id, file)
+        .caption(lang.tr("my-data"))
+        .await?;
+    Ok(())
+}
+
+/// Deletes the stored data of the user and stops all of their trackings, in groups too.
+async fn forget(
+    bot: Bot,
+    msg: Message,
+    tasks: MyTasksMap,
+    dialogues: Arc<InMemStorage<State>>,
+    storage: Storage,
+    live: LiveLocations,
+) -> HandlerResult {
+    // The language is part of the deleted data
+    let lang = get_user_lang(&storage, msg.from());
+    if !msg.chat.is_private() {
+        bot.send_message(msg.chat.id, lang.tr("private-only"))
+            .await?;
+        return Ok(());
+    }
+
+    let user_id = match msg.from() {
+        Some(user) => user.id,
+        None => return Ok(()),
+    };
+    storage.delete_user_data(&user_id.to_string())?;
+
+    let chats: Vec<ChatId> = {
+        let mut t = tasks.lock().unwrap();
+        let keys: Vec<(ChatId, UserId)> = t.keys().filter(|(_, u)| *u == user_id).copied().collect();
+        for key in keys.iter() {
+            if let Some(task) = t.remove(key) {
+                task.abort();
+            }
+        }
+        keys.into_iter().map(|(chat_id, _)| chat_id).collect()
+    };
+    // Dialogues without a state were never stored, so exiting them fails
+    for chat_id in chats.into_iter().chain([msg.chat.id]) {
+        live.stop((chat_id, user_id));
+        MyDialogue::new(dialogues.clone(), member_dialogue_key(chat_id, user_id))
+            .exit()
+            .await
+            .ok();
+    }
+
+    bot.send_message(msg.chat.id, lang.tr("forgotten"))
+        .await?;
+    Ok(())
+}
 
-        // Insert Task-handle in the HashMap with the associated user
-        // Msg could be put in the hashmap, to edit from the cancel fn
-        {
-            let mut t = tasks.lock().unwrap();
-            let user = q.from.id.to_string();
-            t.insert(user, task);
+//////////////////////////////////////////////////////////
+// Group chats
+//////////////////////////////////////////////////////////
+/// Sends the shared departures board of the group's stop, where each member selects a transit
+/// to track on their own.
+async fn group_board(bot: Bot, msg: Message, api: Api, storage: Storage) -> HandlerResult {
+    let user = match msg.from() {
+        Some(user) => user,
+        None => return Ok(()),
+    };
+    let prefs = get_user_prefs(&storage, user);
+    let lang = prefs.lang;
+    if msg.chat.is_private() {
+        bot.send_message(msg.chat.id, lang.tr("group-only")).await?;
+        return Ok(());
+    }
+    let stop = match get_group_stop(&storage, msg.chat.id) {
+        Some(s) => s,
+        None => {
+            bot.send_message(msg.chat.id, lang.tr("no-group-stop")).await?;
+            return Ok(());
         }
+    };
+
+    let query = BoardQuery {
+        duration: Some(config::board_duration()),
+        results: config::board_results(),
+        ..Default::default()
+    };
+    let departures =
+        fetch_board(&api, &stop.id, BoardKind::Departures, &Products::default(), &query).await?;
+    if departures.is_empty() {
+        send_empty_board(&bot, msg.chat.id, BoardKind::Departures, lang).await?;
+        return Ok(());
+    }
+
+    // The buttons are told apart from the ones of the members' own dialogues by a prefix
+    let transits = transit_buttons(&departures, GROUP_BOARD);
+    let kb_buttons = transits
+        .iter()
+        .map(|(label, data)| (label.clone(), data.as_str()))
+        .collect();
+    let kb = make_callback_keyboard(kb_buttons, prefs.settings.columns);
 
-        dialogue.update(State::ReceiveCancel).await?;
+    for page in format_board(&stop.name, BoardKind::Departures, &query, &departures, lang) {
+        bot.send_message(msg.chat.id, page).parse_mode(Html).await?;
     }
+    bot.send_message(msg.chat.id, lang.tr("select-group-transit"))
+        .parse_mode(Html)
+        .reply_markup(kb)
+        .await?;
     Ok(())
 }
 
-async fn receive_cancel(
+/// Continues the dialogue of a member who selected a transit on the shared board.
+async fn receive_group_board(
     bot: Bot,
     dialogue: MyDialogue,
     q: CallbackQuery,
-    tasks: MyTasksMap,
+    api: Api,
+    storage: Storage,
 ) -> HandlerResult {
-    let user = q.from.id.to_string();
-    {
-        let mut t = tasks.lock().unwrap();
-        if let Some(v) = t.get(&user) {
-            v.abort();
-            t.remove(&user);
+    let chat_id = q.message.as_ref().unwrap().chat.id;
+    let trip_id = q.data.as_deref().unwrap_or_default().trim_start_matches(GROUP_BOARD).to_string();
+    let prefs = get_user_prefs(&storage, &q.from);
+    let lang = prefs.lang;
+    let stop = match get_group_stop(&storage, chat_id) {
+        Some(s) => s,
+        None => {
+            bot.send_message(chat_id, lang.tr("no-group-stop")).await?;
+            return Ok(());
         }
+    };
+    let query = BoardQuery {
+        duration: Some(config::board_duration()),
+        results: config::board_results(),
+        ..Default::default()
+    };
+
+    // The buttons hold the trip of the transit, its label is taken from the board again
+    let departures =
+        fetch_board(&api, &stop.id, BoardKind::Departures, &Products::default(), &query).await?;
+    let transit = match find_transit(&departures, &trip_id) {
+        Some(dep) => transit_label(dep),
+        // The transit left the board in the meantime
+        None => return Ok(()),
+    };
+
+    bot.send_message(chat_id, format!("{}: {}", mention(&q.from), lang.tr("ask-minutes")))
+        .parse_mode(Html)
+        .reply_markup(make_plans_keyboard(&prefs.settings.intervals, lang))
+        .await?;
+
+    // The address of the member is only used for the walking time, which needs the nearby stations
+    let user_data = storage.get_user_data(q.from.id.to_string()).unwrap_or_default();
+    dialogue
+        .update(State::ReceiveMinutes {
+            city: user_data.city,
+            addr: user_data.addr,
+            stations: vec![],
+            stop: stop.name,
+            stop_id: stop.id,
+            board: BoardKind::Departures,
+            query,
+            transit,
+            trip_id,
+        })
+        .await?;
+    Ok(())
+}
+
+/// Sets the stop of the group to the best match of a name like `Alexanderplatz`.
+async fn set_stop(bot: Bot, msg: Message, name: String, api: Api, storage: Storage) -> HandlerResult {
+    let lang = get_user_lang(&storage, msg.from());
+    if !check_group_admin(&bot, &msg, lang).await? {
+        return Ok(());
     }
-    let null_kb = InlineKeyboardMarkup::default();
-    bot.edit_message_reply_markup(dialogue.chat_id(), q.message.as_ref().unwrap().id)
-        .reply_markup(null_kb)
+    if name.trim().is_empty() {
+        bot.send_message(msg.chat.id, lang.tr("set-stop-usage"))
+            .parse_mode(Html)
+            .await?;
+        return Ok(());
+    }
+
+    match api.search_stations(name.trim()).await?.into_iter().next() {
+        Some(station) => {
+            let group = msg.chat.id.to_string();
+            let mut group_data = storage.get_user_data(group.clone()).unwrap_or_default();
+            group_data.id = group;
+            group_data.group_stop = Some(SavedStop {
+                id: station.id,
+                name: station.name.clone(),
+            });
+            storage.store_user_data(group_data)?;
+
+            bot.send_message(
+                msg.chat.id,
+                lang.tr_args("group-stop-saved", &[("stop", escape_name(&station.name).into())]),
+            )
+            .parse_mode(Html)
+            .await?;
+        }
+        None => {
+            bot.send_message(msg.chat.id, lang.tr("stop-not-found"))
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+async fn clear_stop(bot: Bot, msg: Message, storage: Storage) -> HandlerResult {
+    let lang = get_user_lang(&storage, msg.from());
+    if !check_group_admin(&bot, &msg, lang).await? {
+        return Ok(());
+    }
+
+    let group = msg.chat.id.to_string();
+    if let Ok(mut group_data) = storage.get_user_data(group) {
+        group_data.group_stop = None;
+        storage.store_user_data(group_data)?;
+    }
+    bot.send_message(msg.chat.id, lang.tr("group-stop-cleared"))
         .await?;
+    Ok(())
+}
+
+/// Whether the sender is an admin of the group, otherwise tells them why the command is refused.
+async fn check_group_admin(
+    bot: &Bot,
+    msg: &Message,
+    lang: Lang,
+) -> Result<bool, Box<dyn Error + Send + Sync>> {
+    let info = if msg.chat.is_private() {
+        "group-only"
+    } else {
+        // Without a sender, e.g. in channel posts, nobody can be an admin
+        if let Some(user) = msg.from() {
+            let member = bot.get_chat_member(msg.chat.id, user.id).await?;
+            if member.is_privileged() {
+                return Ok(true);
+            }
+        }
+        "admin-only"
+    };
+    bot.send_message(msg.chat.id, lang.tr(info)).await?;
+    Ok(false)
+}
 
+/// Stop of the group set by its admins.
+fn get_group_stop(storage: &Storage, chat_id: ChatId) -> Option<SavedStop> {
+    storage
+        .get_user_data(chat_id.to_string())
+        .ok()
+        .and_then(|g| g.group_stop)
+}
+
+/// Link to a user, which notifies them in groups.
+fn mention(user: &User) -> String {
+    teloxide::utils::html::user_mention(
+        user.id.0 as i64,
+        &teloxide::utils::html::escape(&user.first_name),
+    )
+}
+
+//////////////////////////////////////////////////////////
+// Inline mode
+//////////////////////////////////////////////////////////
+/// Maximum number of stations answering an inline query
+const INLINE_STATIONS: usize = 5;
+/// Maximum number of departures on each board of an inline result
+const INLINE_DEPARTURES: usize = 10;
+
+/// Answers `@wimt_bot <station>` with the departure boards of the matching stations, or of the
+/// stations near the stored address or the stored stop if no station was given.
+async fn inline_query(bot: Bot, q: InlineQuery, api: Api, storage: Storage) -> HandlerResult {
+    let lang = get_user_lang(&storage, Some(&q.from));
+    let user_data = storage.get_user_data(q.from.id.to_string()).unwrap_or_default();
+
+    let mut stations = if !q.query.trim().is_empty() {
+        api.search_stations(q.query.trim()).await?
+    } else if !user_data.lat.is_empty() {
+        api.get_nearby_stations(user_data.lat, user_data.lon, &user_data.products, &config::nearby_query())
+            .await?
+    } else if let Some(stop) = &user_data.stop {
+        vec![stop.station()]
+    } else {
+        vec![]
+    };
+    stations.truncate(INLINE_STATIONS);
+
+    // Fetch the boards of all stations at once, inline queries have to be answered quickly
+    let query = BoardQuery {
+        duration: Some(config::board_duration()),
+        ..Default::default()
+    };
+    let boards: Vec<_> = stations
+        .iter()
+        .map(|station| {
+            let (api, stop_id, products) = (api.clone(), station.id.clone(), user_data.products);
+            tokio::spawn(async move {
+                fetch_board(&api, &stop_id, BoardKind::Departures, &products, &query).await
+            })
+        })
+        .collect();
+
+    let mut results = vec![];
+    for (station, board) in stations.iter().zip(boards) {
+        let mut departures = match board.await? {
+            Ok(d) => d,
+            Err(e) => {
+                log::warn!("Unable to fetch the departures of {}: {}", station.id, e);
+                continue;
+            }
+        };
+        departures.truncate(INLINE_DEPARTURES);
+        let article = make_board_article(station, &query, &departures, lang);
+        results.push(InlineQueryResult::Article(article));
+    }
+
+    // The results depend on the products and the language of the user
+    bot.answer_inline_query(q.id, results)
+        .is_personal(true)
+        .cache_time(30)
+        .await?;
+    Ok(())
+}
+
+/// Creates the inline result of the departure board of a station, the next departures as its
+/// description.
+fn make_board_article(
+    station: &Station,
+    query: &BoardQuery,
+    departures: &[TransitDeparture],
+    lang: Lang,
+) -> InlineQueryResultArticle {
+    let (text, description) = if departures.is_empty() {
+        (lang.tr("no-departures"), lang.tr("no-departures"))
+    } else {
+        let tz = config::timezone();
+        let next: Vec<String> = departures
+            .iter()
+            .take(3)
+            .map(|dep| {
+                let time = dep.when.as_deref().unwrap_or(&dep.planned);
+                format!("{} {} → {}", format_time(time, tz), dep.name, dep.direction)
+            })
+            .collect();
+        (
+            // Inline results have a single message, which holds the first page
+            format_board(&station.name, BoardKind::Departures, query, departures, lang)
+                .into_iter()
+                .next()
+                .unwrap_or_default(),
+            next.join(" · "),
+        )
+    };
+
+    InlineQueryResultArticle::new(
+        station.id.clone(),
+        station.name.clone(),
+        InputMessageContent::Text(InputMessageContentText::new(text).parse_mode(Html)),
+    )
+    .description(description)
+}
+
+//////////////////////////////////////////////////////////
+// Operators
+//////////////////////////////////////////////////////////
+/// Pause between the messages of a broadcast, on top of the limits of the send queue
+const BROADCAST_DELAY: std::time::Duration = std::time::Duration::from_millis(50);
+
+/// Shows how many users and groups are stored, the active trackings and the API error rates.
+async fn stats(bot: Bot, msg: Message, tasks: MyTasksMap, api: Api, storage: Storage) -> HandlerResult {
+    let lang = get_user_lang(&storage, msg.from());
+    let records = storage.all_user_data()?;
+    let groups = records.iter().filter(|u| u.id.starts_with('-')).count();
+    let trackings = tasks
+        .lock()
+        .unwrap()
+        .values()
+        .filter(|t| !t.is_finished())
+        .count();
+    let rate = |stats: &RequestStats| format!("{:.1}", stats.error_rate());
+
+    let text = lang.tr_args(
+        "admin-stats",
+        &[
+            ("users", (records.len() - groups).into()),
+            ("groups", groups.into()),
+            ("trackings", trackings.into()),
+            ("transport-requests", api.stats.transport.requests().into()),
+            ("transport-errors", rate(&api.stats.transport).into()),
+            ("locationiq-requests", api.stats.locationiq.requests().into()),
+            ("locationiq-errors", rate(&api.stats.locationiq).into()),
+        ],
+    );
+    bot.send_message(msg.chat.id, text)
+        .parse_mode(Html)
+        .await?;
+    Ok(())
+}
+
+/// Sends a message to all stored users in the background, one after another to leave room for
+/// the messages of the trackings, and reports to how many of them it was sent.
+async fn broadcast(
+    bot: Bot,
+    msg: Message,
+    text: String,
+    storage: Storage,
+    queue: SendQueue,
+) -> HandlerResult {
+    let lang = get_user_lang(&storage, msg.from());
+    let text = text.trim().to_string();
+    if text.is_empty() {
+        bot.send_message(msg.chat.id, lang.tr("admin-broadcast-usage"))
+            .parse_mode(Html)
+            .await?;
+        return Ok(());
+    }
+
+    // Groups are stored with their negative chat ids
+    let chats: Vec<ChatId> = storage
+        .all_user_data()?
+        .iter()
+        .filter_map(|u| u.id.parse::<i64>().ok())
+        .map(ChatId)
+        .filter(|c| c.is_user())
+        .collect();
     bot.send_message(
-        dialogue.chat_id(),
-        "🚫 Cancelled! You can start over using /start!",
+        msg.chat.id,
+        lang.tr_args("admin-broadcast-started", &[("count", chats.len().into())]),
     )
     .await?;
-    dialogue.exit().await?;
+
+    let admin_chat = msg.chat.id;
+    tokio::spawn(async move {
+        let mut sent = 0;
+        for chat_id in chats.iter() {
+            match queue.send(*chat_id, bot.send_message(*chat_id, &text)).await {
+                Ok(_) => sent += 1,
+                Err(e) => log::warn!("Broadcast to {} failed: {}", chat_id, e),
+            }
+            tokio::time::sleep(BROADCAST_DELAY).await;
+        }
+        let args = [("sent", sent.into()), ("count", chats.len().into())];
+        bot.send_message(admin_chat, lang.tr_args("admin-broadcast-done", &args))
+            .await?;
+        HandlerResult::Ok(())
+    });
     Ok(())
 }
 
-fn get_user_id(msg: &Message) -> String {
-    let user_id = match &msg.kind {
-        MessageKind::Common(MessageCommon { from, .. }) => Some(from.as_ref().unwrap().id),
-        _ => None,
+/// Lists the active trackings with a button to cancel each of them.
+async fn trackings(bot: Bot, msg: Message, tasks: MyTasksMap, storage: Storage) -> HandlerResult {
+    let lang = get_user_lang(&storage, msg.from());
+    let mut active: Vec<(ChatId, UserId)> = tasks
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(_, t)| !t.is_finished())
+        .map(|(key, _)| *key)
+        .collect();
+    active.sort_by_key(|(chat_id, user_id)| (chat_id.0, user_id.0));
+
+    if active.is_empty() {
+        bot.send_message(msg.chat.id, lang.tr("admin-no-trackings"))
+            .await?;
+        return Ok(());
+    }
+
+    let data: Vec<String> = active
+        .iter()
+        .map(|(chat_id, user_id)| format!("{}{}:{}", ADMIN_CANCEL, chat_id, user_id))
+        .collect();
+    let buttons = active
+        .iter()
+        .zip(data.iter())
+        .map(|((chat_id, user_id), data)| {
+            let args = [("chat", chat_id.to_string().into()), ("user", user_id.to_string().into())];
+            (lang.tr_args("button-cancel-tracking", &args), data.as_str())
+        })
+        .collect();
+
+    bot.send_message(
+        msg.chat.id,
+        lang.tr_args("admin-trackings", &[("count", active.len().into())]),
+    )
+    .parse_mode(Html)
+    .reply_markup(make_callback_keyboard(buttons, 1))
+    .await?;
+    Ok(())
+}
+
+/// Tracking that an operator cancels with a button of /trackings.
+#[derive(Clone)]
+struct AdminCancel {
+    chat_id: ChatId,
+    user_id: UserId,
+}
+
+impl AdminCancel {
+    fn from_callback(q: CallbackQuery) -> Option<AdminCancel> {
+        let data = q.data?;
+        let (chat_id, user_id) = data.strip_prefix(ADMIN_CANCEL)?.split_once(':')?;
+        Some(AdminCancel {
+            chat_id: ChatId(chat_id.parse().ok()?),
+            user_id: UserId(user_id.parse().ok()?),
+        })
+    }
+}
+
+/// Cancels a tracking of any user, who is told about it and starts over.
+async fn receive_admin_cancel(
+    bot: Bot,
+    q: CallbackQuery,
+    cancel: AdminCancel,
+    tasks: MyTasksMap,
+    dialogues: Arc<InMemStorage<State>>,
+    storage: Storage,
+) -> HandlerResult {
+    let lang = get_user_lang(&storage, Some(&q.from));
+    let admin_chat = q.message.as_ref().unwrap().chat.id;
+    let task = tasks
+        .lock()
+        .unwrap()
+        .remove(&(cancel.chat_id, cancel.user_id))
+        .filter(|t| !t.is_finished());
+
+    let task = match task {
+        Some(t) => t,
+        None => {
+            bot.send_message(admin_chat, lang.tr("admin-tracking-gone"))
+                .await?;
+            return Ok(());
+        }
+    };
+    task.abort();
+    // The dialogue may be gone already, e.g. after a /forget
+    MyDialogue::new(dialogues, member_dialogue_key(cancel.chat_id, cancel.user_id))
+        .exit()
+        .await
+        .ok();
+
+    let user_lang = storage
+        .get_user_data(cancel.user_id.to_string())
+        .ok()
+        .and_then(|u| u.language)
+        .unwrap_or_default();
+    bot.send_message(cancel.chat_id, user_lang.tr("cancelled-by-admin"))
+        .await?;
+    bot.send_message(admin_chat, lang.tr("admin-tracking-cancelled"))
+        .await?;
+    Ok(())
+}
+
+/// Shows the stored data of a user with a button to delete it.
+async fn user_info(bot: Bot, msg: Message, id: String, storage: Storage) -> HandlerResult {
+    let lang = get_user_lang(&storage, msg.from());
+    let id = id.trim().to_string();
+    if id.is_empty() {
+        bot.send_message(msg.chat.id, lang.tr("admin-user-usage"))
+            .parse_mode(Html)
+            .await?;
+        return Ok(());
+    }
+
+    let user_data = match storage.get_user_data(id.clone()) {
+        Ok(u) => u,
+        Err(_) => {
+            bot.send_message(msg.chat.id, lang.tr("admin-user-not-found"))
+                .await?;
+            return Ok(());
+        }
     };
+    let json = serde_json::to_string_pretty(&user_data)?;
+    let text = format!(
+        "{}\n<pre>{}</pre>",
+        lang.tr_args("admin-user", &[("id", id.clone().into())]),
+        teloxide::utils::html::escape(&json)
+    );
+    let data = format!("{}{}", ADMIN_DELETE, id);
+    let keyboard = make_callback_keyboard(vec![(lang.tr("button-delete-user"), &data)], 1);
+
+    bot.send_message(msg.chat.id, text)
+        .parse_mode(Html)
+        .reply_markup(keyboard)
+        .await?;
+    Ok(())
+}
+
+/// Stored data of a user that an operator deletes with the button of /user.
+#[derive(Clone)]
+struct AdminDelete(String);
 
-    let UserId(id_num) = user_id.unwrap();
-    id_num.to_string()
+impl AdminDelete {
+    fn from_callback(q: CallbackQuery) -> Option<AdminDelete> {
+        Some(AdminDelete(q.data?.strip_prefix(ADMIN_DELETE)?.to_string()))
+    }
+}
+
+async fn receive_admin_delete(
+    bot: Bot,
+    q: CallbackQuery,
+    AdminDelete(id): AdminDelete,
+    storage: Storage,
+) -> HandlerResult {
+    let lang = get_user_lang(&storage, Some(&q.from));
+    let message = q.message.as_ref().unwrap();
+    bot.edit_message_reply_markup(message.chat.id, message.id)
+        .reply_markup(InlineKeyboardMarkup::default())
+        .await?;
+
+    let info = match storage.delete_user_data(&id)? {
+        true => "admin-user-deleted",
+        false => "admin-user-not-found",
+    };
+    bot.send_message(message.chat.id, lang.tr(info))
+        .await?;
+    Ok(())
+}
+
+/// Products of the user, all products if nothing was stored yet.
+fn get_user_products(storage: &Storage, user_id: UserId) -> Products {
+    storage
+        .get_user_data(user_id.to_string())
+        .map(|u| u.products)
+        .unwrap_or_default()
+}
+
+/// Language of the user, the one chosen with /language or else the one of the Telegram app.
+fn get_user_lang(storage: &Storage, user: Option<&User>) -> Lang {
+    match user {
+        Some(u) => get_user_prefs(storage, u).lang,
+        None => Lang::default(),
+    }
+}
+
+/// Language and settings of a user, read together for the handlers that need both.
+struct UserPrefs {
+    lang: Lang,
+    settings: Settings,
+}
+
+fn get_user_prefs(storage: &Storage, user: &User) -> UserPrefs {
+    let data = storage.get_user_data(user.id.to_string()).unwrap_or_default();
+    UserPrefs {
+        lang: data
+            .language
+            .unwrap_or_else(|| Lang::from_code(user.language_code.as_deref())),
+        settings: data.settings,
+    }
+}
+
+async fn send_empty_board(bot: &Bot, chat_id: ChatId, board: BoardKind, lang: Lang) -> HandlerResult {
+    let info = match board {
+        BoardKind::Departures => "no-departures",
+        BoardKind::Arrivals => "no-arrivals",
+    };
+    bot.send_message(chat_id, lang.tr(info)).await?;
+    Ok(())
+}
+
+/// Id of the sender of a message as stored, `None` for channel posts.
+fn get_user_id(msg: &Message) -> Option<String> {
+    msg.from().map(|user| user.id.to_string())
 }
 
 //////////////////////////////////////////////////////////
 // Keyboards
 //////////////////////////////////////////////////////////
+// Callback data of the control buttons, their labels depend on the language
+const CHANGE_ADDRESS: &str = "change_address";
+const MORE_STATIONS: &str = "more_stations";
+const STATION: &str = "station:";
+const EARLIER: &str = "earlier";
+const LATER: &str = "later";
+const TOGGLE_BOARD: &str = "toggle_board";
+const TRIP: &str = "trip";
+const CANCEL: &str = "cancel";
+const DONE: &str = "done";
+// Prefixes of the callback data of the transits of the boards, followed by their trip
+const TRANSIT: &str = "transit:";
+const GROUP_BOARD: &str = "board:";
+/// Bytes of callback data Telegram accepts at most
+const CALLBACK_DATA_LIMIT: usize = 64;
+// Plans offered to notify at some minutes before the transit arrives
+const NOTIFY_PLANS: [&str; 2] = ["10, 5, 2", "15, 10, 5"];
+// Prefixes of the callback data of the operator buttons
+const ADMIN_CANCEL: &str = "admin_cancel:";
+const ADMIN_DELETE: &str = "admin_delete:";
+const SETTINGS_INTERVALS: &str = "settings_intervals";
+const SETTINGS_COLUMNS: &str = "settings_columns";
+const SETTINGS_LANGUAGE: &str = "settings_language";
+const SETTINGS_QUIET_HOURS: &str = "settings_quiet_hours";
+const SETTINGS_WALKING_SPEED: &str = "settings_walking_speed";
+const SETTINGS_PRIVACY: &str = "settings_privacy";
+
 /// Creates a keyboard made by buttons in a big column.
 fn make_inline_keyboard(list: Vec<&str>, chunks: usize) -> InlineKeyboardMarkup {
     let mut keyboard: Vec<Vec<InlineKeyboardButton>> = vec![];
@@ -733,6 +2355,117 @@ fn make_inline_keyboard(list: Vec<&str>, chunks: usize) -> InlineKeyboardMarkup
     InlineKeyboardMarkup::new(keyboard)
 }
 
+/// Creates a keyboard of buttons with a label and a different callback data.
+fn make_callback_keyboard(list: Vec<(String, &str)>, chunks: usize) -> InlineKeyboardMarkup {
+    let mut keyboard: Vec<Vec<InlineKeyboardButton>> = vec![];
+
+    for values in list.chunks(chunks) {
+        let row = values
+            .iter()
+            .map(|(label, data)| InlineKeyboardButton::callback(label.to_owned(), data.to_string()))
+            .collect();
+
+        keyboard.push(row);
+    }
+
+    InlineKeyboardMarkup::new(keyboard)
+}
+
+/// Labels and callback data of the buttons of the transits of a board, the data is the prefix
+/// and the trip of the transit. Transits whose trip is unknown or too long can't be selected.
+fn transit_buttons(departures: &[TransitDeparture], prefix: &str) -> Vec<(String, String)> {
+    departures
+        .iter()
+        .map(|dep| (transit_label(dep), format!("{}{}", prefix, dep.trip_id)))
+        .filter(|(_, data)| data.len() > prefix.len() && data.len() <= CALLBACK_DATA_LIMIT)
+        .collect()
+}
+
+/// Creates a keyboard of the nearby stations with their distance, walking time and products,
+/// a button to show more stations and one to change the address.
+fn make_stations_keyboard(stations: &[Station], lang: Lang, settings: &Settings, more: bool) -> InlineKeyboardMarkup {
+    let speed = settings.walking_speed.unwrap_or_else(config::walking_speed);
+    let data: Vec<String> = stations.iter().map(|s| format!("{}{}", STATION, s.id)).collect();
+    let mut buttons: Vec<(String, &str)> = stations
+        .iter()
+        .zip(data.iter())
+        .map(|(s, d)| (format_station(s, speed, lang), d.as_str()))
+        .collect();
+    if more {
+        buttons.push((lang.tr("button-more-stations"), MORE_STATIONS));
+    }
+    buttons.push((lang.tr("button-change-address"), CHANGE_ADDRESS));
+    make_callback_keyboard(buttons, settings.columns)
+}
+
+/// Creates a keyboard to toggle each product on or off.
+fn make_products_keyboard(products: &Products, lang: Lang) -> InlineKeyboardMarkup {
+    let mut buttons: Vec<(String, &str)> = Products::NAMES
+        .iter()
+        .map(|&name| {
+            let state = if products.get(name) { "✅" } else { "❌" };
+            let label = lang.tr(&format!("product-{}", name));
+            (format!("{} {}", state, label), name)
+        })
+        .collect();
+
+    buttons.push((lang.tr("button-done"), DONE));
+    make_callback_keyboard(buttons, 2)
+}
+
+/// Creates a keyboard of the intervals and of the plans to notify at some minutes before.
+fn make_plans_keyboard(intervals: &[i64], lang: Lang) -> InlineKeyboardMarkup {
+    let intervals: Vec<String> = intervals.iter().map(|i| i.to_string()).collect();
+    let mut kb = make_inline_keyboard(intervals.iter().map(|i| i.as_str()).collect(), intervals.len());
+
+    for plan in NOTIFY_PLANS {
+        kb = kb.append_row(vec![InlineKeyboardButton::callback(
+            lang.tr_args("button-plan", &[("minutes", plan.into())]),
+            plan,
+        )]);
+    }
+    kb
+}
+
+/// Creates the settings menu with a button showing the current choice of each setting.
+fn make_settings_keyboard(user_data: &UserData, lang: Lang) -> InlineKeyboardMarkup {
+    let settings = &user_data.settings;
+    let intervals = settings
+        .intervals
+        .iter()
+        .map(|i| i.to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let language = match user_data.language {
+        Some(l) => lang.tr(&format!("language-{}", l.code())),
+        None => lang.tr("language-auto"),
+    };
+    let quiet_hours = match settings.quiet_hours {
+        Some(h) => format!("{:02}:00–{:02}:00", h.from, h.until),
+        None => lang.tr("quiet-hours-off"),
+    };
+    let walking_speed = settings.walking_speed.unwrap_or_else(config::walking_speed);
+    let privacy = match settings.privacy {
+        Privacy::Off => lang.tr("privacy-off"),
+        Privacy::Rounded => lang.tr("privacy-rounded"),
+        Privacy::StopOnly => lang.tr("privacy-stop-only"),
+    };
+
+    let buttons = vec![
+        (lang.tr_args("settings-intervals", &[("intervals", intervals.into())]), SETTINGS_INTERVALS),
+        (lang.tr_args("settings-columns", &[("columns", settings.columns.into())]), SETTINGS_COLUMNS),
+        (lang.tr_args("settings-language", &[("language", language.into())]), SETTINGS_LANGUAGE),
+        (lang.tr_args("settings-quiet-hours", &[("hours", quiet_hours.into())]), SETTINGS_QUIET_HOURS),
+        (
+            lang.tr_args("settings-walking-speed", &[("speed", format!("{:.1}", walking_speed).into())]),
+            SETTINGS_WALKING_SPEED,
+        ),
+        (lang.tr_args("settings-privacy", &[("privacy", privacy.into())]), SETTINGS_PRIVACY),
+        (lang.tr("button-done"), DONE),
+    ];
+    make_callback_keyboard(buttons, 1)
+}
+
 /// Creates a keyboard made by buttons in a big column.
 fn _make_keyboard(list: Vec<&str>, chunks: usize) -> KeyboardMarkup {
     let mut keyboard: Vec<Vec<KeyboardButton>> = vec![];
@@ -740,7 +2473,7 @@ fn _make_keyboard(list: Vec<&str>, chunks: usize) -> KeyboardMarkup {
     for values in list.chunks(chunks) {
         let row = values
             .iter()
-            .map(|&value| KeyboardButton::new(format!("{}", value)))
+            .map(|&value| KeyboardButton::new(value.to_string()))
             .collect();
 
         keyboard.push(row);
@@ -755,7 +2488,7 @@ fn _make_reply_keyboard(list: Vec<&str>, chunks: usize) -> ReplyMarkup {
     for values in list.chunks(chunks) {
         let row = values
             .iter()
-            .map(|&value| KeyboardButton::new(format!("{}", value)))
+            .map(|&value| KeyboardButton::new(value.to_string()))
             .collect();
 
         keyboard.push(row);
@@ -0,0 +1,194 @@
+use std::{error::Error, f64::consts::PI, io::Cursor};
+
+use image::{imageops, ImageOutputFormat, Rgba, RgbaImage};
+
+use crate::{api::Api, structs::Location};
+
+//////////////////////////////////////////////////////////
+// Map rendering
+//////////////////////////////////////////////////////////
+pub const MAP_WIDTH: u32 = 640;
+pub const MAP_HEIGHT: u32 = 480;
+/// Size of the tiles of the tile server and of the grid of the schematic maps
+const TILE_SIZE: u32 = 256;
+/// Space in pixels between the points and the border of the map
+const PADDING: f64 = 48.0;
+/// Zoom of the maps that show only the vehicle, and the closest zoom of all maps
+const MAX_ZOOM: u32 = 16;
+
+const BACKGROUND: Rgba<u8> = Rgba([242, 239, 233, 255]);
+const GRID: Rgba<u8> = Rgba([225, 221, 212, 255]);
+const OUTLINE: Rgba<u8> = Rgba([255, 255, 255, 255]);
+const ROUTE: Rgba<u8> = Rgba([30, 136, 229, 255]);
+const STOP: Rgba<u8> = Rgba([229, 57, 53, 255]);
+const VEHICLE: Rgba<u8> = Rgba([33, 33, 33, 255]);
+
+/// Renders a map of the vehicle and its remaining route as PNG. The last point of the route
+/// is the tracked stop. The background is made of the tiles of `MAP_TILE_URL`, tiles that can't
+/// be fetched are left schematic.
+pub async fn render_map(
+    api: &Api,
+    vehicle: &Location,
+    route: &[Location],
+) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+    let vehicle = vehicle.coordinates().ok_or("Invalid position of the vehicle!")?;
+    let route: Vec<(f64, f64)> = route.iter().filter_map(Location::coordinates).collect();
+    let view = View::fit(&[&[vehicle], route.as_slice()].concat());
+
+    let mut canvas = RgbaImage::from_pixel(MAP_WIDTH, MAP_HEIGHT, BACKGROUND);
+    for (x, y, tile) in view.tiles() {
+        let (left, top) = (
+            (x as f64 * TILE_SIZE as f64 - view.left) as i64,
+            (y as f64 * TILE_SIZE as f64 - view.top) as i64,
+        );
+        let tile = match api.tile_url.is_some() {
+            true => api.get_tile(view.zoom, tile.0, tile.1).await,
+            false => Err("No tile server!".into()),
+        };
+        match tile.and_then(|t| Ok(image::load_from_memory(&t)?.into_rgba8())) {
+            Ok(tile) => imageops::overlay(&mut canvas, &tile, left, top),
+            Err(e) => {
+                if api.tile_url.is_some() {
+                    log::warn!("Unable to fetch a map tile: {}", e);
+                }
+                draw_grid(&mut canvas, left, top);
+            }
+        }
+    }
+
+    // The route runs from the vehicle through the remaining stops to the tracked stop
+    let points: Vec<(f64, f64)> = [&[vehicle], route.as_slice()]
+        .concat()
+        .into_iter()
+        .map(|p| view.pixel(p))
+        .collect();
+    for line in points.windows(2) {
+        draw_line(&mut canvas, line[0], line[1], 5.0, OUTLINE);
+    }
+    for line in points.windows(2) {
+        draw_line(&mut canvas, line[0], line[1], 3.0, ROUTE);
+    }
+    if let Some(stop) = route.last() {
+        draw_marker(&mut canvas, view.pixel(*stop), STOP);
+    }
+    draw_marker(&mut canvas, points[0], VEHICLE);
+
+    let mut png = vec![];
+    image::DynamicImage::ImageRgba8(canvas).write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)?;
+    Ok(png)
+}
+
+/// Pixel of a coordinate on the Web Mercator world map of the zoom, as used by the tile servers.
+fn world_pixel((lat, lon): (f64, f64), zoom: u32) -> (f64, f64) {
+    let size = TILE_SIZE as f64 * 2f64.powi(zoom as i32);
+    let lat = lat.clamp(-85.0511, 85.0511).to_radians();
+    let x = (lon + 180.0) / 360.0 * size;
+    let y = (1.0 - (lat.tan() + 1.0 / lat.cos()).ln() / PI) / 2.0 * size;
+    (x, y)
+}
+
+/// Part of the world map shown on a map.
+struct View {
+    zoom: u32,
+    /// World pixel of the top left corner
+    left: f64,
+    top: f64,
+}
+
+impl View {
+    /// Closest zoom that shows all points, centered on them.
+    fn fit(points: &[(f64, f64)]) -> View {
+        let bounds = |zoom| {
+            let pixels: Vec<(f64, f64)> = points.iter().map(|&p| world_pixel(p, zoom)).collect();
+            let min_x = pixels.iter().map(|p| p.0).fold(f64::INFINITY, f64::min);
+            let max_x = pixels.iter().map(|p| p.0).fold(f64::NEG_INFINITY, f64::max);
+            let min_y = pixels.iter().map(|p| p.1).fold(f64::INFINITY, f64::min);
+            let max_y = pixels.iter().map(|p| p.1).fold(f64::NEG_INFINITY, f64::max);
+            (min_x, max_x, min_y, max_y)
+        };
+        let fits = |zoom| {
+            let (min_x, max_x, min_y, max_y) = bounds(zoom);
+            max_x - min_x <= MAP_WIDTH as f64 - 2.0 * PADDING
+                && max_y - min_y <= MAP_HEIGHT as f64 - 2.0 * PADDING
+        };
+
+        let zoom = (0..=MAX_ZOOM).rev().find(|&z| fits(z)).unwrap_or(0);
+        let (min_x, max_x, min_y, max_y) = bounds(zoom);
+        View {
+            zoom,
+            left: (min_x + max_x - MAP_WIDTH as f64) / 2.0,
+            top: (min_y + max_y - MAP_HEIGHT as f64) / 2.0,
+        }
+    }
+
+    /// Pixel of a coordinate on the map.
+    fn pixel(&self, point: (f64, f64)) -> (f64, f64) {
+        let (x, y) = world_pixel(point, self.zoom);
+        (x - self.left, y - self.top)
+    }
+
+    /// Tiles covering the map: their position in the grid of the zoom and the tile to fetch
+    /// for it, which wraps around the antimeridian. Tiles beyond the poles are left out.
+    fn tiles(&self) -> Vec<(i64, i64, (u32, u32))> {
+        let count = 2i64.pow(self.zoom);
+        let size = TILE_SIZE as f64;
+        let (first_x, last_x) = (
+            (self.left / size).floor() as i64,
+            ((self.left + MAP_WIDTH as f64) / size).floor() as i64,
+        );
+        let (first_y, last_y) = (
+            (self.top / size).floor().max(0.0) as i64,
+            ((self.top + MAP_HEIGHT as f64) / size).floor().min(count as f64 - 1.0) as i64,
+        );
+        let mut tiles = vec![];
+        for y in first_y..=last_y {
+            for x in first_x..=last_x {
+                tiles.push((x, y, (x.rem_euclid(count) as u32, y as u32)));
+            }
+        }
+        tiles
+    }
+}
+
+/// Draws the border of a schematic tile at the given position.
+fn draw_grid(canvas: &mut RgbaImage, left: i64, top: i64) {
+    for i in 0..TILE_SIZE as i64 {
+        put_pixel(canvas, left + i, top, GRID);
+        put_pixel(canvas, left, top + i, GRID);
+    }
+}
+
+/// Draws a line with round ends between two pixels.
+fn draw_line(canvas: &mut RgbaImage, from: (f64, f64), to: (f64, f64), radius: f64, color: Rgba<u8>) {
+    let steps = (to.0 - from.0).hypot(to.1 - from.1).ceil().max(1.0) as usize;
+    for step in 0..=steps {
+        let t = step as f64 / steps as f64;
+        let point = (from.0 + (to.0 - from.0) * t, from.1 + (to.1 - from.1) * t);
+        draw_disc(canvas, point, radius, color);
+    }
+}
+
+/// Draws a dot with a white border.
+fn draw_marker(canvas: &mut RgbaImage, center: (f64, f64), color: Rgba<u8>) {
+    draw_disc(canvas, center, 11.0, OUTLINE);
+    draw_disc(canvas, center, 8.0, color);
+}
+
+fn draw_disc(canvas: &mut RgbaImage, (cx, cy): (f64, f64), radius: f64, color: Rgba<u8>) {
+    let r = radius.ceil() as i64;
+    let (x0, y0) = (cx.round() as i64, cy.round() as i64);
+    for y in y0 - r..=y0 + r {
+        for x in x0 - r..=x0 + r {
+            if (x as f64 - cx).hypot(y as f64 - cy) <= radius {
+                put_pixel(canvas, x, y, color);
+            }
+        }
+    }
+}
+
+/// Colors a pixel, if it is on the canvas.
+fn put_pixel(canvas: &mut RgbaImage, x: i64, y: i64, color: Rgba<u8>) {
+    if (0..canvas.width() as i64).contains(&x) && (0..canvas.height() as i64).contains(&y) {
+        canvas.put_pixel(x as u32, y as u32, color);
+    }
+}
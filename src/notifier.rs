@@ -0,0 +1,332 @@
+use chrono::Timelike;
+use fluent_bundle::FluentValue;
+use teloxide::{
+    payloads::{SendLocationSetters, SendPhotoSetters},
+    types::{InputFile, InputMedia, InputMediaPhoto},
+    utils::html::escape,
+    RequestError,
+};
+use tokio::sync::mpsc::UnboundedReceiver;
+
+use crate::*;
+
+//////////////////////////////////////////////////////////
+// Telegram notifier
+//////////////////////////////////////////////////////////
+/// Sends the events of a tracking to the chat of a dialogue.
+pub(crate) struct TelegramNotifier {
+    bot: Bot,
+    queue: SendQueue,
+    dialogue: MyDialogue,
+    chat_id: ChatId,
+    stop: String,
+    /// Name and direction of the tracked transit
+    transit: String,
+    board: BoardKind,
+    lang: Lang,
+    /// Mention of the member of a group the tracking belongs to
+    mention: Option<String>,
+    /// Last countdown message, replaced on every countdown
+    countdown_msg: Option<Message>,
+    /// Messages showing the current position of the transit
+    position_msgs: Vec<Message>,
+    /// API to render the maps of the transit with, the position is sent as location without it
+    map_api: Option<Api>,
+    /// Map of the transit, edited on every position update
+    map_msg: Option<Message>,
+    /// Hours in which messages are sent silently, with the clock of the tracking
+    quiet_hours: Option<(QuietHours, TokioClock)>,
+}
+
+impl TelegramNotifier {
+    pub(crate) fn new(
+        queue: SendQueue,
+        dialogue: MyDialogue,
+        chat_id: ChatId,
+        stop: String,
+        transit: String,
+        board: BoardKind,
+        lang: Lang,
+    ) -> Self {
+        TelegramNotifier {
+            bot: queue.bot().clone(),
+            queue,
+            dialogue,
+            chat_id,
+            stop,
+            transit,
+            board,
+            lang,
+            mention: None,
+            countdown_msg: None,
+            position_msgs: vec![],
+            map_api: None,
+            map_msg: None,
+            quiet_hours: None,
+        }
+    }
+
+    /// Sends the messages without a notification sound during the quiet hours of the user.
+    pub(crate) fn with_quiet_hours(mut self, hours: Option<QuietHours>, clock: TokioClock) -> Self {
+        self.quiet_hours = hours.map(|h| (h, clock));
+        self
+    }
+
+    /// Shows the position of the transit on a map with its route, instead of a location.
+    pub(crate) fn with_map(mut self, api: Api) -> Self {
+        self.map_api = Some(api);
+        self
+    }
+
+    /// Mentions the member in front of every message, for the trackings in groups.
+    pub(crate) fn for_member(mut self, user: &User) -> Self {
+        self.mention = Some(mention(user));
+        self
+    }
+
+    fn mentioned(&self, text: String) -> String {
+        match &self.mention {
+            Some(mention) => format!("{}: {}", mention, text),
+            None => text,
+        }
+    }
+
+    fn is_quiet(&self) -> bool {
+        match &self.quiet_hours {
+            Some((hours, clock)) => {
+                hours.contains(clock.now().with_timezone(&config::timezone()).hour())
+            }
+            None => false,
+        }
+    }
+
+    /// Notifies the chat of all events until the tracking is done, then exits the dialogue.
+    /// A message that can't be sent doesn't end the tracking, unless the chat can't be reached.
+    /// On shutdown the tracking ends early and tells the chat so.
+    pub(crate) async fn run(mut self, mut events: UnboundedReceiver<Event>) -> HandlerResult {
+        let queue = self.queue.clone();
+        loop {
+            let event = tokio::select! {
+                event = events.recv() => event,
+                _ = queue.closed() => {
+                    self.stop().await;
+                    self.dialogue.exit().await?;
+                    break;
+                }
+            };
+            let Some(event) = event else { break };
+            let done = event.is_final();
+            if let Err(e) = self.notify(event).await {
+                if e.downcast_ref::<RequestError>().is_some_and(is_unreachable) {
+                    self.dialogue.exit().await?;
+                    return Err(e);
+                }
+                log::warn!("Unable to notify {} of the tracking: {}", self.chat_id, e);
+            }
+            if done {
+                self.dialogue.exit().await?;
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    async fn notify(&mut self, event: Event) -> HandlerResult {
+        let chat_id = self.chat_id;
+        let lang = self.lang;
+        let quiet = self.is_quiet();
+        let transit = ("transit", FluentValue::from(escape(&self.transit)));
+        let stop = ("stop", FluentValue::from(escape_name(&self.stop)));
+        match event {
+            Event::Leave { minutes } => {
+                let text = lang.tr_args("leave-now", &[transit, stop, ("minutes", minutes.into())]);
+                let request = self
+                    .bot
+                    .send_message(chat_id, self.mentioned(text))
+                    .parse_mode(Html)
+                    .disable_notification(quiet);
+                self.queue.send(chat_id, request).await?;
+            }
+            Event::Countdown { minutes, leave_in } => {
+                let leave_info = match leave_in {
+                    Some(l) => format!("\n{}", lang.tr_args("leave-in", &[("minutes", l.into())])),
+                    None => String::new(),
+                };
+                let text = lang.tr_args("countdown", &[transit, ("minutes", minutes.into())]);
+                self.send_countdown(format!("{}{}", text, leave_info))
+                    .await?;
+            }
+            Event::ArrivingNow => {
+                let text = lang.tr_args("arriving-now", &[transit]);
+                self.send_countdown(text).await?;
+            }
+            Event::Delayed { minutes } => {
+                let text = match minutes {
+                    0 => lang.tr_args("on-time", &[transit]),
+                    m if m > 0 => lang.tr_args("delayed", &[transit, ("minutes", m.into())]),
+                    m => lang.tr_args("early", &[transit, ("minutes", (-m).into())]),
+                };
+                let request = self
+                    .bot
+                    .send_message(chat_id, self.mentioned(text))
+                    .parse_mode(Html)
+                    .disable_notification(quiet);
+                self.queue.send(chat_id, request).await?;
+            }
+            // The position is shown again with the next update, so it is skipped first
+            Event::PositionUpdate { .. } if self.queue.is_congested(chat_id) => {
+                log::info!("Skipping a position update of the congested chat {}", chat_id);
+            }
+            Event::PositionUpdate { position, route } => {
+                for m in std::mem::take(&mut self.position_msgs) {
+                    self.delete_message(m).await;
+                }
+                let map = match &self.map_api {
+                    Some(api) => render_map(api, &position, &route)
+                        .await
+                        .map_err(|e| log::warn!("Unable to render the map of {}: {}", self.transit, e))
+                        .ok(),
+                    None => None,
+                };
+                if let Some(png) = map {
+                    return self.send_map(png).await;
+                }
+
+                let request = self
+                    .bot
+                    .send_message(chat_id, self.mentioned(lang.tr("position-info")))
+                    .parse_mode(Html)
+                    .disable_notification(quiet);
+                self.position_msgs.push(self.queue.send(chat_id, request).await?);
+                // unwrap() since Location.lat is a string that contains always a number
+                let request = self
+                    .bot
+                    .send_location(
+                        chat_id,
+                        position.lat.parse::<f64>().unwrap(),
+                        position.lon.parse::<f64>().unwrap(),
+                    )
+                    .disable_notification(quiet);
+                self.position_msgs.push(self.queue.send(chat_id, request).await?);
+            }
+            Event::Departed | Event::Vanished => {
+                // Delete last update message
+                self.delete_countdown().await;
+
+                let status = match self.board {
+                    BoardKind::Departures => "departing",
+                    BoardKind::Arrivals => "arriving",
+                };
+                let request = self
+                    .bot
+                    .send_message(chat_id, self.mentioned(lang.tr_args(status, &[transit, stop])))
+                    .parse_mode(Html)
+                    .disable_notification(quiet);
+                self.queue.send(chat_id, request).await?;
+            }
+            Event::Cancelled => {
+                self.delete_countdown().await;
+                let request = self
+                    .bot
+                    .send_message(chat_id, self.mentioned(lang.tr_args("transit-cancelled", &[transit])))
+                    .parse_mode(Html)
+                    .disable_notification(quiet);
+                self.queue.send(chat_id, request).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Replaces the last countdown message by a new one.
+    async fn send_countdown(&mut self, text: String) -> HandlerResult {
+        let chat_id = self.chat_id;
+        let kb = make_callback_keyboard(
+            vec![
+                (self.lang.tr("button-trip"), TRIP),
+                (self.lang.tr("button-cancel"), CANCEL),
+            ],
+            2,
+        );
+
+        self.delete_countdown().await;
+        let request = self
+            .bot
+            .send_message(chat_id, self.mentioned(text))
+            .parse_mode(Html)
+            .reply_markup(kb)
+            .disable_notification(self.is_quiet());
+        self.countdown_msg = Some(self.queue.send(chat_id, request).await?);
+        Ok(())
+    }
+
+    /// Replaces the image of the map message, or sends a new one.
+    async fn send_map(&mut self, png: Vec<u8>) -> HandlerResult {
+        let chat_id = self.chat_id;
+        let mut caption = self.mentioned(self.lang.tr("position-info"));
+        if self.map_api.as_ref().is_some_and(|api| api.tile_url.is_some()) {
+            caption = format!("{}\n{}", caption, escape(&config::map_attribution()));
+        }
+        let photo = InputFile::memory(png).file_name("map.png");
+
+        if let Some(msg) = &self.map_msg {
+            let media = InputMediaPhoto::new(photo.clone())
+                .caption(caption.clone())
+                .parse_mode(Html);
+            let request = self.bot.edit_message_media(chat_id, msg.id, InputMedia::Photo(media));
+            match self.queue.send(chat_id, request).await {
+                Ok(_) => return Ok(()),
+                Err(e) if is_unreachable(&e) => return Err(e.into()),
+                // The user may have deleted the map, so a new one is sent
+                Err(e) => log::warn!("Unable to update the map in {}: {}", chat_id, e),
+            }
+        }
+        let request = self
+            .bot
+            .send_photo(chat_id, photo)
+            .caption(caption)
+            .parse_mode(Html)
+            .disable_notification(self.is_quiet());
+        self.map_msg = Some(self.queue.send(chat_id, request).await?);
+        Ok(())
+    }
+
+    /// Tells the chat that the tracking ended with the shutdown, in the countdown message if
+    /// there is one.
+    async fn stop(&mut self) {
+        let chat_id = self.chat_id;
+        let transit = ("transit", FluentValue::from(escape(&self.transit)));
+        let text = self.mentioned(self.lang.tr_args("tracking-stopped", &[transit]));
+        let result = match self.countdown_msg.take() {
+            // Without a reply markup the buttons of the countdown are removed
+            Some(msg) => {
+                let request = self.bot.edit_message_text(chat_id, msg.id, text).parse_mode(Html);
+                self.queue.send(chat_id, request).await.map(|_| ())
+            }
+            None => {
+                let request = self
+                    .bot
+                    .send_message(chat_id, text)
+                    .parse_mode(Html)
+                    .disable_notification(true);
+                self.queue.send(chat_id, request).await.map(|_| ())
+            }
+        };
+        if let Err(e) = result {
+            log::warn!("Unable to tell {} that the tracking stopped: {}", chat_id, e);
+        }
+    }
+
+    async fn delete_countdown(&mut self) {
+        if let Some(msg) = self.countdown_msg.take() {
+            self.delete_message(msg).await;
+        }
+    }
+
+    /// Deletes a message of the tracking, which the user may have deleted already.
+    async fn delete_message(&self, msg: Message) {
+        let request = self.bot.delete_message(self.chat_id, msg.id);
+        if let Err(e) = self.queue.send(self.chat_id, request).await {
+            log::warn!("Unable to delete a message of the tracking in {}: {}", self.chat_id, e);
+        }
+    }
+}
@@ -0,0 +1,179 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use teloxide::{
+    requests::{Output, Request},
+    types::ChatId,
+    ApiError, Bot, RequestError,
+};
+use tokio::{
+    sync::watch,
+    time::{Duration, Instant},
+};
+
+//////////////////////////////////////////////////////////
+// Send queue
+//////////////////////////////////////////////////////////
+/// Attempts of a request that Telegram asks to retry later
+const MAX_ATTEMPTS: u32 = 3;
+/// Delay of the next free slot of a chat above which messages that can be skipped are skipped
+const CONGESTION: Duration = Duration::from_secs(10);
+/// Number of chats above which the slots of idle chats are dropped
+const MAX_CHATS: usize = 10_000;
+
+/// Minimum time between two requests to the same chat and between any two requests.
+#[derive(Clone, Copy, Debug)]
+pub struct SendLimits {
+    pub private_chat: Duration,
+    pub group_chat: Duration,
+    pub global: Duration,
+}
+
+impl Default for SendLimits {
+    /// Limits of Telegram: a message per second in a chat, 20 per minute in a group and
+    /// 30 per second overall.
+    fn default() -> Self {
+        SendLimits {
+            private_chat: Duration::from_secs(1),
+            group_chat: Duration::from_secs(3),
+            global: Duration::from_millis(34),
+        }
+    }
+}
+
+/// Queue of the requests to Telegram, each one waits for the next free slot of its chat and of
+/// the bot. Requests that Telegram asks to retry later are sent again after the given time.
+/// Closing the queue on shutdown tells the trackings to stop, it still sends their last messages.
+#[derive(Clone, Debug)]
+pub struct SendQueue {
+    bot: Bot,
+    limits: SendLimits,
+    slots: Arc<Mutex<Slots>>,
+    close: Arc<watch::Sender<bool>>,
+    closed: watch::Receiver<bool>,
+}
+
+/// Next free slots of the bot and of each chat.
+#[derive(Debug)]
+struct Slots {
+    global: Instant,
+    chats: HashMap<ChatId, Instant>,
+}
+
+impl SendQueue {
+    pub fn new(bot: Bot, limits: SendLimits) -> Self {
+        let (close, closed) = watch::channel(false);
+        SendQueue {
+            bot,
+            limits,
+            slots: Arc::new(Mutex::new(Slots {
+                global: Instant::now(),
+                chats: HashMap::new(),
+            })),
+            close: Arc::new(close),
+            closed,
+        }
+    }
+
+    /// Bot to create the requests with.
+    pub fn bot(&self) -> &Bot {
+        &self.bot
+    }
+
+    /// Sends a request in the next free slot of the chat.
+    pub async fn send<R>(&self, chat_id: ChatId, request: R) -> Result<Output<R>, RequestError>
+    where
+        R: Request<Err = RequestError>,
+    {
+        let mut attempt = 1;
+        loop {
+            tokio::time::sleep_until(self.reserve_chat(chat_id)).await;
+            tokio::time::sleep_until(self.reserve_global()).await;
+            match request.send_ref().await {
+                Err(RequestError::RetryAfter(wait)) if attempt < MAX_ATTEMPTS => {
+                    log::warn!("Telegram asks to wait {:?} before sending to {}", wait, chat_id);
+                    self.delay(chat_id, wait);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Tells everyone waiting in `closed()` that the bot shuts down.
+    pub fn close(&self) {
+        self.close.send_replace(true);
+    }
+
+    /// Waits until the queue is closed.
+    pub async fn closed(&self) {
+        let mut closed = self.closed.clone();
+        while !*closed.borrow_and_update() {
+            // The sender lives as long as the queue
+            if closed.changed().await.is_err() {
+                std::future::pending::<()>().await;
+            }
+        }
+    }
+
+    /// Whether the requests to the chat are piling up, so that skippable messages are skipped.
+    pub fn is_congested(&self, chat_id: ChatId) -> bool {
+        let slots = self.slots.lock().unwrap();
+        slots
+            .chats
+            .get(&chat_id)
+            .is_some_and(|next| next.saturating_duration_since(Instant::now()) > CONGESTION)
+    }
+
+    /// Reserves the next free slot of the chat, in the order of the calls.
+    pub(crate) fn reserve_chat(&self, chat_id: ChatId) -> Instant {
+        let now = Instant::now();
+        let mut slots = self.slots.lock().unwrap();
+        if slots.chats.len() > MAX_CHATS {
+            slots.chats.retain(|_, next| *next > now);
+        }
+
+        let at = slots.chats.get(&chat_id).copied().unwrap_or(now).max(now);
+        let interval = match chat_id.is_user() {
+            true => self.limits.private_chat,
+            false => self.limits.group_chat,
+        };
+        slots.chats.insert(chat_id, at + interval);
+        at
+    }
+
+    /// Reserves the next free slot of the bot, only once the slot of the chat has come, so that
+    /// a busy chat doesn't hold up the others.
+    pub(crate) fn reserve_global(&self) -> Instant {
+        let mut slots = self.slots.lock().unwrap();
+        let at = slots.global.max(Instant::now());
+        slots.global = at + self.limits.global;
+        at
+    }
+
+    /// Moves the next free slot of the chat to after the time Telegram asks to wait.
+    fn delay(&self, chat_id: ChatId, wait: Duration) {
+        let until = Instant::now() + wait;
+        let mut slots = self.slots.lock().unwrap();
+        let next = slots.chats.entry(chat_id).or_insert(until);
+        *next = (*next).max(until);
+    }
+}
+
+/// Whether the bot can't send anything to the chat anymore, e.g. because the user blocked it.
+pub fn is_unreachable(error: &RequestError) -> bool {
+    matches!(
+        error,
+        RequestError::Api(
+            ApiError::BotBlocked
+                | ApiError::BotKicked
+                | ApiError::BotKickedFromSupergroup
+                | ApiError::ChatNotFound
+                | ApiError::GroupDeactivated
+                | ApiError::UserDeactivated
+                | ApiError::CantInitiateConversation
+        )
+    )
+}
@@ -1,5 +1,8 @@
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
 use serde::{Deserialize, Serialize};
 
+use crate::i18n::Lang;
+
 #[derive(Clone, Debug, Serialize, Deserialize, Default)]
 pub struct UserData {
     pub id: String,
@@ -7,30 +10,524 @@ pub struct UserData {
     pub addr: String,
     pub lat: String,
     pub lon: String,
+    /// Preferred products to filter stations and departures
+    #[serde(default)]
+    pub products: Products,
+    /// Language chosen with /language, the one of the Telegram app if not set
+    #[serde(default)]
+    pub language: Option<Lang>,
+    /// Preferences edited with /settings
+    #[serde(default)]
+    pub settings: Settings,
+    /// Default stop of a group chat set by its admins, only on the records of groups
+    #[serde(default)]
+    pub group_stop: Option<SavedStop>,
+    /// Last selected stop, only stored instead of the address in the stop only privacy mode
+    #[serde(default)]
+    pub stop: Option<SavedStop>,
+    /// When the user last started a dialogue, their location is purged after the retention period
+    #[serde(default)]
+    pub last_active: Option<DateTime<Utc>>,
 }
 
-#[derive(Debug, Clone, Default)]
+impl UserData {
+    /// Removes what the privacy mode of the user doesn't allow to store.
+    pub fn apply_privacy(&mut self) {
+        match self.settings.privacy {
+            Privacy::Off => (),
+            Privacy::Rounded => {
+                self.addr.clear();
+                self.lat = round_coordinate(&self.lat);
+                self.lon = round_coordinate(&self.lon);
+            }
+            Privacy::StopOnly => {
+                self.addr.clear();
+                self.lat.clear();
+                self.lon.clear();
+            }
+        }
+    }
+
+    /// Removes the address, coordinates and stop while keeping the preferences.
+    pub fn forget_location(&mut self) {
+        self.addr.clear();
+        self.lat.clear();
+        self.lon.clear();
+        self.stop = None;
+    }
+
+    pub fn has_location(&self) -> bool {
+        !self.lat.is_empty() || self.stop.is_some()
+    }
+}
+
+/// Coordinate rounded to 3 decimals, about 100 m, empty if it isn't a number.
+fn round_coordinate(coordinate: &str) -> String {
+    coordinate
+        .parse::<f64>()
+        .map(|c| format!("{:.3}", c))
+        .unwrap_or_default()
+}
+
+/// Stop saved by its id and name, like the stop of a group chat.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SavedStop {
+    pub id: String,
+    pub name: String,
+}
+
+impl SavedStop {
+    /// Station of the stop, with an unknown distance and all products.
+    pub fn station(&self) -> Station {
+        Station {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            distance: -1,
+            ..Default::default()
+        }
+    }
+}
+
+/// How much of the location of a user is stored.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Privacy {
+    /// The address and its exact coordinates
+    #[default]
+    Off,
+    /// Only the city and coordinates rounded to about 100 m
+    Rounded,
+    /// Only the city and the last selected stop
+    StopOnly,
+}
+
+/// Preferences of a user, each one cycles through its choices in the /settings menu.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Minutes between each tracking update offered after selecting a transit
+    pub intervals: Vec<i64>,
+    /// Number of buttons in each row of the stations and transits keyboards
+    pub columns: usize,
+    /// Walking speed in km/h, the configured one if not set
+    pub walking_speed: Option<f64>,
+    /// Hours in which tracking updates are sent without a notification sound
+    pub quiet_hours: Option<QuietHours>,
+    /// How much of the address is stored
+    pub privacy: Privacy,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            intervals: vec![1, 2, 3],
+            columns: 2,
+            walking_speed: None,
+            quiet_hours: None,
+            privacy: Privacy::Off,
+        }
+    }
+}
+
+impl Settings {
+    pub const INTERVALS: [[i64; 3]; 3] = [[1, 2, 3], [2, 5, 10], [5, 10, 15]];
+    pub const COLUMNS: [usize; 3] = [1, 2, 3];
+    pub const WALKING_SPEEDS: [Option<f64>; 4] = [None, Some(3.5), Some(5.5), Some(6.5)];
+    pub const QUIET_HOURS: [Option<QuietHours>; 4] = [
+        None,
+        Some(QuietHours { from: 22, until: 7 }),
+        Some(QuietHours { from: 23, until: 6 }),
+        Some(QuietHours { from: 0, until: 8 }),
+    ];
+    pub const PRIVACY: [Privacy; 3] = [Privacy::Off, Privacy::Rounded, Privacy::StopOnly];
+
+    pub fn next_intervals(&mut self) {
+        let intervals = Settings::INTERVALS.map(|i| i.to_vec());
+        self.intervals = next_choice(&intervals, &self.intervals);
+    }
+
+    pub fn next_columns(&mut self) {
+        self.columns = next_choice(&Settings::COLUMNS, &self.columns);
+    }
+
+    pub fn next_walking_speed(&mut self) {
+        self.walking_speed = next_choice(&Settings::WALKING_SPEEDS, &self.walking_speed);
+    }
+
+    pub fn next_quiet_hours(&mut self) {
+        self.quiet_hours = next_choice(&Settings::QUIET_HOURS, &self.quiet_hours);
+    }
+
+    pub fn next_privacy(&mut self) {
+        self.privacy = next_choice(&Settings::PRIVACY, &self.privacy);
+    }
+}
+
+/// Choice after the current one, the first one if the current one is the last or unknown.
+fn next_choice<T: Clone + PartialEq>(choices: &[T], current: &T) -> T {
+    let next = choices
+        .iter()
+        .position(|c| c == current)
+        .map_or(0, |i| (i + 1) % choices.len());
+    choices[next].clone()
+}
+
+/// Hours of the day from `from` until `until`, passing midnight if `from` is later.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuietHours {
+    pub from: u32,
+    pub until: u32,
+}
+
+impl QuietHours {
+    pub fn contains(&self, hour: u32) -> bool {
+        if self.from <= self.until {
+            self.from <= hour && hour < self.until
+        } else {
+            hour >= self.from || hour < self.until
+        }
+    }
+}
+
+/// Product types of transits, used as a filter or as the products served by a station.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Products {
+    pub bus: bool,
+    pub tram: bool,
+    pub subway: bool,
+    pub suburban: bool,
+    pub regional: bool,
+    pub national: bool,
+}
+
+impl Default for Products {
+    fn default() -> Self {
+        Products {
+            bus: true,
+            tram: true,
+            subway: true,
+            suburban: true,
+            regional: true,
+            national: true,
+        }
+    }
+}
+
+impl Products {
+    pub const NAMES: [&'static str; 6] = ["bus", "tram", "subway", "suburban", "regional", "national"];
+
+    pub fn get(&self, name: &str) -> bool {
+        match name {
+            "bus" => self.bus,
+            "tram" => self.tram,
+            "subway" => self.subway,
+            "suburban" => self.suburban,
+            "regional" => self.regional,
+            "national" => self.national,
+            _ => false,
+        }
+    }
+
+    pub fn set(&mut self, name: &str, value: bool) {
+        match name {
+            "bus" => self.bus = value,
+            "tram" => self.tram = value,
+            "subway" => self.subway = value,
+            "suburban" => self.suburban = value,
+            "regional" => self.regional = value,
+            "national" => self.national = value,
+            _ => (),
+        }
+    }
+
+    /// Parses the products object of the API, e.g. `{"nationalExpress": true, "bus": false, ...}`.
+    pub fn from_json(json: &serde_json::Value) -> Products {
+        let has = |key: &str| json.get(key).and_then(|v| v.as_bool()).unwrap_or(false);
+        Products {
+            bus: has("bus"),
+            tram: has("tram"),
+            subway: has("subway"),
+            suburban: has("suburban"),
+            regional: has("regional") || has("regionalExp"),
+            national: has("national") || has("nationalExpress"),
+        }
+    }
+
+    /// Whether a line product name of the API (e.g. `nationalExpress`) is included.
+    /// Products without a preference like ferries are always included.
+    pub fn contains(&self, product: &str) -> bool {
+        match product {
+            "bus" => self.bus,
+            "tram" => self.tram,
+            "subway" => self.subway,
+            "suburban" => self.suburban,
+            "regional" | "regionalExp" => self.regional,
+            "national" | "nationalExpress" => self.national,
+            _ => true,
+        }
+    }
+
+    /// Whether a station serving the products object of the API is included, i.e. at least one
+    /// of them is included like `contains` decides for the lines.
+    pub fn serves(&self, json: &serde_json::Value) -> bool {
+        json.as_object()
+            .is_some_and(|served| served.iter().any(|(name, v)| v.as_bool() == Some(true) && self.contains(name)))
+    }
+
+    /// Whether at least one product is included in both.
+    pub fn intersects(&self, other: &Products) -> bool {
+        Products::NAMES
+            .iter()
+            .any(|name| self.get(name) && other.get(name))
+    }
+
+    /// Query parameters of the API to only include these products.
+    pub fn query(&self) -> String {
+        format!(
+            "nationalExpress={n}&national={n}&regionalExp={r}&regional={r}&suburban={}&bus={}&subway={}&tram={}",
+            self.suburban,
+            self.bus,
+            self.subway,
+            self.tram,
+            n = self.national,
+            r = self.regional
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
 pub struct Station {
     pub id: String,
     pub name: String,
     pub location: Location,
+    /// Distance in meters from the user's address, -1 if undefined
     pub distance: i64,
+    /// Products served by the station
+    pub products: Products,
 }
 
-#[derive(Debug, Clone, Default)]
+impl Station {
+    /// Estimated time to walk the distance to the station with the given speed in km/h.
+    /// Returns `None` if the distance is undefined.
+    pub fn walking_time(&self, speed: f64) -> Option<Duration> {
+        if self.distance < 0 || speed <= 0.0 {
+            return None;
+        }
+        let secs = self.distance as f64 / (speed * 1000.0 / 3600.0);
+        Some(Duration::seconds(secs.ceil() as i64))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
 pub struct Location {
     pub lat: String,
     pub lon: String,
 }
 
-#[derive(Debug, Clone, Default)]
+impl Location {
+    /// Latitude and longitude as numbers, `None` if they aren't numbers.
+    pub fn coordinates(&self) -> Option<(f64, f64)> {
+        Some((self.lat.parse().ok()?, self.lon.parse().ok()?))
+    }
+
+    /// Approximate distance to another location in degrees of latitude, good enough to compare
+    /// short distances.
+    pub fn distance_to(&self, other: &Location) -> Option<f64> {
+        let ((lat1, lon1), (lat2, lon2)) = (self.coordinates()?, other.coordinates()?);
+        let lon_scale = ((lat1 + lat2) / 2.0).to_radians().cos();
+        Some((lat1 - lat2).hypot((lon1 - lon2) * lon_scale))
+    }
+
+    /// Approximate distance to another location in meters.
+    pub fn meters_to(&self, other: &Location) -> Option<i64> {
+        // Length of a degree of latitude
+        Some((self.distance_to(other)? * 111_195.0).round() as i64)
+    }
+}
+
+/// Which board of a stop is shown and tracked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BoardKind {
+    #[default]
+    Departures,
+    Arrivals,
+}
+
+impl BoardKind {
+    /// Path of the board in the stops endpoint of the API.
+    pub fn endpoint(&self) -> &'static str {
+        match self {
+            BoardKind::Departures => "departures",
+            BoardKind::Arrivals => "arrivals",
+        }
+    }
+
+    pub fn toggle(&self) -> BoardKind {
+        match self {
+            BoardKind::Departures => BoardKind::Arrivals,
+            BoardKind::Arrivals => BoardKind::Departures,
+        }
+    }
+}
+
+/// Time window of a departures or arrivals board.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BoardQuery {
+    /// Start of the window, `None` means now
+    pub when: Option<DateTime<Utc>>,
+    /// Length of the window in minutes, the API default if `None`
+    pub duration: Option<i64>,
+    /// Maximum number of transits, the API default if `None`
+    pub results: Option<usize>,
+}
+
+impl BoardQuery {
+    /// Query parameters of the API for this window, each prefixed with `&`.
+    pub fn query(&self) -> String {
+        let mut query = String::new();
+        if let Some(when) = self.when {
+            query += &format!("&when={}", when.to_rfc3339_opts(SecondsFormat::Secs, true));
+        }
+        if let Some(duration) = self.duration {
+            query += &format!("&duration={}", duration);
+        }
+        if let Some(results) = self.results {
+            query += &format!("&results={}", results);
+        }
+        query
+    }
+
+    /// The window right before this one, starting from `now` if no start is set.
+    pub fn earlier(&self, now: DateTime<Utc>) -> BoardQuery {
+        BoardQuery {
+            when: Some(self.when.unwrap_or(now) - Duration::minutes(self.duration.unwrap_or(10))),
+            ..*self
+        }
+    }
+
+    /// The window right after this one, starting from `now` if no start is set.
+    pub fn later(&self, now: DateTime<Utc>) -> BoardQuery {
+        BoardQuery {
+            when: Some(self.when.unwrap_or(now) + Duration::minutes(self.duration.unwrap_or(10))),
+            ..*self
+        }
+    }
+
+    /// Window of a tracking of a transit of this window: it starts now, so the transit stays
+    /// in it until it is gone, and lasts at least until the end of this window.
+    pub fn tracking(&self, now: DateTime<Utc>) -> BoardQuery {
+        let duration = self.duration.unwrap_or(10);
+        let end = self.when.unwrap_or(now) + Duration::minutes(duration);
+        BoardQuery {
+            when: None,
+            // Rounded up, so that transits at the end of the window are still included
+            duration: Some(((end - now).num_seconds() + 59).div_euclid(60).max(duration)),
+            // All transits until then, a limit could leave the tracked one out
+            results: None,
+        }
+    }
+}
+
+/// Search of the stations near a location.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NearbyQuery {
+    /// Maximum walking distance to the stations in meters
+    pub radius: u32,
+    /// Maximum number of stations
+    pub results: usize,
+}
+
+impl Default for NearbyQuery {
+    fn default() -> Self {
+        NearbyQuery {
+            radius: 1000,
+            results: 8,
+        }
+    }
+}
+
+impl NearbyQuery {
+    /// Stations shown at most, so that the keyboard stays usable
+    pub const MAX_RESULTS: usize = 32;
+
+    /// Query parameters of the API for this search, each prefixed with `&`.
+    pub fn query(&self) -> String {
+        format!("&distance={}&results={}", self.radius, self.results)
+    }
+
+    /// The search with twice as many stations twice as far away, `None` if it would show too
+    /// many stations.
+    pub fn more(&self) -> Option<NearbyQuery> {
+        (self.results < NearbyQuery::MAX_RESULTS).then(|| NearbyQuery {
+            radius: self.radius.saturating_mul(2),
+            results: (self.results * 2).min(NearbyQuery::MAX_RESULTS),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
 pub struct TransitDeparture {
     pub stop_id: String,
+    /// Whether this is a departure from or an arrival at the stop
+    pub kind: BoardKind,
     pub planned: String,
+    /// Real-time departure/arrival, `None` if unknown or cancelled
+    pub when: Option<String>,
     pub delay: Option<i64>,
+    /// Direction of the transit, or where it comes from for arrivals
     pub direction: String,
     /// Name of bus
     pub name: String,
-    pub destination: Station,
+    /// Product of the line as named by the API, e.g. `bus` or `nationalExpress`
+    pub product: String,
+    /// Id of the trip to fetch all of its stopovers
+    pub trip_id: String,
+    pub origin: Option<Station>,
+    pub destination: Option<Station>,
+    pub curr_position: Option<Location>,
+    pub cancelled: bool,
+}
+
+/// A trip of a transit with all of its stopovers.
+#[derive(Debug, Clone, Default)]
+pub struct Trip {
+    pub id: String,
+    /// Name of the line
+    pub name: String,
+    pub direction: String,
     pub curr_position: Option<Location>,
-}
\ No newline at end of file
+    pub stopovers: Vec<Stopover>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Stopover {
+    pub stop: Station,
+    pub planned_arrival: Option<String>,
+    /// Real-time arrival
+    pub arrival: Option<String>,
+    pub arrival_delay: Option<i64>,
+    pub planned_departure: Option<String>,
+    /// Real-time departure
+    pub departure: Option<String>,
+    pub departure_delay: Option<i64>,
+    pub cancelled: bool,
+}
+
+impl Stopover {
+    /// Planned time the transit is at the stop, departure preferred over arrival.
+    pub fn planned(&self) -> Option<&String> {
+        self.planned_departure.as_ref().or(self.planned_arrival.as_ref())
+    }
+
+    /// Real-time the transit leaves the stop, falling back to the arrival and the planned times.
+    pub fn when(&self) -> Option<&String> {
+        self.departure
+            .as_ref()
+            .or(self.arrival.as_ref())
+            .or(self.planned())
+    }
+
+    pub fn delay(&self) -> Option<i64> {
+        self.departure_delay.or(self.arrival_delay)
+    }
+}
@@ -1,48 +1,2296 @@
+mod fake_bot;
+mod mock_server;
+
 use crate::*;
+use chrono::{NaiveTime, TimeZone, Timelike};
+use fake_bot::*;
+use mock_server::*;
 
-fn get_user_data_json() -> String {
-    let user_data_path = std::env::var("USER_DATA_PATH").expect("USER_DATA_PATH must be set.");
-    let path = Path::new(&user_data_path);
+/// Storage in a fresh file of the temp directory.
+fn temp_storage(name: &str) -> Storage {
+    let path = std::env::temp_dir().join(format!("wimt-bot-{}-{}.json", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    Storage::new(path)
+}
 
-    let mut file_data = String::new();
-    let mut file = File::options()
-        .create(true)
-        .read(true)
-        .write(true)
-        .truncate(true)
-        .open(path)
-        .expect("Unable to open file");
-    file.read_to_string(&mut file_data).expect("Unable to read to string");
+/// Label of the button of Alexanderplatz in the keyboard of the stations near the address.
+const ALEXANDERPLATZ: &str = "Berlin Alexanderplatz · 120 m · 🚶 2 min · 🚌🚋🚇🚈🚆";
 
-    let json = serde_json::to_string(&file_data).unwrap();
-    json
+fn mock_api(server: &MockServer) -> Api {
+    Api::new(server.url.clone(), server.url.clone(), "test-token".to_string())
 }
 
 #[test]
 fn test_storing_data() {
-    let user1 = UserData::default();
-    let user2 = UserData::default();
-    
-    let _ = store_user_data(user1);
-    let json1 = get_user_data_json();
-    let _ = store_user_data(user2);
-    let json2 = get_user_data_json();
+    let storage = temp_storage("storing");
+    let user1 = UserData {
+        id: "1".to_string(),
+        city: "Berlin".to_string(),
+        ..Default::default()
+    };
+    let user2 = UserData {
+        id: "2".to_string(),
+        city: "Hamburg".to_string(),
+        ..Default::default()
+    };
+
+    assert!(storage.get_user_data("1".to_string()).is_err());
+    storage.store_user_data(user1.clone()).unwrap();
+    storage.store_user_data(user2).unwrap();
+    assert_eq!(storage.get_user_data("1".to_string()).unwrap().city, "Berlin");
+    assert_eq!(storage.get_user_data("2".to_string()).unwrap().city, "Hamburg");
+
+    // Storing a user again updates the existing record
+    storage
+        .store_user_data(UserData {
+            city: "München".to_string(),
+            ..user1
+        })
+        .unwrap();
+    assert_eq!(storage.get_user_data("1".to_string()).unwrap().city, "München");
+    assert!(storage.get_user_data("3".to_string()).is_err());
+}
+
+#[test]
+fn test_deleting_data() {
+    let storage = temp_storage("deleting");
+    assert!(storage.all_user_data().unwrap().is_empty());
+    assert!(!storage.delete_user_data("1").unwrap());
+
+    for id in ["1", "2"] {
+        storage
+            .store_user_data(UserData {
+                id: id.to_string(),
+                ..Default::default()
+            })
+            .unwrap();
+    }
+    assert_eq!(storage.all_user_data().unwrap().len(), 2);
+
+    assert!(storage.delete_user_data("1").unwrap());
+    assert!(storage.get_user_data("1".to_string()).is_err());
+    let users = storage.all_user_data().unwrap();
+    assert_eq!(users.len(), 1);
+    assert_eq!(users[0].id, "2");
+}
+
+#[test]
+fn test_purge_inactive_users() {
+    let storage = temp_storage("purge");
+    let now = Utc.with_ymd_and_hms(2023, 3, 1, 12, 0, 0).unwrap();
+    let located = |id: &str, last_active: Option<DateTime<Utc>>| UserData {
+        id: id.to_string(),
+        city: "Berlin".to_string(),
+        addr: "Alexanderplatz 1".to_string(),
+        lat: "52.5219184".to_string(),
+        lon: "13.4132147".to_string(),
+        language: Some(Lang::De),
+        last_active,
+        ..Default::default()
+    };
+    assert_eq!(storage.purge_inactive_users(now, Duration::days(30)).unwrap(), 0);
+
+    storage.store_user_data(located("1", Some(now - Duration::days(31)))).unwrap();
+    storage.store_user_data(located("2", Some(now - Duration::days(29)))).unwrap();
+    storage.store_user_data(located("3", None)).unwrap();
+    storage.store_user_data(located("-100", Some(now - Duration::days(365)))).unwrap();
+    assert_eq!(storage.purge_inactive_users(now, Duration::days(30)).unwrap(), 1);
+
+    // Only the location is forgotten, the preferences are kept
+    let inactive = storage.get_user_data("1".to_string()).unwrap();
+    assert!(!inactive.has_location());
+    assert!(inactive.addr.is_empty());
+    assert_eq!(inactive.city, "Berlin");
+    assert_eq!(inactive.language, Some(Lang::De));
+    assert!(storage.get_user_data("2".to_string()).unwrap().has_location());
+    assert!(storage.get_user_data("-100".to_string()).unwrap().has_location());
+
+    // Users stored before their activity was recorded are active as of the first purge
+    let unknown = storage.get_user_data("3".to_string()).unwrap();
+    assert!(unknown.has_location());
+    assert_eq!(unknown.last_active, Some(now));
+
+    // Each handled update keeps the user active
+    assert!(storage.touch_user("2", now + Duration::days(30)).unwrap());
+    assert!(!storage.touch_user("4", now).unwrap());
+    let later = now + Duration::days(31);
+    assert_eq!(storage.purge_inactive_users(later, Duration::days(30)).unwrap(), 1);
+    assert!(storage.get_user_data("2".to_string()).unwrap().has_location());
+    assert!(!storage.get_user_data("3".to_string()).unwrap().has_location());
+    assert!(storage.get_user_data("4".to_string()).is_err());
+}
+
+#[test]
+fn test_privacy_modes() {
+    let user = UserData {
+        addr: "Alexanderplatz 1".to_string(),
+        lat: "52.5219184".to_string(),
+        lon: "13.4132147".to_string(),
+        ..Default::default()
+    };
+
+    let mut off = user.clone();
+    off.apply_privacy();
+    assert_eq!((off.addr, off.lat), (user.addr.clone(), user.lat.clone()));
+
+    let mut rounded = user.clone();
+    rounded.settings.next_privacy();
+    assert_eq!(rounded.settings.privacy, Privacy::Rounded);
+    rounded.apply_privacy();
+    assert_eq!((rounded.addr.as_str(), rounded.lat.as_str(), rounded.lon.as_str()), ("", "52.522", "13.413"));
+
+    let mut stop_only = rounded.clone();
+    stop_only.settings.next_privacy();
+    assert_eq!(stop_only.settings.privacy, Privacy::StopOnly);
+    stop_only.apply_privacy();
+    assert!(!stop_only.has_location());
+    stop_only.stop = Some(SavedStop {
+        id: "8011155".to_string(),
+        name: "Berlin Alexanderplatz".to_string(),
+    });
+    assert!(stop_only.has_location());
+    assert_eq!(stop_only.stop.as_ref().unwrap().station().distance, -1);
+}
+
+#[test]
+fn test_walking_time() {
+    let station = Station {
+        distance: 450,
+        ..Default::default()
+    };
+    assert_eq!(station.walking_time(4.5), Some(chrono::Duration::seconds(360)));
+
+    let undefined = Station {
+        distance: -1,
+        ..Default::default()
+    };
+    assert_eq!(undefined.walking_time(4.5), None);
+}
+
+#[test]
+fn test_products_filter() {
+    let products = Products {
+        bus: false,
+        ..Default::default()
+    };
+    assert!(!products.contains("bus"));
+    assert!(products.contains("nationalExpress"));
+    assert!(products.contains("ferry"));
+
+    let served = Products::from_json(&serde_json::json!({"bus": true, "regionalExp": false}));
+    assert!(served.bus && !served.regional);
+    assert!(!served.intersects(&products));
+
+    // Stations are filtered like their lines, products without a preference are included
+    assert!(!products.serves(&serde_json::json!({"bus": true, "regionalExp": false})));
+    assert!(products.serves(&serde_json::json!({"bus": true, "nationalExpress": true})));
+    assert!(products.serves(&serde_json::json!({"bus": true, "ferry": true})));
+    assert!(!products.serves(&serde_json::json!({})));
+}
+
+#[test]
+fn test_settings_choices() {
+    // Records stored before the settings existed get the defaults
+    let user: UserData = serde_json::from_str(r#"{"id":"1","city":"","addr":"","lat":"","lon":""}"#).unwrap();
+    assert_eq!(user.settings, Settings::default());
+
+    let mut settings = Settings::default();
+    settings.next_intervals();
+    assert_eq!(settings.intervals, vec![2, 5, 10]);
+    settings.next_columns();
+    settings.next_columns();
+    assert_eq!(settings.columns, 1);
+    settings.next_walking_speed();
+    assert_eq!(settings.walking_speed, Some(3.5));
+
+    // Unknown choices, e.g. edited by hand, start over with the first one
+    settings.intervals = vec![7];
+    settings.next_intervals();
+    assert_eq!(settings.intervals, vec![1, 2, 3]);
+
+    let night = QuietHours { from: 22, until: 7 };
+    assert!(night.contains(23) && night.contains(0) && night.contains(6));
+    assert!(!night.contains(7) && !night.contains(21));
+    let afternoon = QuietHours { from: 13, until: 15 };
+    assert!(afternoon.contains(13) && !afternoon.contains(15));
+}
+
+#[test]
+fn test_board_query_paging() {
+    use chrono::TimeZone;
+    let now = chrono::Utc.with_ymd_and_hms(2023, 1, 10, 16, 0, 0).unwrap();
+    let query = BoardQuery {
+        duration: Some(30),
+        ..Default::default()
+    };
+    assert_eq!(query.query(), "&duration=30");
+
+    let later = query.later(now);
+    assert_eq!(later.when, Some(now + chrono::Duration::minutes(30)));
+    assert_eq!(later.query(), "&when=2023-01-10T16:30:00Z&duration=30");
+    assert_eq!(later.earlier(now).when, Some(now));
+
+    // Trackings start now and last until the end of the window
+    let tracking = later.tracking(now);
+    assert_eq!(tracking.when, None);
+    assert_eq!(tracking.query(), "&duration=60");
+    assert_eq!(query.earlier(now).tracking(now).duration, Some(30));
+}
+
+#[test]
+fn test_board_config() {
+    // An empty board is no use, so the API default is used instead
+    assert_eq!(config::parse_board_results(Some("0")), None);
+    assert_eq!(config::parse_board_results(Some("12")), Some(12));
+    assert_eq!(config::parse_board_results(None), None);
+    assert_eq!(config::parse_board_duration(Some("0")), config::DEFAULT_BOARD_DURATION);
+    assert_eq!(config::parse_board_duration(Some("soon")), config::DEFAULT_BOARD_DURATION);
+    assert_eq!(config::parse_board_duration(Some("60")), 60);
+}
+
+#[test]
+fn test_format_trip_progress() {
+    use chrono::TimeZone;
+    let stopover = |id: &str, time: &str| Stopover {
+        stop: Station {
+            id: id.to_string(),
+            name: format!("Stop {}", id),
+            ..Default::default()
+        },
+        planned_departure: Some(time.to_string()),
+        departure: Some(time.to_string()),
+        ..Default::default()
+    };
+    let trip = Trip {
+        name: "Bus 100".to_string(),
+        direction: "Zoo".to_string(),
+        stopovers: vec![
+            stopover("1", "2023-01-10T16:00:00+01:00"),
+            stopover("2", "2023-01-10T16:05:00+01:00"),
+            stopover("3", "2023-01-10T16:10:00+01:00"),
+        ],
+        ..Default::default()
+    };
+    let now = chrono::Utc.with_ymd_and_hms(2023, 1, 10, 15, 2, 0).unwrap();
+
+    let info = format_trip(&trip, "3", now, Lang::En);
+    let lines: Vec<&str> = info.lines().collect();
+    assert_eq!(lines[2], "▪️ 16:00 Stop 1");
+    assert_eq!(lines[3], "      🚌");
+    assert_eq!(lines[4], "▫️ 16:05 Stop 2");
+    assert_eq!(lines[5], "▫️ 16:10 <b>📍 Stop 3</b>");
+}
 
-    assert_ne!(json1, json2);
+#[test]
+fn test_format_trip_delays() {
+    let stopover = |id: &str, delay: i64, when: &str| Stopover {
+        stop: Station {
+            id: id.to_string(),
+            name: format!("Stop {}", id),
+            ..Default::default()
+        },
+        planned_departure: Some("2023-01-10T16:00:00+01:00".to_string()),
+        departure: Some(when.to_string()),
+        departure_delay: Some(delay),
+        ..Default::default()
+    };
+    let trip = Trip {
+        name: "Bus 100".to_string(),
+        direction: "Zoo".to_string(),
+        stopovers: vec![
+            stopover("1", 120, "2023-01-10T16:02:00+01:00"),
+            stopover("2", -60, "2023-01-10T15:59:00+01:00"),
+            stopover("3", 30, "2023-01-10T16:00:30+01:00"),
+        ],
+        ..Default::default()
+    };
+    let now = Utc.with_ymd_and_hms(2023, 1, 10, 14, 0, 0).unwrap();
+
+    let info = format_trip(&trip, "3", now, Lang::En);
+    let lines: Vec<&str> = info.lines().collect();
+    assert_eq!(lines[2], "▫️ 16:00 → 16:02 (+2) Stop 1");
+    // Early stops are told like on the boards
+    assert_eq!(lines[3], "▫️ 16:00 → 15:59 (-1) Stop 2");
+    assert_eq!(lines[4], "▫️ 16:00 <b>📍 Stop 3</b>");
+}
+
+#[test]
+fn test_find_transit() {
+    let dep = |name: &str, trip_id: &str| TransitDeparture {
+        name: name.to_string(),
+        direction: "S Westkreuz (Berlin)".to_string(),
+        trip_id: trip_id.to_string(),
+        ..Default::default()
+    };
+    let deps = vec![dep("S 5", "1|100|0|80"), dep("S 5", "1|200|0|80"), dep("S 7", "")];
+    assert_eq!(transit_label(&deps[0]), "S 5 (S Westkreuz (Berlin))");
+    // Departures of the same line are told apart by their trips
+    assert_eq!(find_transit(&deps, "1|200|0|80"), Some(&deps[1]));
+    assert_eq!(find_transit(&deps, "1|300|0|80"), None);
+    assert_eq!(find_transit(&deps, ""), None);
+}
+
+#[test]
+fn test_transit_buttons() {
+    let dep = |direction: &str, trip_id: &str| TransitDeparture {
+        name: "RE 1".to_string(),
+        direction: direction.to_string(),
+        trip_id: trip_id.to_string(),
+        ..Default::default()
+    };
+    let long = "Frankfurt (Oder), Bahnhof/Europa-Universität Viadrina über Berlin Ostbahnhof";
+    let deps = vec![
+        dep(long, "1|200311|0|80|10012023"),
+        dep("Magdeburg Hbf", ""),
+        dep("Magdeburg Hbf", &"1".repeat(64)),
+    ];
+    // Long directions only make the label long, transits without a usable trip get no button
+    let buttons = transit_buttons(&deps, GROUP_BOARD);
+    assert_eq!(
+        buttons,
+        vec![(format!("RE 1 ({})", long), "board:1|200311|0|80|10012023".to_string())]
+    );
+}
+
+#[test]
+fn test_format_board_escaping() {
+    let dep = TransitDeparture {
+        planned: "2023-01-10T16:04:00+01:00".to_string(),
+        delay: Some(420),
+        name: "Bus <X>".to_string(),
+        product: "bus".to_string(),
+        direction: "Tom & Jerry Straße".to_string(),
+        ..Default::default()
+    };
+    let pages = format_board("A & B", BoardKind::Departures, &BoardQuery::default(), std::slice::from_ref(&dep), Lang::En);
+    assert_eq!(
+        pages,
+        ["🚏 Departures from selected station (+ mins delay): <b>A &amp; B</b>\n\n\
+         🔴 <code>16:04</code> (+7) 🚌 <b>Bus &lt;X&gt;</b> to <b>Tom &amp; Jerry Straße</b>"]
+    );
+
+    // Early transits and long names
+    let dep = TransitDeparture {
+        delay: Some(-60),
+        direction: "A".repeat(60),
+        ..dep
+    };
+    let pages = format_board("Stop", BoardKind::Arrivals, &BoardQuery::default(), &[dep], Lang::En);
+    assert!(pages[0].ends_with(&format!("🟢 <code>16:04</code> (-1) 🚌 <b>Bus &lt;X&gt;</b> from <b>{}…</b>", "A".repeat(47))));
+    assert_eq!(escape_name("Berlin Hbf"), "Berlin Hbf");
+}
+
+#[test]
+fn test_format_board_pages() {
+    let deps: Vec<TransitDeparture> = (0..200)
+        .map(|i| TransitDeparture {
+            planned: "2023-01-10T16:04:00+01:00".to_string(),
+            name: format!("Bus {}", i),
+            product: "bus".to_string(),
+            direction: "Ü".repeat(48),
+            ..Default::default()
+        })
+        .collect();
+    let query = BoardQuery::default();
+    let pages = format_board("Stop", BoardKind::Departures, &query, &deps, Lang::En);
+    assert_eq!(pages.len(), 3);
+    for page in &pages {
+        assert!(page.encode_utf16().count() <= MESSAGE_LIMIT);
+        assert!(page.starts_with("🚏 Departures from selected station (+ mins delay): <b>Stop</b>\n\n⚪"));
+    }
+    // Transits are never split, the ones left over are counted
+    let shown: usize = pages.iter().map(|p| p.matches("⚪").count()).sum();
+    assert!(pages[2].ends_with(&format!("… and {} more, see them with <b>Later »</b>.", 200 - shown)));
+    assert!(pages[0].lines().last().unwrap().ends_with("</b>"));
+
+    let pages = format_board("Stop", BoardKind::Departures, &query, &deps[..10], Lang::En);
+    assert_eq!(pages.len(), 1);
+    assert_eq!(pages[0].matches("⚪").count(), 10);
+}
+
+/// Ids of all messages of a Fluent catalog.
+fn catalog_ids(catalog: &str) -> Vec<&str> {
+    catalog
+        .lines()
+        .filter(|l| l.starts_with(|c: char| c.is_ascii_lowercase()))
+        .filter_map(|l| l.split_once(" =").map(|(id, _)| id))
+        .collect()
+}
+
+#[test]
+fn test_catalogs_complete() {
+    let en = catalog_ids(EN_CATALOG);
+    let de = catalog_ids(DE_CATALOG);
+    assert!(en.len() > 50);
+    assert_eq!(en, de);
+
+    // Every message formats without falling back to its id
+    for id in en {
+        for lang in [Lang::En, Lang::De] {
+            assert_ne!(lang.tr(id), id, "{} in {}", id, lang.code());
+        }
+    }
+}
+
+#[test]
+fn test_localized_messages() {
+    assert_eq!(Lang::from_code(Some("de")), Lang::De);
+    assert_eq!(Lang::from_code(Some("de-AT")), Lang::De);
+    assert_eq!(Lang::from_code(Some("en-GB")), Lang::En);
+    assert_eq!(Lang::from_code(Some("fr")), Lang::En);
+    assert_eq!(Lang::from_code(None), Lang::En);
+
+    // Arguments are inserted without isolation marks and select the plural form
+    let countdown = |lang: Lang, minutes: i64| {
+        lang.tr_args("countdown", &[("transit", "S 5".into()), ("minutes", minutes.into())])
+    };
+    assert_eq!(
+        countdown(Lang::En, 1),
+        "🔔 Your transit: <b>S 5</b> 🚌 arrives in <b>1</b> minute ⌛!"
+    );
+    assert!(countdown(Lang::En, 3).contains("<b>3</b> minutes"));
+    assert_eq!(
+        countdown(Lang::De, 1),
+        "🔔 Dein Verkehrsmittel: <b>S 5</b> 🚌 kommt in <b>1</b> Minute ⌛!"
+    );
+    assert!(countdown(Lang::De, 3).contains("<b>3</b> Minuten"));
+    assert_eq!(Lang::De.tr("missing-message"), "missing-message");
+}
+
+#[test]
+fn test_format_time_dst() {
+    let berlin = chrono_tz::Europe::Berlin;
+    // Times are shown in the timezone of the stations, whatever offset the API returned
+    assert_eq!(format_time("2023-01-10T15:04:00Z", berlin), "16:04");
+    assert_eq!(format_time("2023-01-10T16:04:00+01:00", berlin), "16:04");
+    assert_eq!(format_time("2023-07-10T16:04:00+01:00", berlin), "17:04");
+    assert_eq!(format_time("2023-07-10T16:04:00+01:00", chrono_tz::Europe::London), "16:04");
+    assert_eq!(format_time("invalid", berlin), "");
+
+    // Start of daylight saving time, 02:00 CET is 03:00 CEST
+    assert_eq!(format_time("2023-03-26T00:59:00Z", berlin), "01:59");
+    assert_eq!(format_time("2023-03-26T01:00:00Z", berlin), "03:00");
+    // End of daylight saving time, 03:00 CEST is 02:00 CET
+    assert_eq!(format_time("2023-10-29T00:30:00Z", berlin), "02:30");
+    assert_eq!(format_time("2023-10-29T01:30:00Z", berlin), "02:30");
+}
+
+#[test]
+fn test_next_occurrence_dst() {
+    let berlin = chrono_tz::Europe::Berlin;
+    let at = |h, m| NaiveTime::from_hms_opt(h, m, 0).unwrap();
+    let utc = |d, h, m| Utc.with_ymd_and_hms(2023, 3, d, h, m, 0).unwrap();
+
+    // 17:30 CET is 16:30 UTC, tomorrow once it has passed
+    assert_eq!(next_occurrence(at(17, 30), utc(10, 15, 0), berlin), utc(10, 16, 30));
+    assert_eq!(next_occurrence(at(17, 30), utc(10, 17, 0), berlin), utc(11, 16, 30));
+
+    // Night of the start of daylight saving time, 02:30 does not exist
+    let now = utc(26, 0, 0);
+    assert_eq!(next_occurrence(at(1, 30), now, berlin), utc(26, 0, 30));
+    assert_eq!(next_occurrence(at(2, 30), now, berlin), utc(26, 1, 30));
+    assert_eq!(next_occurrence(at(3, 30), now, berlin), utc(26, 1, 30));
+
+    // Night of the end of daylight saving time, 02:30 exists twice
+    let now = Utc.with_ymd_and_hms(2023, 10, 28, 22, 0, 0).unwrap();
+    assert_eq!(
+        next_occurrence(at(2, 30), now, berlin),
+        Utc.with_ymd_and_hms(2023, 10, 29, 0, 30, 0).unwrap()
+    );
+    assert_eq!(
+        next_occurrence(at(3, 30), now, berlin),
+        Utc.with_ymd_and_hms(2023, 10, 29, 2, 30, 0).unwrap()
+    );
+}
+
+#[tokio::test]
+async fn test_mock_server_records_requests() {
+    let server = MockServer::start(|request| match request.method.as_str() {
+        "POST" => Response::ok(r#"{"ok": true}"#),
+        _ => Response::not_found(),
+    })
+    .await;
+
+    let resp = reqwest::Client::new()
+        .post(format!("{}/echo?x=1", server.url))
+        .json(&serde_json::json!({"text": "hello"}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+
+    let request = &server.requests()[0];
+    assert_eq!(request.path, "/echo");
+    assert_eq!(request.query, "x=1");
+    assert_eq!(request.json()["text"], "hello");
 }
 
 #[tokio::test]
 async fn test_fetch_geocode() {
-    use dotenv::dotenv;
-    dotenv().ok();
-    let geocode = api::fetch_geocode("empire state building".into(), "nyc".into()).await.unwrap();
-    assert_eq!(geocode, ("40.748428399999995".to_string(), "-73.98565461987332".to_string()));
+    let server = start_api_mock().await;
+    let geocode = mock_api(&server)
+        .fetch_geocode("Alexanderplatz 1".into(), "Berlin".into())
+        .await
+        .unwrap();
+    assert_eq!(geocode, ("52.5219184".to_string(), "13.4132147".to_string()));
+
+    let request = &server.requests()[0];
+    assert_eq!(request.path, "/search");
+    assert!(request.query.contains("key=test-token"));
+}
+
+#[tokio::test]
+async fn test_fetch_geocode_not_found() {
+    let server = MockServer::start(|_| Response::ok(r#"{"error": "Unable to geocode"}"#)).await;
+    let geocode = mock_api(&server)
+        .fetch_geocode("Nowhere 1".into(), "Berlin".into())
+        .await;
+    assert!(geocode.is_err());
+}
+
+#[tokio::test]
+async fn test_api_stats() {
+    let server = start_api_mock().await;
+    let api = mock_api(&server);
+    api.search_stations("Alexanderplatz").await.unwrap();
+    api.fetch_geocode("Alexanderplatz 1".into(), "Berlin".into()).await.unwrap();
+
+    // Answers with an error status are counted as failed
+    let failing = MockServer::start(|_| Response::not_found()).await;
+    let mut failing_api = api.clone();
+    failing_api.transport_url = failing.url.clone();
+    let departures = failing_api
+        .get_board(
+            "8011155".into(),
+            BoardKind::Departures,
+            &Products::default(),
+            &BoardQuery::default(),
+        )
+        .await
+        .unwrap();
+    assert!(departures.is_empty());
+
+    // The statistics are shared by all clones
+    assert_eq!(api.stats.transport.requests(), 2);
+    assert_eq!(api.stats.transport.errors(), 1);
+    assert_eq!(api.stats.transport.error_rate(), 50.0);
+    assert_eq!(api.stats.locationiq.requests(), 1);
+    assert_eq!(api.stats.locationiq.errors(), 0);
+    assert_eq!(RequestStats::default().error_rate(), 0.0);
 }
 
 #[tokio::test]
 async fn test_fetch_address() {
-    use dotenv::dotenv;
-    dotenv().ok();
-    let addr = api::fetch_address("40.748428399999995".into(), "-73.98565461987332".into()).await.unwrap();
-    assert_eq!(addr, "5th Avenue 350");
-}
\ No newline at end of file
+    let server = start_api_mock().await;
+    let addr = mock_api(&server)
+        .fetch_address("52.5219184".into(), "13.4132147".into())
+        .await
+        .unwrap();
+    assert_eq!(addr, "Alexanderplatz 1");
+    assert_eq!(server.requests()[0].path, "/reverse");
+}
+
+#[tokio::test]
+async fn test_get_nearby_stations() {
+    let server = start_api_mock().await;
+    let api = mock_api(&server);
+
+    let stations = api
+        .get_nearby_stations("52.5219184".into(), "13.4132147".into(), &Products::default(), &NearbyQuery::default())
+        .await
+        .unwrap();
+    assert_eq!(stations.len(), 3);
+    assert_eq!(stations[0].id, "8011155");
+    assert_eq!(stations[0].distance, 120);
+    assert!(stations[0].products.suburban && !stations[0].products.national);
+    // Station names are kept whole, stations are told apart by their id
+    assert_eq!(stations[1].name, "Berlin, Alexanderplatz/Dircksenstr.");
+    assert_eq!(stations[2].name, "Spandauer Str./Marienkirche, Berlin");
+    assert!(server.requests()[0].query.contains("distance=1000&results=8"));
+
+    let trams = Products {
+        bus: false,
+        ..Default::default()
+    };
+    let stations = api
+        .get_nearby_stations("52.5219184".into(), "13.4132147".into(), &trams, &NearbyQuery::default())
+        .await
+        .unwrap();
+    assert_eq!(stations.len(), 2);
+}
+
+#[test]
+fn test_nearby_query() {
+    let query = NearbyQuery::default();
+    assert_eq!(query.query(), "&distance=1000&results=8");
+    let more = query.more().unwrap();
+    assert_eq!((more.radius, more.results), (2000, 16));
+    // The search grows until it shows as many stations as fit on a keyboard
+    let most = more.more().unwrap();
+    assert_eq!(most.results, NearbyQuery::MAX_RESULTS);
+    assert_eq!(most.more(), None);
+}
+
+#[tokio::test]
+async fn test_search_stations() {
+    let server = start_api_mock().await;
+    let stations = mock_api(&server).search_stations("Alexanderplatz").await.unwrap();
+    assert_eq!(stations.len(), 2);
+    assert_eq!(stations[0].id, "8011155");
+    assert_eq!(stations[0].name, "Berlin Alexanderplatz");
+    assert_eq!(stations[0].distance, -1);
+
+    let request = &server.requests()[0];
+    assert_eq!(request.path, "/locations");
+    assert!(request.query.contains("query=Alexanderplatz"));
+}
+
+#[tokio::test]
+async fn test_get_departures() {
+    let server = start_api_mock().await;
+    let query = BoardQuery {
+        duration: Some(30),
+        results: Some(5),
+        ..Default::default()
+    };
+    let products = Products {
+        regional: false,
+        ..Default::default()
+    };
+    let deps = mock_api(&server)
+        .get_board("8011155".into(), BoardKind::Departures, &products, &query)
+        .await
+        .unwrap();
+
+    assert_eq!(deps.len(), 3);
+    assert_eq!(deps[0].name, "S 5");
+    assert_eq!(deps[0].product, "suburban");
+    assert_eq!(deps[0].direction, "S Westkreuz (Berlin)");
+    assert_eq!(deps[0].delay, Some(120));
+    assert_eq!(deps[0].trip_id, "1|200125|0|80|10012023");
+    assert_eq!(deps[0].destination.as_ref().unwrap().name, "Berlin Westkreuz");
+    assert!(deps[0].curr_position.is_some());
+    assert!(deps[2].cancelled && !deps[0].cancelled);
+    assert!(deps[1].curr_position.is_none());
+    // Cancelled departures have no real-time
+    assert_eq!(deps[2].when, None);
+    assert_eq!(deps[2].delay, None);
+
+    let request = &server.requests()[0];
+    assert_eq!(request.path, "/stops/8011155/departures");
+    assert!(request.query.contains("regional=false"));
+    assert!(request.query.contains("bus=true"));
+    assert!(request.query.contains("duration=30"));
+    assert!(request.query.contains("results=5"));
+}
+
+#[tokio::test]
+async fn test_get_arrivals() {
+    let server = start_api_mock().await;
+    let arrs = mock_api(&server)
+        .get_board(
+            "8011155".into(),
+            BoardKind::Arrivals,
+            &Products::default(),
+            &BoardQuery::default(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(arrs.len(), 2);
+    assert_eq!(arrs[0].kind, BoardKind::Arrivals);
+    assert_eq!(arrs[0].direction, "Frankfurt (Oder)");
+    assert_eq!(arrs[0].origin.as_ref().unwrap().id, "8010113");
+    assert!(arrs[0].destination.is_none());
+    assert_eq!(server.requests()[0].path, "/stops/8011155/arrivals");
+}
+
+#[tokio::test]
+async fn test_api_unavailable() {
+    // Proxies answer with an HTML page while the API is down
+    let server = MockServer::start(|_| Response {
+        status: 503,
+        body: b"<html><body>Service Unavailable</body></html>".to_vec(),
+    })
+    .await;
+    let api = mock_api(&server);
+    let board = api
+        .get_board(
+            "8011155".into(),
+            BoardKind::Departures,
+            &Products::default(),
+            &BoardQuery::default(),
+        )
+        .await;
+    assert!(board.is_err());
+    let nearby = api
+        .get_nearby_stations("52.5219184".into(), "13.4132147".into(), &Products::default(), &NearbyQuery::default())
+        .await;
+    assert!(nearby.is_err());
+}
+
+#[tokio::test]
+async fn test_get_trip() {
+    let server = start_api_mock().await;
+    let trip = mock_api(&server)
+        .get_trip("1|200125|0|80|10012023".into(), "S 5".into())
+        .await
+        .unwrap();
+
+    assert_eq!(trip.name, "S 5");
+    assert_eq!(trip.stopovers.len(), 5);
+    assert!(trip.stopovers[2].cancelled);
+    assert_eq!(trip.stopovers[3].stop.id, "8011155");
+    assert!(trip.curr_position.is_some());
+
+    // The trip id is passed as a single path segment
+    let request = &server.requests()[0];
+    assert_eq!(request.method, "GET");
+    assert_eq!(request.path, "/trips/1|200125|0|80|10012023");
+    assert!(request.query.contains("lineName=S+5"));
+}
+
+//////////////////////////////////////////////////////////
+// Tracker
+//////////////////////////////////////////////////////////
+/// Departures of a tracking that can be changed while it is running.
+#[derive(Clone, Default)]
+struct FakeSource(Arc<Mutex<Vec<TransitDeparture>>>);
+
+impl FakeSource {
+    fn set(&self, deps: Vec<TransitDeparture>) {
+        *self.0.lock().unwrap() = deps;
+    }
+}
+
+impl DepartureSource for FakeSource {
+    async fn departures(&self) -> Result<Vec<TransitDeparture>, Box<dyn Error + Send + Sync>> {
+        Ok(self.0.lock().unwrap().clone())
+    }
+
+    async fn trip(&self, _dep: &TransitDeparture) -> Result<Trip, Box<dyn Error + Send + Sync>> {
+        Err("No trips in tests!")?
+    }
+}
+
+/// S 5 to Westkreuz planned at 15:10 with the given delay in seconds.
+fn s5(delay: Option<i64>) -> TransitDeparture {
+    TransitDeparture {
+        planned: "2023-01-10T16:10:00+01:00".to_string(),
+        delay,
+        direction: "Westkreuz".to_string(),
+        name: "S 5".to_string(),
+        trip_id: "1|5005|0|80|10012023".to_string(),
+        ..Default::default()
+    }
+}
+
+/// Tracker of the S 5 started at 15:00, with its clock and departures.
+fn s5_tracker(
+    plan: NotifyPlan,
+    walk_time: Option<Duration>,
+) -> (Tracker<TokioClock, FakeSource>, TokioClock, FakeSource) {
+    let clock = TokioClock::starting_at(Utc.with_ymd_and_hms(2023, 1, 10, 15, 0, 0).unwrap());
+    let source = FakeSource::default();
+    source.set(vec![s5(None)]);
+    let tracker = Tracker::new(
+        clock.clone(),
+        source.clone(),
+        "1|5005|0|80|10012023".to_string(),
+        plan,
+        walk_time,
+    );
+    (tracker, clock, source)
+}
+
+/// Waits for the next update and returns its events with the minute it happened at.
+async fn next_events<C: Clock, S: DepartureSource>(
+    tracker: &mut Tracker<C, S>,
+    clock: &TokioClock,
+) -> (u32, Vec<Event>) {
+    tracker.wait().await;
+    let events = tracker.poll().await.unwrap();
+    (clock.now().minute(), events)
+}
+
+/// Minutes of the countdown of an update, `0` when arriving now, `None` when done.
+fn countdown(events: &[Event]) -> Option<i64> {
+    events.iter().find_map(|e| match e {
+        Event::Countdown { minutes, .. } => Some(*minutes),
+        Event::ArrivingNow => Some(0),
+        _ => None,
+    })
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_throttle_budgets() {
+    let throttle = Throttle::new(2, 3);
+    assert_eq!(throttle.check(UserId(1)), Ok(()));
+    assert_eq!(throttle.check(UserId(1)), Ok(()));
+
+    // Only the first refused update is answered
+    let cooldown = throttle.check(UserId(1)).unwrap_err();
+    assert_eq!(cooldown.wait, tokio::time::Duration::from_secs(30));
+    assert!(cooldown.notify);
+    assert!(!throttle.check(UserId(1)).unwrap_err().notify);
+
+    // The global budget is shared by all users
+    assert_eq!(throttle.check(UserId(2)), Ok(()));
+    let cooldown = throttle.check(UserId(3)).unwrap_err();
+    assert_eq!(cooldown.wait, tokio::time::Duration::from_secs(20));
+
+    tokio::time::advance(tokio::time::Duration::from_secs(30)).await;
+    assert_eq!(throttle.check(UserId(1)), Ok(()));
+    assert!(throttle.check(UserId(1)).unwrap_err().notify);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_send_queue_slots() {
+    use tokio::time::{Duration, Instant};
+
+    let queue = SendQueue::new(Bot::new("1234:TEST"), SendLimits::default());
+    let start = Instant::now();
+    let private: Vec<Duration> = (0..3).map(|_| queue.reserve_chat(ChatId(1)) - start).collect();
+    assert_eq!(private, [Duration::ZERO, Duration::from_secs(1), Duration::from_secs(2)]);
+    assert!(!queue.is_congested(ChatId(1)));
+
+    // Chats don't wait for each other, groups have to wait longer
+    assert_eq!(queue.reserve_chat(ChatId(-1)) - start, Duration::ZERO);
+    assert_eq!(queue.reserve_chat(ChatId(-1)) - start, Duration::from_secs(3));
+
+    let global: Vec<Duration> = (0..3).map(|_| queue.reserve_global() - start).collect();
+    assert_eq!(global, [Duration::ZERO, Duration::from_millis(34), Duration::from_millis(68)]);
+
+    for _ in 0..10 {
+        queue.reserve_chat(ChatId(1));
+    }
+    assert!(queue.is_congested(ChatId(1)));
+    tokio::time::advance(Duration::from_secs(13)).await;
+    assert!(!queue.is_congested(ChatId(1)));
+}
+
+/// Answer of the Bot API to a request, with the given result or error.
+fn bot_api_response(request: &mock_server::Request, error: Option<(u16, &str, Option<u64>)>) -> Response {
+    match error {
+        Some((status, description, retry_after)) => {
+            let mut body = serde_json::json!({
+                "ok": false,
+                "error_code": status,
+                "description": description
+            });
+            if let Some(seconds) = retry_after {
+                body["parameters"] = serde_json::json!({ "retry_after": seconds });
+            }
+            Response {
+                status,
+                body: body.to_string().into_bytes(),
+            }
+        }
+        None => {
+            let chat_id = request.json()["chat_id"].as_i64().unwrap_or(CHAT_ID);
+            let message = serde_json::json!({
+                "message_id": 1,
+                "date": 0,
+                "chat": { "id": chat_id, "type": "private", "first_name": "Test" },
+                "text": ""
+            });
+            Response::ok(serde_json::json!({ "ok": true, "result": message }).to_string())
+        }
+    }
+}
+
+/// Bot of a mock Bot API that answers the first `sendMessage` with a flood error, and every
+/// `deleteMessage` with an error.
+async fn flooded_bot_api(blocked: bool) -> (MockServer, SendQueue) {
+    let flooded = Arc::new(Mutex::new(false));
+    let server = MockServer::start(move |request| {
+        let method = request.path.rsplit('/').next().unwrap_or_default().to_lowercase();
+        match method.as_str() {
+            "deletemessage" => bot_api_response(
+                request,
+                Some((400, "Bad Request: message to delete not found", None)),
+            ),
+            "sendmessage" if blocked => bot_api_response(
+                request,
+                Some((403, "Forbidden: bot was blocked by the user", None)),
+            ),
+            "sendmessage" if !std::mem::replace(&mut *flooded.lock().unwrap(), true) => {
+                bot_api_response(request, Some((429, "Too Many Requests: retry after 1", Some(1))))
+            }
+            _ => bot_api_response(request, None),
+        }
+    })
+    .await;
+    let bot = Bot::new("1234:TEST").set_api_url(reqwest::Url::parse(&server.url).unwrap());
+    let limits = SendLimits {
+        private_chat: tokio::time::Duration::ZERO,
+        group_chat: tokio::time::Duration::ZERO,
+        global: tokio::time::Duration::ZERO,
+    };
+    (server, SendQueue::new(bot, limits))
+}
+
+#[tokio::test]
+async fn test_send_queue_retry_after() {
+    let (server, queue) = flooded_bot_api(false).await;
+    let start = tokio::time::Instant::now();
+    let message = queue
+        .send(ChatId(CHAT_ID), queue.bot().send_message(ChatId(CHAT_ID), "hello"))
+        .await
+        .unwrap();
+    assert_eq!(message.chat.id, ChatId(CHAT_ID));
+    assert!(start.elapsed() >= tokio::time::Duration::from_secs(1));
+    assert_eq!(server.requests().len(), 2);
+
+    let error = queue
+        .send(ChatId(CHAT_ID), queue.bot().delete_message(ChatId(CHAT_ID), message.id))
+        .await
+        .unwrap_err();
+    assert!(!is_unreachable(&error));
+}
+
+/// Notifier of a tracking in the private chat, with its dialogue in the tracking state.
+async fn test_notifier(queue: SendQueue) -> (TelegramNotifier, Arc<InMemStorage<State>>) {
+    use teloxide::dispatching::dialogue::Storage as _;
+
+    let dialogues = InMemStorage::<State>::new();
+    dialogues
+        .clone()
+        .update_dialogue(ChatId(CHAT_ID), State::ReceiveProducts)
+        .await
+        .unwrap();
+    let notifier = TelegramNotifier::new(
+        queue,
+        MyDialogue::new(dialogues.clone(), ChatId(CHAT_ID)),
+        ChatId(CHAT_ID),
+        "Berlin Alexanderplatz".to_string(),
+        "S 5 (S Westkreuz (Berlin))".to_string(),
+        BoardKind::Departures,
+        Lang::En,
+    );
+    (notifier, dialogues)
+}
+
+#[tokio::test]
+async fn test_notifier_degrades() {
+    use teloxide::dispatching::dialogue::Storage as _;
+
+    // Failed deletes and floods don't end the tracking
+    let (server, queue) = flooded_bot_api(false).await;
+    let (notifier, dialogues) = test_notifier(queue).await;
+    let (events, receiver) = tokio::sync::mpsc::unbounded_channel();
+    for event in [
+        Event::Countdown { minutes: 5, leave_in: None },
+        Event::Countdown { minutes: 4, leave_in: None },
+        Event::Departed,
+    ] {
+        events.send(event).unwrap();
+    }
+    notifier.run(receiver).await.unwrap();
+    let methods: Vec<String> = server
+        .requests()
+        .iter()
+        .map(|r| r.path.rsplit('/').next().unwrap().to_lowercase())
+        .collect();
+    assert_eq!(
+        methods,
+        ["sendmessage", "sendmessage", "deletemessage", "sendmessage", "deletemessage", "sendmessage"]
+    );
+    assert!(dialogues.clone().get_dialogue(ChatId(CHAT_ID)).await.unwrap().is_none());
+
+    // Chats that can't be reached end it
+    let (server, queue) = flooded_bot_api(true).await;
+    let (notifier, dialogues) = test_notifier(queue).await;
+    let (events, receiver) = tokio::sync::mpsc::unbounded_channel();
+    events.send(Event::Countdown { minutes: 5, leave_in: None }).unwrap();
+    events.send(Event::Countdown { minutes: 4, leave_in: None }).unwrap();
+    assert!(notifier.run(receiver).await.is_err());
+    assert_eq!(server.requests().len(), 1);
+    assert!(dialogues.clone().get_dialogue(ChatId(CHAT_ID)).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_notifier_map() {
+    let (server, queue) = flooded_bot_api(false).await;
+    let (notifier, _dialogues) = test_notifier(queue).await;
+    let api = Api::new(server.url.clone(), server.url.clone(), "test-token".to_string());
+    let route = vec![location(52.521508, 13.411267)];
+    let (events, receiver) = tokio::sync::mpsc::unbounded_channel();
+    for position in [location(52.523405, 13.428069), location(52.5228, 13.4215)] {
+        events.send(Event::PositionUpdate { position, route: route.clone() }).unwrap();
+    }
+    events.send(Event::Vanished).unwrap();
+    notifier.with_map(api).run(receiver).await.unwrap();
+
+    // The map is sent once and then updated
+    let requests = server.requests();
+    let methods: Vec<String> = requests
+        .iter()
+        .map(|r| r.path.rsplit('/').next().unwrap().to_lowercase())
+        .collect();
+    assert_eq!(methods[..2], ["sendphoto", "editmessagemedia"]);
+    let photo = requests[0].form();
+    assert_eq!(photo["caption"], "Current position of the transit:");
+    assert!(photo["photo"].as_str().unwrap().contains("PNG"));
+    assert_eq!(requests[1].form()["media"]["type"], "photo");
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_tracker_countdown() {
+    let (mut tracker, clock, _source) = s5_tracker(NotifyPlan::Every(3), None);
+
+    let mut updates = vec![];
+    loop {
+        let (minute, events) = next_events(&mut tracker, &clock).await;
+        if events == vec![Event::Departed] {
+            assert_eq!(minute, 11);
+            break;
+        }
+        updates.push((minute, countdown(&events).unwrap()));
+    }
+    // The interval shrinks to the remaining time, then to a minute once arriving
+    assert_eq!(updates, vec![(0, 10), (3, 7), (6, 4), (9, 1), (10, 0)]);
+    assert_eq!(tracker.update_time(), 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_tracker_delay() {
+    let (mut tracker, clock, source) = s5_tracker(NotifyPlan::Every(5), None);
+    assert_eq!(countdown(&next_events(&mut tracker, &clock).await.1), Some(10));
+
+    source.set(vec![s5(Some(300))]);
+    let expected = vec![
+        Event::Delayed { minutes: 5 },
+        Event::Countdown {
+            minutes: 10,
+            leave_in: None,
+        },
+    ];
+    assert_eq!(next_events(&mut tracker, &clock).await, (5, expected));
+    // An unchanged delay is only reported once
+    assert_eq!(next_events(&mut tracker, &clock).await.1.len(), 1);
+    assert_eq!(countdown(&next_events(&mut tracker, &clock).await.1), Some(0));
+    assert_eq!(clock.now().minute(), 15);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_tracker_early_arrival() {
+    let (mut tracker, clock, source) = s5_tracker(NotifyPlan::Every(5), None);
+    assert_eq!(countdown(&next_events(&mut tracker, &clock).await.1), Some(10));
+
+    // Three minutes early, the next update comes before the interval is over
+    source.set(vec![s5(Some(-180))]);
+    let (minute, events) = next_events(&mut tracker, &clock).await;
+    assert_eq!((minute, countdown(&events)), (5, Some(2)));
+    assert_eq!(events[0], Event::Delayed { minutes: -3 });
+    assert_eq!(tracker.update_time(), 2);
+    let (minute, events) = next_events(&mut tracker, &clock).await;
+    assert_eq!((minute, countdown(&events)), (7, Some(0)));
+    assert_eq!(next_events(&mut tracker, &clock).await, (8, vec![Event::Departed]));
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_tracker_vanished_departure() {
+    let (mut tracker, clock, source) = s5_tracker(NotifyPlan::Every(2), None);
+    assert_eq!(countdown(&next_events(&mut tracker, &clock).await.1), Some(10));
+
+    source.set(vec![]);
+    assert_eq!(next_events(&mut tracker, &clock).await, (2, vec![Event::Vanished]));
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_tracker_same_line() {
+    let (mut tracker, clock, source) = s5_tracker(NotifyPlan::Every(2), None);
+    // An earlier S 5 of another trip to the same direction is not the tracked one
+    let earlier = TransitDeparture {
+        planned: "2023-01-10T16:04:00+01:00".to_string(),
+        trip_id: "1|5004|0|80|10012023".to_string(),
+        ..s5(None)
+    };
+    source.set(vec![earlier, s5(None)]);
+    assert_eq!(countdown(&next_events(&mut tracker, &clock).await.1), Some(10));
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_tracker_leave_alert() {
+    let (mut tracker, clock, _source) = s5_tracker(NotifyPlan::Every(3), Some(Duration::minutes(4)));
+
+    let (_, events) = next_events(&mut tracker, &clock).await;
+    assert_eq!(events, vec![Event::Countdown { minutes: 10, leave_in: Some(6) }]);
+    let (_, events) = next_events(&mut tracker, &clock).await;
+    assert_eq!(events, vec![Event::Countdown { minutes: 7, leave_in: Some(3) }]);
+
+    // The leave alert is sent once, followed by the countdown without leave info
+    let (minute, events) = next_events(&mut tracker, &clock).await;
+    assert_eq!(minute, 6);
+    assert_eq!(
+        events,
+        vec![
+            Event::Leave { minutes: 4 },
+            Event::Countdown { minutes: 4, leave_in: None }
+        ]
+    );
+    let (_, events) = next_events(&mut tracker, &clock).await;
+    assert_eq!(events, vec![Event::Countdown { minutes: 1, leave_in: None }]);
+}
+
+#[test]
+fn test_notify_plan_parse() {
+    assert_eq!(NotifyPlan::parse("4"), Some(NotifyPlan::Every(4)));
+    assert_eq!(NotifyPlan::parse(" 2, 10 5,5 "), Some(NotifyPlan::At(vec![10, 5, 2])));
+    assert_eq!(NotifyPlan::parse("10 5 2").unwrap().to_string(), "10, 5, 2");
+    assert_eq!(NotifyPlan::parse("10, 5, 2").unwrap().to_string(), "10, 5, 2");
+
+    for invalid in ["", "0", "61", "-2", "ten", "5, 0", "121, 5", "1 2 3 4 5 6"] {
+        assert_eq!(NotifyPlan::parse(invalid), None, "{}", invalid);
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_tracker_thresholds() {
+    let (mut tracker, clock, source) = s5_tracker(NotifyPlan::At(vec![5, 2]), None);
+
+    // The first update is always sent, then it sleeps until the next threshold
+    let (minute, events) = next_events(&mut tracker, &clock).await;
+    assert_eq!((minute, countdown(&events)), (0, Some(10)));
+    assert_eq!(tracker.update_time(), 5);
+
+    // A delay postpones the threshold
+    source.set(vec![s5(Some(180))]);
+    assert_eq!(next_events(&mut tracker, &clock).await, (5, vec![Event::Delayed { minutes: 3 }]));
+    assert_eq!(countdown(&next_events(&mut tracker, &clock).await.1), Some(5));
+    assert_eq!(clock.now().minute(), 8);
+    assert_eq!(countdown(&next_events(&mut tracker, &clock).await.1), Some(2));
+    assert_eq!(clock.now().minute(), 11);
+
+    // No countdown after the last threshold until the transit is gone
+    assert_eq!(next_events(&mut tracker, &clock).await, (13, vec![]));
+    assert_eq!(next_events(&mut tracker, &clock).await, (14, vec![Event::Departed]));
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_tracker_run_events() {
+    let (tracker, _clock, source) = s5_tracker(NotifyPlan::Every(5), None);
+    let position = Location {
+        lat: "52.52".to_string(),
+        lon: "13.41".to_string(),
+    };
+    source.set(vec![TransitDeparture {
+        curr_position: Some(position.clone()),
+        ..s5(None)
+    }]);
+
+    let (events, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+    let tracking = tokio::spawn(tracker.run(events));
+
+    // The position is only reported when it changes
+    assert_eq!(receiver.recv().await, Some(Event::PositionUpdate { position, route: vec![] }));
+    assert!(matches!(receiver.recv().await, Some(Event::Countdown { minutes: 10, .. })));
+    assert!(matches!(receiver.recv().await, Some(Event::Countdown { minutes: 5, .. })));
+
+    // The tracking ends with the cancellation of the transit
+    source.set(vec![TransitDeparture {
+        cancelled: true,
+        ..s5(None)
+    }]);
+    assert_eq!(receiver.recv().await, Some(Event::Cancelled));
+    assert_eq!(receiver.recv().await, None);
+    assert!(tracking.await.unwrap().is_ok());
+}
+
+/// Location with the given coordinates.
+fn location(lat: f64, lon: f64) -> Location {
+    Location {
+        lat: lat.to_string(),
+        lon: lon.to_string(),
+    }
+}
+
+#[test]
+fn test_remaining_route() {
+    // Ostbahnhof, Jannowitzbrücke and Alexanderplatz
+    let route = vec![
+        location(52.510972, 13.434567),
+        location(52.515503, 13.418027),
+        location(52.521508, 13.411267),
+    ];
+    // At a stop
+    assert_eq!(remaining_route(&route, &location(52.510972, 13.434567)), route);
+    // Between two stops, past the closest one
+    assert_eq!(remaining_route(&route, &location(52.513, 13.427)), route[1..]);
+    // Before the closest stop
+    assert_eq!(remaining_route(&route, &location(52.514, 13.421)), route[1..]);
+    assert_eq!(remaining_route(&route, &location(52.52, 13.412)), route[2..]);
+    // Past the tracked stop only the stop is left
+    assert_eq!(remaining_route(&route, &location(52.53, 13.40)), route[2..]);
+    assert!(remaining_route(&[], &location(52.53, 13.40)).is_empty());
+}
+
+#[tokio::test]
+async fn test_nearest_catchable_stop() {
+    let server = start_api_mock().await;
+    let trip = mock_api(&server)
+        .get_trip("1|200125|0|80|10012023".into(), "S 5".into())
+        .await
+        .unwrap();
+    let at = |h, m, s| Utc.with_ymd_and_hms(2023, 1, 10, h, m, s).unwrap();
+    let ostbahnhof = location(52.5108, 13.4348);
+
+    // Right next to Ostbahnhof, which the transit leaves at 16:02
+    let stop = nearest_catchable_stop(&trip, &ostbahnhof, at(15, 0, 0), 4.5).unwrap();
+    assert_eq!(stop.stop.id, "8089027");
+    assert_eq!(stop.stop.distance, 25);
+    // Too late for Ostbahnhof and too far from the other stops
+    assert!(nearest_catchable_stop(&trip, &ostbahnhof, at(15, 2, 30), 4.5).is_none());
+    // Jannowitzbrücke is cancelled, so Alexanderplatz is the nearest stop
+    let stop = nearest_catchable_stop(&trip, &location(52.5155, 13.4180), at(14, 50, 0), 4.5).unwrap();
+    assert_eq!(stop.stop.id, "8011155");
+}
+
+#[test]
+fn test_live_locations() {
+    let live = LiveLocations::default();
+    let key = (ChatId(CHAT_ID), UserId(USER_ID));
+    let start = Utc.with_ymd_and_hms(2023, 1, 10, 15, 0, 0).unwrap();
+    let live_location = LiveLocation {
+        location: location(52.52, 13.41),
+        keyboard: None,
+        suggested: None,
+        until: start + Duration::hours(1),
+    };
+    assert_eq!(live.moved(key, location(52.53, 13.41), start), None);
+    live.start(key, live_location, start);
+
+    // Moves below `MIN_MOVE` are left out
+    assert_eq!(live.moved(key, location(52.5205, 13.41), start), None);
+    let moved = live.moved(key, location(52.522, 13.41), start).unwrap();
+    assert_eq!(moved.location, location(52.522, 13.41));
+    assert_eq!(live.get(key).unwrap().location, location(52.522, 13.41));
+
+    assert!(live.suggest(key, "8089027"));
+    assert!(!live.suggest(key, "8089027"));
+    assert!(live.suggest(key, "8011155"));
+
+    // The location isn't followed anymore once it ended
+    assert_eq!(live.moved(key, location(52.53, 13.41), start + Duration::hours(2)), None);
+    assert_eq!(live.get(key), None);
+}
+
+#[tokio::test]
+async fn test_tracker_route() {
+    let server = start_api_mock().await;
+    let clock = TokioClock::starting_at(Utc.with_ymd_and_hms(2023, 1, 10, 15, 0, 0).unwrap());
+    let source = BoardSource {
+        api: mock_api(&server),
+        stop_id: "8011155".to_string(),
+        board: BoardKind::Departures,
+        products: Products::default(),
+        query: BoardQuery::default(),
+    };
+    let mut tracker = Tracker::new(
+        clock,
+        source,
+        "1|200125|0|80|10012023".to_string(),
+        NotifyPlan::Every(1),
+        None,
+    );
+
+    // The route runs from Jannowitzbrücke to the tracked Alexanderplatz
+    let events = tracker.poll().await.unwrap();
+    let route = events.iter().find_map(|e| match e {
+        Event::PositionUpdate { route, .. } => Some(route.clone()),
+        _ => None,
+    });
+    assert_eq!(
+        route,
+        Some(vec![location(52.515503, 13.418027), location(52.521508, 13.411267)])
+    );
+    let trips = server.requests().iter().filter(|r| r.path.starts_with("/trips/")).count();
+    assert_eq!(trips, 1);
+}
+
+//////////////////////////////////////////////////////////
+// Maps
+//////////////////////////////////////////////////////////
+/// Colors of the pixels of a rendered map.
+fn map_colors(png: &[u8]) -> std::collections::HashSet<[u8; 4]> {
+    let map = image::load_from_memory(png).unwrap().into_rgba8();
+    assert_eq!(map.dimensions(), (MAP_WIDTH, MAP_HEIGHT));
+    map.pixels().map(|p| p.0).collect()
+}
+
+#[tokio::test]
+async fn test_render_map() {
+    let vehicle = location(52.523405, 13.428069);
+    let route = [location(52.515503, 13.418027), location(52.521508, 13.411267)];
+    let (route_color, stop_color, vehicle_color) = ([30, 136, 229, 255], [229, 57, 53, 255], [33, 33, 33, 255]);
+
+    // Without a tile server the map is schematic
+    let server = start_api_mock().await;
+    let colors = map_colors(&render_map(&mock_api(&server), &vehicle, &route).await.unwrap());
+    assert!(colors.contains(&[242, 239, 233, 255]));
+    assert!(colors.contains(&route_color) && colors.contains(&stop_color) && colors.contains(&vehicle_color));
+    assert!(server.requests().is_empty());
+
+    // The tiles are fetched once
+    let mut tile = vec![];
+    image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(256, 256, image::Rgba([0, 200, 0, 255])))
+        .write_to(&mut std::io::Cursor::new(&mut tile), image::ImageOutputFormat::Png)
+        .unwrap();
+    let tiles = MockServer::start(move |_| Response::ok(tile.clone())).await;
+    let mut api = mock_api(&tiles);
+    api.tile_url = Some(format!("{}/tiles/{{z}}/{{x}}/{{y}}.png", tiles.url));
+    let colors = map_colors(&render_map(&api, &vehicle, &route).await.unwrap());
+    assert!(colors.contains(&[0, 200, 0, 255]));
+    assert!(!colors.contains(&[242, 239, 233, 255]));
+    assert!(colors.contains(&stop_color) && colors.contains(&vehicle_color));
+    // The closest zoom that shows the route
+    let requests = tiles.requests();
+    assert!(!requests.is_empty());
+    assert!(requests.iter().all(|r| r.path.starts_with("/tiles/15/")));
+
+    render_map(&api, &location(52.5234, 13.4281), &route).await.unwrap();
+    assert_eq!(tiles.requests().len(), requests.len());
+
+    // Maps of vehicles without a valid position aren't rendered
+    let unknown = Location {
+        lat: "unknown".to_string(),
+        ..vehicle
+    };
+    assert!(render_map(&api, &unknown, &route).await.is_err());
+}
+
+//////////////////////////////////////////////////////////
+// Dialogues
+//////////////////////////////////////////////////////////
+async fn fake_bot(name: &str) -> (MockServer, FakeBot) {
+    let server = start_api_mock().await;
+    let fake = FakeBot::start(mock_api(&server), temp_storage(name)).await;
+    (server, fake)
+}
+
+/// Stores the address of the fake user like a previous dialogue did.
+fn store_fake_user(fake: &FakeBot) {
+    fake.storage
+        .store_user_data(UserData {
+            id: USER_ID.to_string(),
+            city: "Berlin".to_string(),
+            addr: "Alexanderplatz 1".to_string(),
+            lat: "52.5219184".to_string(),
+            lon: "13.4132147".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_dialogue_new_user_tracking() {
+    let (_server, fake) = fake_bot("dialogue-new").await;
+
+    fake.send_text("/start").await.unwrap();
+    assert_eq!(fake.state().await, Some(State::ReceiveCity));
+
+    fake.send_text("Berlin").await.unwrap();
+    assert_eq!(
+        fake.state().await,
+        Some(State::ReceiveAddress {
+            city: "Berlin".to_string()
+        })
+    );
+
+    fake.send_text("Alexanderplatz 1").await.unwrap();
+    match fake.state().await {
+        Some(State::ReceiveStop { city, addr, stations, nearby }) => {
+            assert_eq!(city, "Berlin");
+            assert_eq!(addr, "Alexanderplatz 1");
+            assert_eq!(stations.len(), 3);
+            assert_eq!(nearby, Some(NearbyQuery::default()));
+        }
+        state => panic!("Unexpected state {:?}", state),
+    }
+    let user = fake.storage.get_user_data(USER_ID.to_string()).unwrap();
+    assert_eq!((user.lat.as_str(), user.lon.as_str()), ("52.5219184", "13.4132147"));
+
+    fake.press(ALEXANDERPLATZ).await.unwrap();
+    match fake.state().await {
+        Some(State::ReceiveTransit { stop, stop_id, board, .. }) => {
+            assert_eq!(stop, "Berlin Alexanderplatz");
+            assert_eq!(stop_id, "8011155");
+            assert_eq!(board, BoardKind::Departures);
+        }
+        state => panic!("Unexpected state {:?}", state),
+    }
+    // The buttons of the stations were removed
+    let edit = fake.calls_of("editmessagereplymarkup").pop().unwrap();
+    assert!(edit.buttons().is_empty());
+
+    fake.press("S 5 (S Westkreuz (Berlin))").await.unwrap();
+    assert!(matches!(
+        fake.state().await,
+        Some(State::ReceiveMinutes { transit, .. }) if transit == "S 5 (S Westkreuz (Berlin))"
+    ));
+    assert_eq!(fake.calls_of("deletemessage").len(), 1);
+
+    fake.press("1").await.unwrap();
+    assert!(matches!(fake.state().await, Some(State::ReceiveCancel { .. })));
+    assert!(fake.wait_for_text("arrives in <b>6</b> minutes").await);
+    assert!(fake.sent_texts().iter().any(|t| t.contains("is delayed by <b>2</b> minutes")));
+    assert_eq!(fake.tasks.lock().unwrap().len(), 1);
+    // The position is shown on a map instead of a location
+    let map = fake.calls_of("sendphoto").pop().unwrap();
+    assert_eq!(map.body["caption"], "Current position of the transit:");
+    assert!(fake.calls_of("sendlocation").is_empty());
+
+    fake.press("<< Cancel").await.unwrap();
+    assert_eq!(fake.state().await, None);
+    assert!(fake.tasks.lock().unwrap().is_empty());
+    assert!(fake.sent_texts().last().unwrap().contains("Cancelled"));
+}
+
+#[tokio::test]
+async fn test_dialogue_more_stations() {
+    let (server, fake) = fake_bot("dialogue-more-stations").await;
+    store_fake_user(&fake);
+
+    fake.send_text("/start").await.unwrap();
+    let stations = fake.calls_of("sendmessage").pop().unwrap();
+    assert_eq!(
+        stations.buttons(),
+        [
+            ALEXANDERPLATZ,
+            "Berlin, Alexanderplatz/Dircksenstr. · 245 m · 🚶 4 min · 🚌🚋",
+            "Spandauer Str./Marienkirche, Berlin · 410 m · 🚶 6 min · 🚌",
+            "🔍 More stations",
+            "<< Change address",
+        ]
+    );
+    // Stations are told apart by their id instead of their name
+    assert_eq!(stations.callback_data(ALEXANDERPLATZ).unwrap(), "station:8011155");
+
+    fake.press("🔍 More stations").await.unwrap();
+    let request = server.requests().into_iter().rev().find(|r| r.path == "/stops/nearby").unwrap();
+    assert!(request.query.contains("distance=2000&results=16"));
+    assert!(matches!(
+        fake.state().await,
+        Some(State::ReceiveStop { nearby: Some(NearbyQuery { radius: 2000, .. }), .. })
+    ));
+    // Nothing more was found, so the button is gone
+    let edit = fake.calls_of("editmessagereplymarkup").pop().unwrap();
+    assert_eq!(edit.buttons().len(), 4);
+    assert!(!edit.buttons().contains(&"🔍 More stations".to_string()));
+
+    fake.press(ALEXANDERPLATZ).await.unwrap();
+    assert!(matches!(
+        fake.state().await,
+        Some(State::ReceiveTransit { stop_id, .. }) if stop_id == "8011155"
+    ));
+}
+
+#[tokio::test]
+async fn test_dialogue_live_location_stations() {
+    // Alexanderplatz is farther away from the moved location
+    let server = MockServer::start(|request| match request.query.contains("latitude=52.526") {
+        true if request.path == "/stops/nearby" => Response::ok(NEARBY.replace("\"distance\": 120", "\"distance\": 620")),
+        _ => api_fixtures(request),
+    })
+    .await;
+    let fake = FakeBot::start(mock_api(&server), temp_storage("dialogue-live-stations")).await;
+
+    fake.send_text("/start").await.unwrap();
+    fake.send_text("Berlin").await.unwrap();
+    let message_id = fake.send_live_location(52.5219184, 13.4132147).await.unwrap();
+    let keyboard = fake.calls_of("sendmessage").pop().unwrap();
+    assert!(keyboard.buttons().contains(&ALEXANDERPLATZ.to_string()));
+
+    // Small moves don't search the stations again
+    fake.move_live_location(message_id, 52.5224, 13.4132147).await.unwrap();
+    assert_eq!(server.requests().iter().filter(|r| r.path == "/stops/nearby").count(), 1);
+
+    fake.move_live_location(message_id, 52.5265, 13.4132147).await.unwrap();
+    let edit = fake.calls_of("editmessagereplymarkup").pop().unwrap();
+    assert_eq!(edit.body["message_id"], keyboard.message_id.unwrap());
+    assert_eq!(edit.buttons()[0], "Berlin Alexanderplatz · 620 m · 🚶 9 min · 🚌🚋🚇🚈🚆");
+    assert!(matches!(
+        fake.state().await,
+        Some(State::ReceiveStop { stations, .. }) if stations[0].distance == 620
+    ));
+    // Address and stored location stay where the user shared the location first
+    let user = fake.storage.get_user_data(USER_ID.to_string()).unwrap();
+    assert_eq!(user.lat, "52.5219184");
+
+    fake.press("Berlin Alexanderplatz · 620 m · 🚶 9 min · 🚌🚋🚇🚈🚆").await.unwrap();
+    assert!(matches!(fake.state().await, Some(State::ReceiveTransit { .. })));
+}
+
+#[tokio::test]
+async fn test_dialogue_live_location_closer_stop() {
+    let (_server, fake) = fake_bot("dialogue-live-stop").await;
+    store_fake_user(&fake);
+
+    fake.send_text("/start").await.unwrap();
+    fake.press(ALEXANDERPLATZ).await.unwrap();
+    fake.press("S 5 (S Westkreuz (Berlin))").await.unwrap();
+    fake.press("1").await.unwrap();
+    assert!(matches!(fake.state().await, Some(State::ReceiveCancel { .. })));
+
+    // Shared next to Ostbahnhof, where the transit is a few minutes earlier
+    let message_id = fake.send_live_location(52.5108, 13.4348).await.unwrap();
+    let suggestion = fake.sent_texts().into_iter().find(|t| t.contains("nearest stop")).unwrap();
+    assert!(suggestion.contains("<b>Berlin Ostbahnhof</b> is the nearest stop to catch your transit: <b>S 5 (S Westkreuz (Berlin))</b>"));
+    assert!(suggestion.contains("25 m or about <b>1</b> minute on foot. It is there at <b>16:02</b>"));
+
+    // The same stop isn't suggested again, nor the tracked one
+    fake.move_live_location(message_id, 52.5118, 13.4348).await.unwrap();
+    fake.move_live_location(message_id, 52.5214, 13.4114).await.unwrap();
+    assert_eq!(fake.sent_texts().iter().filter(|t| t.contains("nearest stop")).count(), 1);
+    assert!(matches!(fake.state().await, Some(State::ReceiveCancel { .. })));
+
+    // The button comes with the first countdown of the tracking
+    assert!(fake.wait_for_text("arrives in").await);
+    fake.press("<< Cancel").await.unwrap();
+}
+
+#[tokio::test]
+async fn test_dialogue_track_arrival() {
+    let (server, fake) = fake_bot("dialogue-arrival").await;
+    store_fake_user(&fake);
+
+    fake.send_text("/start").await.unwrap();
+    fake.press(ALEXANDERPLATZ).await.unwrap();
+    fake.press("⇄ Arrivals").await.unwrap();
+    assert!(server.requests().iter().any(|r| r.path == "/stops/8011155/arrivals"));
+    let board = fake.calls_of("sendmessage").pop().unwrap();
+    assert!(board.buttons().contains(&"RE 1 (Frankfurt (Oder))".to_string()));
+
+    fake.press("RE 1 (Frankfurt (Oder))").await.unwrap();
+    fake.press("1").await.unwrap();
+    assert!(matches!(
+        fake.state().await,
+        Some(State::ReceiveCancel { board: BoardKind::Arrivals, .. })
+    ));
+    assert!(fake.wait_for_text("arrives in <b>8</b> minutes").await);
+
+    fake.press("<< Cancel").await.unwrap();
+    assert!(fake.tasks.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_dialogue_products_keep_one() {
+    let (_server, fake) = fake_bot("dialogue-products").await;
+    let only_bus = Products {
+        tram: false,
+        subway: false,
+        suburban: false,
+        regional: false,
+        national: false,
+        ..Default::default()
+    };
+    fake.storage
+        .store_user_data(UserData {
+            id: USER_ID.to_string(),
+            products: only_bus,
+            ..Default::default()
+        })
+        .unwrap();
+
+    fake.send_text("/products").await.unwrap();
+    fake.press("✅ Bus").await.unwrap();
+    // The last product stays on and the user is told why
+    let answer = fake.calls_of("answercallbackquery").pop().unwrap();
+    assert_eq!(answer.body["text"], "Keep at least one product, otherwise no transits can be found.");
+    assert!(fake.calls_of("editmessagereplymarkup").is_empty());
+    assert_eq!(fake.storage.get_user_data(USER_ID.to_string()).unwrap().products, only_bus);
+
+    fake.press("❌ Tram").await.unwrap();
+    assert!(fake.storage.get_user_data(USER_ID.to_string()).unwrap().products.tram);
+}
+
+#[tokio::test]
+async fn test_dialogue_track_later_board() {
+    // The later board has the next S 5 of the same line and direction, the tracking sees both
+    let mut departures: Vec<serde_json::Value> = serde_json::from_str(DEPARTURES).unwrap();
+    let mut later = departures[0].clone();
+    later["tripId"] = "1|200126|0|80|10012023".into();
+    later["plannedWhen"] = "2023-01-10T16:34:00+01:00".into();
+    later["when"] = "2023-01-10T16:36:00+01:00".into();
+    let later_board = serde_json::Value::from(vec![later.clone()]).to_string();
+    departures.push(later);
+    let both = serde_json::Value::from(departures).to_string();
+    let server = MockServer::start(move |request| match request.path.as_str() {
+        "/stops/8011155/departures" if request.query.contains("when=") => Response::ok(later_board.clone()),
+        "/stops/8011155/departures" => Response::ok(both.clone()),
+        _ => api_fixtures(request),
+    })
+    .await;
+    let fake = FakeBot::start(mock_api(&server), temp_storage("dialogue-track-later")).await;
+    store_fake_user(&fake);
+
+    fake.send_text("/start").await.unwrap();
+    fake.press(ALEXANDERPLATZ).await.unwrap();
+    fake.press("Later »").await.unwrap();
+    fake.press("S 5 (S Westkreuz (Berlin))").await.unwrap();
+    fake.press("1").await.unwrap();
+    match fake.state().await {
+        Some(State::ReceiveCancel { query, trip_id, .. }) => {
+            assert_eq!(query.when, None);
+            assert_eq!(trip_id, "1|200126|0|80|10012023");
+        }
+        state => panic!("Unexpected state {:?}", state),
+    }
+    // The countdown and the trip are the ones of the chosen S 5, not of the earlier one
+    assert!(fake.wait_for_text("arrives in <b>36</b> minutes").await);
+    fake.press("🛤 Trip").await.unwrap();
+    let trips: Vec<String> = server
+        .requests()
+        .into_iter()
+        .filter(|r| r.path.starts_with("/trips/"))
+        .map(|r| r.path)
+        .collect();
+    assert_eq!(trips.last().unwrap(), "/trips/1|200126|0|80|10012023");
+
+    // The tracking polls from now until the end of the later board instead of its window
+    let polled = server
+        .requests()
+        .into_iter()
+        .rev()
+        .find(|r| r.path == "/stops/8011155/departures")
+        .unwrap();
+    assert!(!polled.query.contains("when="));
+    assert!(polled.query.contains("duration=60"));
+    fake.press("<< Cancel").await.unwrap();
+}
+
+#[tokio::test]
+async fn test_dialogue_returning_user_changes_address() {
+    let (_server, fake) = fake_bot("dialogue-returning").await;
+    store_fake_user(&fake);
+
+    fake.send_text("/start").await.unwrap();
+    assert!(fake.sent_texts()[0].contains("I found your last used address"));
+    assert!(matches!(fake.state().await, Some(State::ReceiveStop { .. })));
+
+    fake.press("<< Change address").await.unwrap();
+    assert_eq!(
+        fake.state().await,
+        Some(State::ReceiveAddress {
+            city: "Berlin".to_string()
+        })
+    );
+    assert_eq!(fake.calls_of("editmessagetext").len(), 1);
+
+    fake.send_location(52.5219184, 13.4132147).await.unwrap();
+    assert!(matches!(
+        fake.state().await,
+        Some(State::ReceiveStop { addr, .. }) if addr == "Alexanderplatz 1"
+    ));
+}
+
+#[tokio::test]
+async fn test_dialogue_language_override() {
+    let (_server, fake) = fake_bot("dialogue-language").await;
+    store_fake_user(&fake);
+
+    fake.send_text("/language fr").await.unwrap();
+    assert!(fake.sent_texts()[0].contains("/language de"));
+
+    // The chosen language wins over the one of the Telegram app
+    fake.send_text("/language de").await.unwrap();
+    let user = fake.storage.get_user_data(USER_ID.to_string()).unwrap();
+    assert_eq!(user.language, Some(Lang::De));
+    assert_eq!(user.city, "Berlin");
+
+    fake.send_text("/start").await.unwrap();
+    assert!(fake.sent_texts()[2].contains("Ich habe deine zuletzt genutzte Adresse gefunden"));
+    fake.press("Berlin Alexanderplatz · 120 m · 🚶 2 Min. · 🚌🚋🚇🚈🚆").await.unwrap();
+    assert!(fake.sent_texts().iter().any(|t| t.contains("Abfahrten an der gewählten Haltestelle")));
+    fake.press("« Früher").await.unwrap();
+    fake.press("⇄ Ankünfte").await.unwrap();
+    assert!(matches!(
+        fake.state().await,
+        Some(State::ReceiveTransit { board: BoardKind::Arrivals, .. })
+    ));
+    fake.send_text("/cancel").await.unwrap();
+
+    fake.send_text("/language auto").await.unwrap();
+    let user = fake.storage.get_user_data(USER_ID.to_string()).unwrap();
+    assert_eq!(user.language, None);
+    assert!(fake.sent_texts().last().unwrap().contains("English"));
+}
+
+/// Number of buttons in each row of the inline keyboard sent with a call.
+fn keyboard_rows(call: &Call) -> Vec<usize> {
+    call.body["reply_markup"]["inline_keyboard"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|row| row.as_array().unwrap().len())
+        .collect()
+}
+
+#[tokio::test]
+async fn test_dialogue_settings() {
+    let (_server, fake) = fake_bot("dialogue-settings").await;
+    store_fake_user(&fake);
+
+    fake.send_text("/settings").await.unwrap();
+    assert_eq!(fake.state().await, Some(State::ReceiveSettings));
+    fake.press("⏱ Update intervals: 1, 2, 3 min").await.unwrap();
+    fake.press("⌨️ Buttons per row: 2").await.unwrap();
+    fake.press("🌙 Quiet hours: off").await.unwrap();
+    fake.press("🌐 Language: Telegram app").await.unwrap();
+    let menu = fake.calls_of("editmessagetext").pop().unwrap();
+    assert!(menu.buttons().contains(&"🌙 Quiet hours: 22:00–07:00".to_string()));
+    assert!(menu.buttons().contains(&"🌐 Language: English".to_string()));
+    fake.press("Done").await.unwrap();
+    assert_eq!(fake.state().await, None);
+
+    let user = fake.storage.get_user_data(USER_ID.to_string()).unwrap();
+    assert_eq!(user.city, "Berlin");
+    assert_eq!(user.language, Some(Lang::En));
+    assert_eq!(user.settings.intervals, vec![2, 5, 10]);
+    assert_eq!(user.settings.columns, 3);
+    assert_eq!(user.settings.quiet_hours, Some(QuietHours { from: 22, until: 7 }));
+
+    // The keyboards of the following dialogue use the settings
+    fake.send_text("/start").await.unwrap();
+    let stations = fake.calls_of("sendmessage").pop().unwrap();
+    assert_eq!(keyboard_rows(&stations), vec![3, 2]);
+    fake.press(ALEXANDERPLATZ).await.unwrap();
+    let transits = fake.calls_of("sendmessage").pop().unwrap();
+    assert!(keyboard_rows(&transits).iter().all(|&len| len <= 3));
+    assert_eq!(keyboard_rows(&transits)[0], 3);
+    fake.press("S 5 (S Westkreuz (Berlin))").await.unwrap();
+    let intervals = fake.calls_of("sendmessage").pop().unwrap();
+    assert_eq!(intervals.buttons()[..3], ["2", "5", "10"]);
+    fake.send_text("/cancel").await.unwrap();
+}
+
+#[tokio::test]
+async fn test_dialogue_privacy() {
+    let (_server, fake) = fake_bot("dialogue-privacy").await;
+    store_fake_user(&fake);
+
+    // Stricter modes also apply to the stored address
+    fake.send_text("/settings").await.unwrap();
+    fake.press("🔒 Stored location: address").await.unwrap();
+    let user = fake.storage.get_user_data(USER_ID.to_string()).unwrap();
+    assert_eq!((user.addr.as_str(), user.lat.as_str()), ("", "52.522"));
+    fake.send_text("/cancel").await.unwrap();
+    fake.send_text("/start").await.unwrap();
+    assert!(fake.sent_texts().pop().unwrap().contains("the area of your last used location in <b>Berlin"));
+    fake.send_text("/cancel").await.unwrap();
+
+    fake.send_text("/settings").await.unwrap();
+    fake.press("🔒 Stored location: rounded").await.unwrap();
+    fake.press("Done").await.unwrap();
+    let user = fake.storage.get_user_data(USER_ID.to_string()).unwrap();
+    assert_eq!(user.settings.privacy, Privacy::StopOnly);
+    assert!(!user.has_location());
+
+    // Only the selected stop is stored
+    fake.send_text("/start").await.unwrap();
+    assert_eq!(fake.state().await, Some(State::ReceiveCity));
+    fake.send_text("Berlin").await.unwrap();
+    fake.send_text("Alexanderplatz 1").await.unwrap();
+    let user = fake.storage.get_user_data(USER_ID.to_string()).unwrap();
+    assert!(user.addr.is_empty() && user.lat.is_empty() && user.lon.is_empty());
+    assert_eq!(user.last_active, Some(*fake.now.lock().unwrap()));
+    fake.press(ALEXANDERPLATZ).await.unwrap();
+    fake.send_text("/cancel").await.unwrap();
+    let user = fake.storage.get_user_data(USER_ID.to_string()).unwrap();
+    assert_eq!(user.stop.unwrap().id, "8011155");
+
+    fake.send_text("/start").await.unwrap();
+    let stations = fake.calls_of("sendmessage").pop().unwrap();
+    assert!(stations.text().contains("your last used stop: <b>Berlin Alexanderplatz"));
+    assert_eq!(stations.buttons(), ["Berlin Alexanderplatz", "<< Change address"]);
+    fake.press(ALEXANDERPLATZ).await.unwrap();
+    assert!(matches!(fake.state().await, Some(State::ReceiveTransit { stop_id, .. }) if stop_id == "8011155"));
+}
+
+#[tokio::test]
+async fn test_dialogue_notify_plan() {
+    let (_server, fake) = fake_bot("dialogue-plan").await;
+    store_fake_user(&fake);
+
+    fake.send_text("/start").await.unwrap();
+    fake.press(ALEXANDERPLATZ).await.unwrap();
+    fake.press("S 5 (S Westkreuz (Berlin))").await.unwrap();
+    let plans = fake.calls_of("sendmessage").pop().unwrap();
+    assert_eq!(plans.callback_data("🔔 10, 5, 2 min before").unwrap(), "10, 5, 2");
+
+    // Invalid plans are explained and can be sent again
+    fake.send_text("5, 0").await.unwrap();
+    assert!(fake.sent_texts().last().unwrap().contains("send me an interval from 1 to 60 minutes"));
+    assert!(matches!(fake.state().await, Some(State::ReceiveMinutes { .. })));
+
+    fake.send_text("2 5").await.unwrap();
+    assert!(matches!(fake.state().await, Some(State::ReceiveCancel { .. })));
+    assert!(fake.sent_texts().iter().any(|t| t.contains("I will notify you <b>5, 2</b> minutes before")));
+    assert!(fake.wait_for_text("arrives in <b>6</b> minutes").await);
+
+    // The buttons of the plans are not used anymore
+    fake.press("1").await.unwrap();
+    assert!(matches!(fake.state().await, Some(State::ReceiveCancel { .. })));
+    fake.press("<< Cancel").await.unwrap();
+    assert_eq!(fake.state().await, None);
+}
+
+#[tokio::test]
+async fn test_group_post_without_sender() {
+    let (_server, fake) = fake_bot("group-no-sender").await;
+    // Channel posts forwarded to a group have no sender
+    let msg: Message = serde_json::from_value(serde_json::json!({
+        "message_id": 1,
+        "date": 1673362800,
+        "chat": { "id": GROUP_ID, "type": "supergroup", "title": "Commuters" },
+        "sender_chat": { "id": -1009999, "type": "channel", "title": "News" },
+        "text": "/board"
+    }))
+    .unwrap();
+    assert_eq!(get_user_id(&msg), None);
+
+    group_board(fake.bot.clone(), msg.clone(), fake.api.clone(), fake.storage.clone()).await.unwrap();
+    assert!(fake.sent_texts().is_empty());
+    assert!(!check_group_admin(&fake.bot, &msg, Lang::En).await.unwrap());
+    assert!(fake.sent_texts()[0].contains("Only the admins"));
+}
+
+#[tokio::test]
+async fn test_dialogue_group() {
+    let (_server, fake) = fake_bot("dialogue-group").await;
+
+    fake.send_group_text(USER_ID, "/board").await.unwrap();
+    assert!(fake.sent_texts()[0].contains("This group has no stop yet"));
+    fake.send_group_text(MEMBER_ID, "/setstop Alexanderplatz").await.unwrap();
+    assert!(fake.sent_texts()[1].contains("Only the admins"));
+    fake.send_text("/setstop Alexanderplatz").await.unwrap();
+    assert!(fake.sent_texts()[2].contains("only works in group chats"));
+
+    fake.send_group_text(USER_ID, "/setstop Alexanderplatz").await.unwrap();
+    assert!(fake.sent_texts()[3].contains("is now <b>Berlin Alexanderplatz</b>"));
+    let group = fake.storage.get_user_data(GROUP_ID.to_string()).unwrap();
+    assert_eq!(group.group_stop.unwrap().id, "8011155");
+
+    // Chatting in the group is ignored
+    fake.send_group_text(MEMBER_ID, "who is coming?").await.unwrap();
+    assert_eq!(fake.sent_texts().len(), 4);
+
+    // Both members track a transit of the shared board
+    fake.send_group_text(MEMBER_ID, "/board").await.unwrap();
+    assert!(fake.sent_texts()[4].contains("Departures from selected station"));
+    let board = fake.calls_of("sendmessage").pop().unwrap();
+    assert_eq!(
+        board.callback_data("S 5 (S Westkreuz (Berlin))").unwrap(),
+        "board:1|200125|0|80|10012023"
+    );
+    fake.press_in_group(MEMBER_ID, "S 5 (S Westkreuz (Berlin))").await.unwrap();
+    fake.press_in_group(USER_ID, "S 5 (S Westkreuz (Berlin))").await.unwrap();
+    assert!(matches!(fake.group_state(MEMBER_ID).await, Some(State::ReceiveMinutes { .. })));
+    assert!(matches!(fake.group_state(USER_ID).await, Some(State::ReceiveMinutes { .. })));
+    assert_eq!(fake.state().await, Some(State::Start));
+
+    fake.press_in_group(MEMBER_ID, "2").await.unwrap();
+    fake.send_group_text(USER_ID, "10, 5").await.unwrap();
+    assert!(fake.wait_for_text("Test 4343</a>: 🔔 Your transit").await);
+    assert_eq!(fake.tasks.lock().unwrap().len(), 2);
+
+    // Cancelling only stops the tracking of the member
+    fake.send_group_text(MEMBER_ID, "/cancel").await.unwrap();
+    assert_eq!(fake.group_state(MEMBER_ID).await, None);
+    assert!(matches!(fake.group_state(USER_ID).await, Some(State::ReceiveCancel { .. })));
+    let tasks = fake.tasks.lock().unwrap();
+    assert!(tasks.contains_key(&(ChatId(GROUP_ID), UserId(USER_ID))));
+    assert_eq!(tasks.len(), 1);
+}
+
+#[tokio::test]
+async fn test_inline_query() {
+    let (server, fake) = fake_bot("inline").await;
+
+    fake.send_inline_query("Alexanderplatz").await.unwrap();
+    let answer = fake.calls_of("answerinlinequery").pop().unwrap();
+    let results = answer.body["results"].as_array().unwrap();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0]["type"], "article");
+    assert_eq!(results[0]["id"], "8011155");
+    assert_eq!(results[0]["title"], "Berlin Alexanderplatz");
+    assert!(results[0]["description"].as_str().unwrap().starts_with("16:06 S 5 → S Westkreuz (Berlin)"));
+    let board = results[0]["input_message_content"]["message_text"].as_str().unwrap();
+    assert!(board.contains("Departures from selected station (+ mins delay): <b>Berlin Alexanderplatz</b>"));
+    assert!(board.contains("<code>16:04</code> → <b>16:06</b> (+2) 🚈 <b>S 5</b>"));
+    assert_eq!(answer.body["is_personal"], true);
+
+    // Without a station the boards of the stations near the stored address are shown
+    fake.send_inline_query("").await.unwrap();
+    let answer = fake.calls_of("answerinlinequery").pop().unwrap();
+    assert!(answer.body["results"].as_array().unwrap().is_empty());
+    store_fake_user(&fake);
+    fake.send_inline_query(" ").await.unwrap();
+    let answer = fake.calls_of("answerinlinequery").pop().unwrap();
+    assert_eq!(answer.body["results"].as_array().unwrap().len(), 3);
+    assert!(server.requests().iter().any(|r| r.path == "/stops/nearby"));
+    // Using the bot in any way keeps the stored address
+    assert!(fake.storage.get_user_data(USER_ID.to_string()).unwrap().last_active.is_some());
+
+    // The boards only show the products of the user like in the dialogue
+    let mut user = fake.storage.get_user_data(USER_ID.to_string()).unwrap();
+    user.products.suburban = false;
+    fake.storage.store_user_data(user).unwrap();
+    fake.send_inline_query("Alexanderplatz").await.unwrap();
+    let answer = fake.calls_of("answerinlinequery").pop().unwrap();
+    let board = answer.body["results"][0]["input_message_content"]["message_text"].as_str().unwrap();
+    assert!(board.contains("<b>Bus 100</b>"));
+    assert!(!board.contains("S 5"));
+}
+
+#[tokio::test]
+async fn test_dialogue_admin() {
+    use teloxide::dispatching::dialogue::Storage as _;
+
+    let (_server, mut fake) = fake_bot("dialogue-admin").await;
+    fake.admins = Admins::new(HashSet::from([UserId(USER_ID), UserId(1)]));
+    store_fake_user(&fake);
+    for id in [MEMBER_ID.to_string(), GROUP_ID.to_string()] {
+        fake.storage
+            .store_user_data(UserData {
+                id,
+                ..Default::default()
+            })
+            .unwrap();
+    }
+
+    // Other users can't use the commands
+    fake.send_group_text(MEMBER_ID, "/stats").await.unwrap();
+    assert!(fake.sent_texts().is_empty());
+
+    fake.send_text("/stats").await.unwrap();
+    let stats = fake.sent_texts().pop().unwrap();
+    assert!(stats.contains("Users: <b>2</b>, groups: <b>1</b>"));
+    assert!(stats.contains("Active trackings: <b>0</b>"));
+    assert!(stats.contains("Transport API: 0 requests, 0.0 % errors"));
+
+    // Trackings of any user can be cancelled
+    fake.send_text("/trackings").await.unwrap();
+    assert_eq!(fake.sent_texts().pop().unwrap(), "There are no active trackings.");
+    let key = member_dialogue_key(ChatId(GROUP_ID), UserId(MEMBER_ID));
+    fake.dialogues
+        .clone()
+        .update_dialogue(key, State::ReceiveProducts)
+        .await
+        .unwrap();
+    let task = tokio::spawn(std::future::pending::<HandlerResult>());
+    fake.tasks
+        .lock()
+        .unwrap()
+        .insert((ChatId(GROUP_ID), UserId(MEMBER_ID)), task);
+    fake.send_text("/trackings").await.unwrap();
+    assert!(fake.sent_texts().pop().unwrap().contains("<b>1</b> active trackings"));
+    fake.press("✖ Chat -1001234, user 4343").await.unwrap();
+    assert!(fake.tasks.lock().unwrap().is_empty());
+    assert_eq!(fake.group_state(MEMBER_ID).await, None);
+    let cancelled = fake.calls_of("sendmessage");
+    assert_eq!(cancelled[cancelled.len() - 2].body["chat_id"], GROUP_ID);
+    assert!(cancelled[cancelled.len() - 2].text().contains("cancelled by the operators"));
+    fake.press("✖ Chat -1001234, user 4343").await.unwrap();
+    assert_eq!(fake.sent_texts().pop().unwrap(), "This tracking has already ended.");
+
+    // Stored data can be inspected and deleted
+    fake.send_text("/user 999").await.unwrap();
+    assert_eq!(fake.sent_texts().pop().unwrap(), "No data is stored for this user.");
+    fake.send_text(&format!("/user {}", MEMBER_ID)).await.unwrap();
+    assert!(fake.sent_texts().pop().unwrap().contains("<pre>{\n  \"id\": \"4343\""));
+    fake.press("🗑 Delete").await.unwrap();
+    assert!(fake.storage.get_user_data(MEMBER_ID.to_string()).is_err());
+    assert_eq!(fake.sent_texts().pop().unwrap(), "✅ Deleted the data of the user.");
+
+    // Broadcasts only go to users, not to groups
+    fake.send_text("/broadcast").await.unwrap();
+    assert!(fake.sent_texts().pop().unwrap().contains("Send me the message"));
+    fake.send_text("/broadcast New stations!").await.unwrap();
+    assert!(fake.wait_for_text("Sent the message to 1 of 1 users.").await);
+    let broadcasts: Vec<Call> = fake
+        .calls_of("sendmessage")
+        .into_iter()
+        .filter(|c| c.text() == "New stations!")
+        .collect();
+    assert_eq!(broadcasts.len(), 1);
+    assert_eq!(broadcasts[0].body["chat_id"], CHAT_ID);
+}
+
+#[tokio::test]
+async fn test_dialogue_my_data_and_forget() {
+    let (_server, fake) = fake_bot("dialogue-forget").await;
+
+    fake.send_text("/mydata").await.unwrap();
+    assert_eq!(fake.sent_texts().pop().unwrap(), "No data is stored about you.");
+
+    store_fake_user(&fake);
+    fake.send_text("/mydata").await.unwrap();
+    let export = fake.calls_of("senddocument").pop().unwrap();
+    assert_eq!(export.body["chat_id"], CHAT_ID);
+    assert_eq!(export.body["caption"], "📄 All data stored about you.");
+    assert_eq!(export.body["document"]["id"], USER_ID.to_string());
+    assert_eq!(export.body["document"]["addr"], "Alexanderplatz 1");
+    assert_eq!(export.body["document"]["lat"], "52.5219184");
+
+    // Stored data is never sent to groups
+    fake.send_group_text(USER_ID, "/mydata").await.unwrap();
+    assert!(fake.sent_texts().pop().unwrap().contains("only works in the private chat"));
+    assert_eq!(fake.calls_of("senddocument").len(), 1);
+
+    // The trackings and live locations of the user stop everywhere, the ones of others go on
+    let now = *fake.now.lock().unwrap();
+    for key in [
+        (ChatId(CHAT_ID), UserId(USER_ID)),
+        (ChatId(GROUP_ID), UserId(USER_ID)),
+        (ChatId(GROUP_ID), UserId(MEMBER_ID)),
+    ] {
+        let task = tokio::spawn(std::future::pending::<HandlerResult>());
+        fake.tasks.lock().unwrap().insert(key, task);
+        let live_location = LiveLocation {
+            location: location(52.52, 13.41),
+            keyboard: None,
+            suggested: None,
+            until: now + Duration::hours(1),
+        };
+        fake.live.start(key, live_location, now);
+    }
+    fake.send_text("/start").await.unwrap();
+    assert!(matches!(fake.state().await, Some(State::ReceiveStop { .. })));
+
+    fake.send_text("/forget").await.unwrap();
+    assert!(fake.sent_texts().pop().unwrap().contains("Deleted all data stored about you"));
+    assert!(fake.storage.get_user_data(USER_ID.to_string()).is_err());
+    assert_eq!(fake.state().await, None);
+    assert_eq!(fake.live.get((ChatId(CHAT_ID), UserId(USER_ID))), None);
+    assert_eq!(fake.live.get((ChatId(GROUP_ID), UserId(USER_ID))), None);
+    assert!(fake.live.get((ChatId(GROUP_ID), UserId(MEMBER_ID))).is_some());
+    let tasks = fake.tasks.lock().unwrap();
+    assert_eq!(tasks.len(), 1);
+    assert!(tasks.contains_key(&(ChatId(GROUP_ID), UserId(MEMBER_ID))));
+}
+
+#[tokio::test]
+async fn test_dialogue_throttle() {
+    let (server, mut fake) = fake_bot("dialogue-throttle").await;
+    fake.storage
+        .store_user_data(UserData {
+            id: MEMBER_ID.to_string(),
+            city: "Berlin".to_string(),
+            addr: "Alexanderplatz 1".to_string(),
+            lat: "52.5219184".to_string(),
+            lon: "13.4132147".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+    fake.throttle = Throttle::new(2, 100);
+
+    fake.send_text_as(MEMBER_ID, "/start").await.unwrap();
+    fake.press_as(MEMBER_ID, ALEXANDERPLATZ).await.unwrap();
+    let requests = server.requests().len();
+
+    // Throttled updates don't reach the APIs, only the first message is answered
+    fake.send_text_as(MEMBER_ID, "17:30").await.unwrap();
+    assert!(fake.sent_texts().pop().unwrap().contains("Try again in 30 seconds."));
+    fake.send_text_as(MEMBER_ID, "17:30").await.unwrap();
+    assert_eq!(fake.sent_texts().iter().filter(|t| t.contains("Slow down")).count(), 1);
+
+    // Buttons are always answered, otherwise they keep loading
+    fake.press_as(MEMBER_ID, "S 5 (S Westkreuz (Berlin))").await.unwrap();
+    let answer = fake.calls_of("answercallbackquery").pop().unwrap();
+    assert!(answer.body["text"].as_str().unwrap().starts_with("⏳ Slow down a little"));
+    assert_eq!(server.requests().len(), requests);
+
+    // Operators are never throttled
+    fake.admins = Admins::new(HashSet::from([UserId(USER_ID)]));
+    for _ in 0..3 {
+        fake.send_text("/stats").await.unwrap();
+        assert!(fake.sent_texts().pop().unwrap().contains("Statistics"));
+    }
+}
+
+#[tokio::test]
+async fn test_dialogue_throttle_group_and_inline() {
+    let (_server, mut fake) = fake_bot("dialogue-throttle-group").await;
+    fake.storage
+        .store_user_data(UserData {
+            id: GROUP_ID.to_string(),
+            group_stop: Some(SavedStop {
+                id: "8011155".to_string(),
+                name: "Berlin Alexanderplatz".to_string(),
+            }),
+            ..Default::default()
+        })
+        .unwrap();
+    fake.throttle = Throttle::new(2, 100);
+
+    // Chatting in the group isn't for the bot and costs nothing
+    for _ in 0..5 {
+        fake.send_group_text(MEMBER_ID, "who is coming?").await.unwrap();
+    }
+    fake.send_group_text(MEMBER_ID, "/board").await.unwrap();
+    assert!(fake.sent_texts()[0].contains("Departures from selected station"));
+
+    // Throttled inline queries are answered with nothing, so they don't keep loading
+    fake.send_inline_query("Alexanderplatz").await.unwrap();
+    fake.send_inline_query("Alexanderplatz").await.unwrap();
+    fake.send_inline_query("Alexanderplatz").await.unwrap();
+    let answer = fake.calls_of("answerinlinequery").pop().unwrap();
+    assert!(answer.body["results"].as_array().unwrap().is_empty());
+    assert_eq!(answer.body["is_personal"], true);
+    assert!(answer.body["cache_time"].as_u64().unwrap() > 0);
+}
+
+#[tokio::test]
+async fn test_dialogue_throttle_live_location() {
+    let (server, mut fake) = fake_bot("dialogue-throttle-live").await;
+    fake.throttle = Throttle::new(4, 100);
+    let nearby = || server.requests().iter().filter(|r| r.path == "/stops/nearby").count();
+
+    fake.send_text("/start").await.unwrap();
+    fake.send_text("Berlin").await.unwrap();
+    let message_id = fake.send_live_location(52.5219184, 13.4132147).await.unwrap();
+    assert_eq!(nearby(), 1);
+
+    // Edits that don't move the location far enough cost nothing
+    for _ in 0..5 {
+        fake.move_live_location(message_id, 52.5224, 13.4132147).await.unwrap();
+    }
+    fake.move_live_location(message_id, 52.5265, 13.4132147).await.unwrap();
+    assert_eq!(nearby(), 2);
+
+    // Throttled moves are left out without a cooldown message
+    fake.move_live_location(message_id, 52.531, 13.4132147).await.unwrap();
+    assert_eq!(nearby(), 2);
+    assert!(!fake.sent_texts().iter().any(|t| t.contains("Slow down")));
+}
+
+#[tokio::test]
+async fn test_dialogue_max_trackings() {
+    let (_server, fake) = fake_bot("dialogue-max-trackings").await;
+    store_fake_user(&fake);
+    for chat_id in [-1, -2, -3] {
+        let task = tokio::spawn(std::future::pending::<HandlerResult>());
+        fake.tasks.lock().unwrap().insert((ChatId(chat_id), UserId(USER_ID)), task);
+    }
+
+    fake.send_text("/start").await.unwrap();
+    fake.press(ALEXANDERPLATZ).await.unwrap();
+    fake.press("S 5 (S Westkreuz (Berlin))").await.unwrap();
+    fake.press("2").await.unwrap();
+    assert!(fake.sent_texts().pop().unwrap().contains("You already track 3 transits"));
+    assert_eq!(fake.state().await, None);
+    assert!(!fake.tasks.lock().unwrap().contains_key(&(ChatId(CHAT_ID), UserId(USER_ID))));
+
+    // Finished trackings don't count
+    fake.tasks.lock().unwrap().remove(&(ChatId(-1), UserId(USER_ID))).unwrap().abort();
+    fake.send_text("/start").await.unwrap();
+    fake.press(ALEXANDERPLATZ).await.unwrap();
+    fake.press("S 5 (S Westkreuz (Berlin))").await.unwrap();
+    fake.press("2").await.unwrap();
+    assert!(matches!(fake.state().await, Some(State::ReceiveCancel { .. })));
+    fake.send_text("/cancel").await.unwrap();
+}
+
+#[tokio::test]
+async fn test_dialogue_shutdown() {
+    let (_server, fake) = fake_bot("dialogue-shutdown").await;
+    store_fake_user(&fake);
+
+    fake.send_text("/start").await.unwrap();
+    fake.press(ALEXANDERPLATZ).await.unwrap();
+    fake.press("S 5 (S Westkreuz (Berlin))").await.unwrap();
+    fake.press("1").await.unwrap();
+    assert!(fake.wait_for_text("arrives in <b>6</b> minutes").await);
+    let countdown = fake
+        .calls_of("sendmessage")
+        .into_iter()
+        .rfind(|c| c.text().contains("arrives in"))
+        .unwrap();
+
+    shut_down(fake.tasks.clone(), fake.queue.clone()).await;
+    assert!(fake.tasks.lock().unwrap().is_empty());
+    assert_eq!(fake.state().await, None);
+    // The countdown says that the tracking ended, without its buttons
+    let stopped = fake.calls_of("editmessagetext").pop().unwrap();
+    assert_eq!(stopped.body["message_id"].as_i64(), countdown.message_id.map(i64::from));
+    assert!(stopped.text().contains("<b>S 5 (S Westkreuz (Berlin))</b> 🚌 has ended"));
+    assert!(stopped.buttons().is_empty());
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_shut_down_timeout() {
+    let queue = SendQueue::new(Bot::new("1234:TEST"), SendLimits::default());
+    let tasks: MyTasksMap = Arc::new(Mutex::new(HashMap::new()));
+    let paused = {
+        let queue = queue.clone();
+        tokio::spawn(async move {
+            queue.closed().await;
+            Ok(())
+        })
+    };
+    let stuck = tokio::spawn(std::future::pending::<HandlerResult>());
+    tasks.lock().unwrap().insert((ChatId(1), UserId(1)), paused);
+    tasks.lock().unwrap().insert((ChatId(2), UserId(2)), stuck);
+
+    let start = tokio::time::Instant::now();
+    shut_down(tasks.clone(), queue).await;
+    assert_eq!(start.elapsed(), SHUTDOWN_TIMEOUT);
+    assert!(tasks.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_dialogue_board_paging() {
+    let (_server, fake) = fake_bot("dialogue-paging").await;
+    store_fake_user(&fake);
+
+    fake.send_text("/start").await.unwrap();
+    fake.press(ALEXANDERPLATZ).await.unwrap();
+    // Real-time departures are shown next to the planned ones
+    let board = fake.sent_texts().into_iter().find(|t| t.contains("Departures from")).unwrap();
+    assert!(board.contains(
+        "\n🟡 <code>16:04</code> → <b>16:06</b> (+2) 🚈 <b>S 5</b> to <b>S Westkreuz (Berlin)</b>\n"
+    ));
+    assert!(board.contains("\n🟢 <code>16:10</code> 🚌 <b>Bus 100</b> to <b>S+U Zoologischer Garten</b>\n"));
+    assert!(board.contains("\n❌ <code>16:17</code> (cancelled) 🚆 <b>RE 1</b> to <b>Magdeburg Hbf</b>"));
+
+    // Typed times are in the timezone of the stations
+    fake.send_text("17:30").await.unwrap();
+    match fake.state().await {
+        Some(State::ReceiveTransit { query, .. }) => {
+            assert_eq!(query.when, Some(Utc.with_ymd_and_hms(2023, 1, 10, 16, 30, 0).unwrap()));
+        }
+        state => panic!("Unexpected state {:?}", state),
+    }
+    assert!(fake.sent_texts().iter().any(|t| t.contains("🕒 From <b>10.01. 17:30</b>")));
+
+    fake.press("⇄ Arrivals").await.unwrap();
+    assert!(matches!(
+        fake.state().await,
+        Some(State::ReceiveTransit { board: BoardKind::Arrivals, .. })
+    ));
+    assert!(fake.sent_texts().iter().any(|t| t.contains("from <b>Frankfurt (Oder)</b>")));
+
+    fake.press("Later »").await.unwrap();
+    match fake.state().await {
+        Some(State::ReceiveTransit { query, .. }) => {
+            assert_eq!(query.when, Some(Utc.with_ymd_and_hms(2023, 1, 10, 17, 0, 0).unwrap()));
+        }
+        state => panic!("Unexpected state {:?}", state),
+    }
+}
+
+#[tokio::test]
+async fn test_dialogue_cancel_and_invalid_messages() {
+    let (_server, fake) = fake_bot("dialogue-cancel").await;
+
+    fake.send_text("hello").await.unwrap();
+    assert!(fake.sent_texts()[0].contains("Unable to handle the message"));
+    assert_eq!(fake.state().await, Some(State::Start));
+
+    fake.send_text("/start").await.unwrap();
+    fake.send_text("/cancel").await.unwrap();
+    assert_eq!(fake.state().await, None);
+    assert!(fake.sent_texts().last().unwrap().contains("Cancelled"));
+}
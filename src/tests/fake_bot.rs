@@ -0,0 +1,407 @@
+use std::{
+    sync::{
+        atomic::{AtomicI32, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use chrono::{DateTime, TimeZone, Utc};
+use serde_json::{json, Value};
+use teloxide::{dispatching::dialogue::Storage as _, types::Me};
+
+use super::mock_server::*;
+use crate::*;
+
+//////////////////////////////////////////////////////////
+// Fake Telegram Bot API
+//////////////////////////////////////////////////////////
+pub const CHAT_ID: i64 = 4242;
+pub const USER_ID: u64 = 4242;
+/// Group in which the user is the owner, next to a member who is no admin.
+pub const GROUP_ID: i64 = -1001234;
+pub const MEMBER_ID: u64 = 4343;
+
+/// Method call of the bot received by the fake Bot API.
+#[derive(Clone, Debug)]
+pub struct Call {
+    /// Lowercase method name, e.g. `sendmessage`
+    pub method: String,
+    pub body: Value,
+    /// Id of the message sent by this call
+    pub message_id: Option<i32>,
+}
+
+impl Call {
+    pub fn text(&self) -> &str {
+        self.body["text"].as_str().unwrap_or_default()
+    }
+
+    /// Labels of the inline keyboard buttons sent with this call.
+    pub fn buttons(&self) -> Vec<String> {
+        self.button_values()
+            .iter()
+            .filter_map(|b| b["text"].as_str().map(|t| t.to_string()))
+            .collect()
+    }
+
+    /// Callback data of the button with the given label.
+    pub fn callback_data(&self, label: &str) -> Option<String> {
+        self.button_values()
+            .iter()
+            .find(|b| b["text"] == label)
+            .and_then(|b| b["callback_data"].as_str().map(|d| d.to_string()))
+    }
+
+    fn button_values(&self) -> Vec<Value> {
+        self.body["reply_markup"]["inline_keyboard"]
+            .as_array()
+            .map(|rows| {
+                rows.iter()
+                    .flat_map(|row| row.as_array().cloned().unwrap_or_default())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// Telegram Bot API that records the calls of the bot and answers them with plausible
+/// results, while updates are injected directly into the handler tree of the bot.
+pub struct FakeBot {
+    pub bot: Bot,
+    pub dialogues: Arc<InMemStorage<State>>,
+    pub tasks: MyTasksMap,
+    pub api: Api,
+    pub storage: Storage,
+    /// Budgets large enough for any test, unless a test sets smaller ones
+    pub throttle: Throttle,
+    pub queue: SendQueue,
+    pub live: LiveLocations,
+    /// Nobody is an operator, unless a test sets some
+    pub admins: Admins,
+    /// Date of injected messages
+    pub now: Mutex<DateTime<Utc>>,
+    calls: Arc<Mutex<Vec<Call>>>,
+    next_id: Arc<AtomicI32>,
+    _server: MockServer,
+}
+
+impl FakeBot {
+    pub async fn start(api: Api, storage: Storage) -> Self {
+        let calls: Arc<Mutex<Vec<Call>>> = Arc::new(Mutex::new(vec![]));
+        let next_id = Arc::new(AtomicI32::new(1));
+
+        let recorded = calls.clone();
+        let ids = next_id.clone();
+        let server = MockServer::start(move |request| {
+            let method = request.path.rsplit('/').next().unwrap_or_default().to_lowercase();
+            // Files are uploaded as multipart forms
+            let body = match request.form() {
+                serde_json::Value::Null => request.json(),
+                form => form,
+            };
+            let (message_id, result) = match method.as_str() {
+                "sendmessage" | "sendlocation" | "sendphoto" | "senddocument" => {
+                    let id = ids.fetch_add(1, Ordering::SeqCst);
+                    (Some(id), bot_message(id, &body))
+                }
+                "editmessagetext" | "editmessagereplymarkup" | "editmessagemedia" => {
+                    let id = body["message_id"].as_i64().unwrap_or_default() as i32;
+                    (None, bot_message(id, &body))
+                }
+                "getchatmember" => {
+                    let user_id = body["user_id"].as_u64().unwrap_or_default();
+                    let mut member = match user_id {
+                        USER_ID => json!({ "status": "creator", "is_anonymous": false }),
+                        _ => json!({ "status": "member" }),
+                    };
+                    member["user"] = user(user_id);
+                    (None, member)
+                }
+                _ => (None, json!(true)),
+            };
+            recorded.lock().unwrap().push(Call {
+                method,
+                body,
+                message_id,
+            });
+            Response::ok(json!({ "ok": true, "result": result }).to_string())
+        })
+        .await;
+
+        let bot = Bot::new("1234:TEST").set_api_url(reqwest::Url::parse(&server.url).unwrap());
+        // The fake Bot API has no limits
+        let limits = SendLimits {
+            private_chat: Duration::ZERO,
+            group_chat: Duration::ZERO,
+            global: Duration::ZERO,
+        };
+        let queue = SendQueue::new(bot.clone(), limits);
+
+        FakeBot {
+            bot,
+            dialogues: InMemStorage::<State>::new(),
+            tasks: Arc::new(Mutex::new(HashMap::new())),
+            api,
+            storage,
+            throttle: Throttle::new(1000, 1000),
+            queue,
+            live: LiveLocations::default(),
+            admins: Admins::default(),
+            now: Mutex::new(Utc.with_ymd_and_hms(2023, 1, 10, 15, 0, 0).unwrap()),
+            calls,
+            next_id,
+            _server: server,
+        }
+    }
+
+    /// Runs an update through the handler tree like the dispatcher does.
+    pub async fn dispatch(&self, update: Value) -> HandlerResult {
+        // Updates only deserialize from strings, not from values
+        let update: Update = serde_json::from_str(&update.to_string()).unwrap();
+        let me: Me = serde_json::from_value(json!({
+            "id": 1234,
+            "is_bot": true,
+            "first_name": "wimt",
+            "username": "wimt_bot",
+            "can_join_groups": true,
+            "can_read_all_group_messages": false,
+            "supports_inline_queries": false
+        }))
+        .unwrap();
+
+        let result = schema()
+            .dispatch(deps![
+                self.bot.clone(),
+                me,
+                update,
+                self.dialogues.clone(),
+                self.tasks.clone(),
+                self.api.clone(),
+                self.storage.clone(),
+                self.throttle.clone(),
+                self.queue.clone(),
+                self.live.clone(),
+                self.admins.clone()
+            ])
+            .await;
+        match result {
+            std::ops::ControlFlow::Break(result) => result,
+            std::ops::ControlFlow::Continue(_) => Err("Update was not handled!")?,
+        }
+    }
+
+    /// Sends a text message of the user.
+    pub async fn send_text(&self, text: &str) -> HandlerResult {
+        self.send_text_in(CHAT_ID, USER_ID, text).await
+    }
+
+    /// Sends a text message of another user in their private chat.
+    pub async fn send_text_as(&self, user_id: u64, text: &str) -> HandlerResult {
+        self.send_text_in(user_id as i64, user_id, text).await
+    }
+
+    /// Sends a text message of a user in the group.
+    pub async fn send_group_text(&self, user_id: u64, text: &str) -> HandlerResult {
+        self.send_text_in(GROUP_ID, user_id, text).await
+    }
+
+    async fn send_text_in(&self, chat_id: i64, user_id: u64, text: &str) -> HandlerResult {
+        let mut message = self.user_message(chat_id, user_id);
+        message["text"] = json!(text);
+        if text.starts_with('/') {
+            let len = text.split(' ').next().unwrap().len();
+            message["entities"] = json!([{ "type": "bot_command", "offset": 0, "length": len }]);
+        }
+        self.dispatch(json!({ "update_id": self.next_id(), "message": message }))
+            .await
+    }
+
+    /// Shares a location of the user.
+    pub async fn send_location(&self, lat: f64, lon: f64) -> HandlerResult {
+        let mut message = self.user_message(CHAT_ID, USER_ID);
+        message["location"] = json!({ "latitude": lat, "longitude": lon });
+        self.dispatch(json!({ "update_id": self.next_id(), "message": message }))
+            .await
+    }
+
+    /// Starts sharing a live location of the user, returns the id of its message.
+    pub async fn send_live_location(&self, lat: f64, lon: f64) -> Result<i32, Box<dyn Error + Send + Sync>> {
+        let mut message = self.user_message(CHAT_ID, USER_ID);
+        message["location"] = json!({ "latitude": lat, "longitude": lon, "live_period": 3600 });
+        let id = message["message_id"].as_i64().unwrap() as i32;
+        self.dispatch(json!({ "update_id": self.next_id(), "message": message }))
+            .await?;
+        Ok(id)
+    }
+
+    /// Moves the live location of the user in the message with the given id.
+    pub async fn move_live_location(&self, message_id: i32, lat: f64, lon: f64) -> HandlerResult {
+        let mut message = self.user_message(CHAT_ID, USER_ID);
+        message["message_id"] = json!(message_id);
+        message["edit_date"] = message["date"].clone();
+        message["location"] = json!({ "latitude": lat, "longitude": lon, "live_period": 3600 });
+        self.dispatch(json!({ "update_id": self.next_id(), "edited_message": message }))
+            .await
+    }
+
+    /// Types `@wimt_bot <query>` in any chat.
+    pub async fn send_inline_query(&self, query: &str) -> HandlerResult {
+        self.dispatch(json!({
+            "update_id": self.next_id(),
+            "inline_query": {
+                "id": self.next_id().to_string(),
+                "from": user(USER_ID),
+                "query": query,
+                "offset": ""
+            }
+        }))
+        .await
+    }
+
+    /// Presses the button with the given label of the last message with an inline keyboard.
+    pub async fn press(&self, label: &str) -> HandlerResult {
+        self.press_in(CHAT_ID, USER_ID, label).await
+    }
+
+    /// Presses a button of the last message in the private chat of another user.
+    pub async fn press_as(&self, user_id: u64, label: &str) -> HandlerResult {
+        self.press_in(user_id as i64, user_id, label).await
+    }
+
+    /// Presses a button of the last message in the group with it as a user of the group.
+    pub async fn press_in_group(&self, user_id: u64, label: &str) -> HandlerResult {
+        self.press_in(GROUP_ID, user_id, label).await
+    }
+
+    async fn press_in(&self, chat_id: i64, user_id: u64, label: &str) -> HandlerResult {
+        let call = self
+            .calls()
+            .into_iter()
+            .rev()
+            .filter(|c| c.body["chat_id"] == chat_id)
+            .find(|c| c.buttons().iter().any(|b| b == label))
+            .unwrap_or_else(|| panic!("No button {}", label));
+        let message_id = call
+            .message_id
+            .or_else(|| call.body["message_id"].as_i64().map(|id| id as i32))
+            .unwrap();
+
+        let mut message = bot_message(message_id, &call.body);
+        message["date"] = json!(self.now.lock().unwrap().timestamp());
+        self.dispatch(json!({
+            "update_id": self.next_id(),
+            "callback_query": {
+                "id": self.next_id().to_string(),
+                "from": user(user_id),
+                "message": message,
+                "chat_instance": "1",
+                "data": call.callback_data(label)
+            }
+        }))
+        .await
+    }
+
+    pub fn calls(&self) -> Vec<Call> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    /// Calls of the given method, e.g. `sendmessage`.
+    pub fn calls_of(&self, method: &str) -> Vec<Call> {
+        self.calls()
+            .into_iter()
+            .filter(|c| c.method == method)
+            .collect()
+    }
+
+    /// Texts of all messages sent by the bot.
+    pub fn sent_texts(&self) -> Vec<String> {
+        self.calls_of("sendmessage")
+            .iter()
+            .map(|c| c.text().to_string())
+            .collect()
+    }
+
+    /// Waits until a message containing `text` was sent, `false` on timeout.
+    pub async fn wait_for_text(&self, text: &str) -> bool {
+        for _ in 0..100 {
+            if self.sent_texts().iter().any(|t| t.contains(text)) {
+                return true;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        false
+    }
+
+    /// Dialogue state of the chat, `None` if the dialogue was exited.
+    pub async fn state(&self) -> Option<State> {
+        self.dialogues
+            .clone()
+            .get_dialogue(ChatId(CHAT_ID))
+            .await
+            .unwrap()
+    }
+
+    /// Dialogue state of a member of the group.
+    pub async fn group_state(&self, user_id: u64) -> Option<State> {
+        let mut message = self.user_message(GROUP_ID, user_id);
+        message["text"] = json!("");
+        let update = json!({ "update_id": 0, "message": message });
+        let update: Update = serde_json::from_str(&update.to_string()).unwrap();
+        self.dialogues
+            .clone()
+            .get_dialogue(dialogue_key(&update).unwrap())
+            .await
+            .unwrap()
+    }
+
+    fn next_id(&self) -> i32 {
+        self.next_id.fetch_add(1, Ordering::SeqCst)
+    }
+
+    fn user_message(&self, chat_id: i64, user_id: u64) -> Value {
+        json!({
+            "message_id": self.next_id(),
+            "date": self.now.lock().unwrap().timestamp(),
+            "chat": chat(chat_id),
+            "from": user(user_id)
+        })
+    }
+}
+
+fn user(id: u64) -> Value {
+    json!({ "id": id, "is_bot": false, "first_name": format!("Test {}", id), "language_code": "en" })
+}
+
+fn chat(id: i64) -> Value {
+    match id {
+        GROUP_ID => json!({ "id": id, "type": "supergroup", "title": "Commuters" }),
+        _ => json!({ "id": id, "type": "private", "first_name": "Test" }),
+    }
+}
+
+/// Message of the bot as returned by the Bot API for a call with the given parameters.
+fn bot_message(id: i32, body: &Value) -> Value {
+    let mut message = json!({
+        "message_id": id,
+        "date": 0,
+        "chat": chat(body["chat_id"].as_i64().unwrap_or(CHAT_ID)),
+        "from": { "id": 1234, "is_bot": true, "first_name": "wimt", "username": "wimt_bot" }
+    });
+    if let (Some(lat), Some(lon)) = (body["latitude"].as_f64(), body["longitude"].as_f64()) {
+        message["location"] = json!({ "latitude": lat, "longitude": lon });
+    } else if !body["document"].is_null() {
+        message["document"] = json!({ "file_id": id.to_string(), "file_unique_id": id.to_string() });
+    } else if !body["photo"].is_null() || body["media"]["type"] == "photo" {
+        let photo = json!({ "file_id": id.to_string(), "file_unique_id": id.to_string(), "width": 640, "height": 480 });
+        message["photo"] = json!([photo]);
+        message["caption"] = json!(body["caption"].as_str().or(body["media"]["caption"].as_str()));
+    } else {
+        message["text"] = json!(body["text"].as_str().unwrap_or_default());
+    }
+    // Only inline keyboards are part of the returned message
+    if body["reply_markup"]["inline_keyboard"].is_array() {
+        message["reply_markup"] = body["reply_markup"].clone();
+    }
+    message
+}
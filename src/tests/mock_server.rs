@@ -0,0 +1,274 @@
+use std::{
+    error::Error,
+    sync::{Arc, Mutex},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+//////////////////////////////////////////////////////////
+// Mock HTTP server
+//////////////////////////////////////////////////////////
+/// Request received by the mock server.
+#[derive(Clone, Debug, Default)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    /// Raw query string without the leading `?`
+    pub query: String,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn json(&self) -> serde_json::Value {
+        serde_json::from_slice(&self.body).unwrap_or_default()
+    }
+
+    /// Fields of a `multipart/form-data` body as a JSON object, with the fields and files that
+    /// contain JSON parsed and all others as strings, and attached files in place of the fields
+    /// referencing them. `Null` if the body isn't multipart.
+    pub fn form(&self) -> serde_json::Value {
+        let body = String::from_utf8_lossy(&self.body);
+        let boundary = match body.split("\r\n").next() {
+            Some(b) if b.starts_with("--") => b.to_string(),
+            _ => return serde_json::Value::Null,
+        };
+
+        let mut fields = serde_json::Map::new();
+        for part in body.split(boundary.as_str()) {
+            let (head, content) = match part.split_once("\r\n\r\n") {
+                Some(p) => p,
+                None => continue,
+            };
+            let name = match head.split("name=\"").nth(1).and_then(|n| n.split('"').next()) {
+                Some(n) => n,
+                None => continue,
+            };
+            let content = content.strip_suffix("\r\n").unwrap_or(content);
+            let value = serde_json::from_str(content)
+                .unwrap_or_else(|_| serde_json::Value::String(content.to_string()));
+            fields.insert(name.to_string(), value);
+        }
+
+        // Files are referenced by fields like `"document": "attach://<name>"`
+        let attached: Vec<(String, String)> = fields
+            .iter()
+            .filter_map(|(k, v)| Some((k.clone(), v.as_str()?.strip_prefix("attach://")?.to_string())))
+            .collect();
+        for (field, name) in attached {
+            if let Some(file) = fields.remove(&name) {
+                fields.insert(field, file);
+            }
+        }
+        serde_json::Value::Object(fields)
+    }
+}
+
+/// Response of the mock server, with a JSON body unless it is e.g. an image.
+#[derive(Clone, Debug)]
+pub struct Response {
+    pub status: u16,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn ok(body: impl Into<Vec<u8>>) -> Self {
+        Response {
+            status: 200,
+            body: body.into(),
+        }
+    }
+
+    pub fn not_found() -> Self {
+        Response {
+            status: 404,
+            body: br#"{"error": "not found"}"#.to_vec(),
+        }
+    }
+}
+
+type Handler = Arc<dyn Fn(&Request) -> Response + Send + Sync>;
+
+/// Local HTTP server answering every request with the given handler and recording all requests.
+pub struct MockServer {
+    /// Base URL of the server, e.g. `http://127.0.0.1:1234`
+    pub url: String,
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl MockServer {
+    pub async fn start(handler: impl Fn(&Request) -> Response + Send + Sync + 'static) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests: Arc<Mutex<Vec<Request>>> = Arc::new(Mutex::new(vec![]));
+        let handler: Handler = Arc::new(handler);
+
+        let recorded = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let handler = handler.clone();
+                let recorded = recorded.clone();
+                tokio::spawn(async move {
+                    let _ = serve(stream, handler, recorded).await;
+                });
+            }
+        });
+
+        MockServer { url, requests }
+    }
+
+    /// All requests received so far.
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+/// Serves a single request, the connection is closed afterwards.
+async fn serve(
+    mut stream: TcpStream,
+    handler: Handler,
+    recorded: Arc<Mutex<Vec<Request>>>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let mut data: Vec<u8> = vec![];
+    let mut buf = [0u8; 4096];
+
+    // Read until the end of the headers
+    let header_end = loop {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        data.extend_from_slice(&buf[..n]);
+        if let Some(pos) = data.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+    };
+
+    let head = String::from_utf8_lossy(&data[..header_end]).to_string();
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or_default().split(' ');
+    let method = request_line.next().unwrap_or_default().to_string();
+    let target = request_line.next().unwrap_or_default().to_string();
+
+    let mut content_length = 0;
+    let mut chunked = false;
+    for line in lines {
+        let (name, value) = match line.split_once(':') {
+            Some(h) => h,
+            None => continue,
+        };
+        match name.trim().to_lowercase().as_str() {
+            "content-length" => content_length = value.trim().parse().unwrap_or(0),
+            "transfer-encoding" => chunked = value.trim().eq_ignore_ascii_case("chunked"),
+            _ => (),
+        }
+    }
+
+    let mut body = data[header_end..].to_vec();
+    if chunked {
+        body = read_chunked(&mut stream, body).await?;
+    } else {
+        while body.len() < content_length {
+            let n = stream.read(&mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            body.extend_from_slice(&buf[..n]);
+        }
+    }
+
+    let (path, query) = match target.split_once('?') {
+        Some((p, q)) => (p.to_string(), q.to_string()),
+        None => (target, String::new()),
+    };
+    let request = Request {
+        method,
+        path,
+        query,
+        body,
+    };
+    recorded.lock().unwrap().push(request.clone());
+
+    let response = handler(&request);
+    let head = format!(
+        "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        response.body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(&response.body).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// Reads a body with chunked transfer encoding, `data` holds the bytes already read.
+async fn read_chunked(
+    stream: &mut TcpStream,
+    mut data: Vec<u8>,
+) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+    let mut buf = [0u8; 4096];
+    let mut body: Vec<u8> = vec![];
+    let mut pos = 0;
+
+    loop {
+        // Wait for the complete size line of the next chunk
+        let line_end = loop {
+            if let Some(end) = data[pos..].windows(2).position(|w| w == b"\r\n") {
+                break pos + end;
+            }
+            let n = stream.read(&mut buf).await?;
+            if n == 0 {
+                return Ok(body);
+            }
+            data.extend_from_slice(&buf[..n]);
+        };
+        let size_line = String::from_utf8_lossy(&data[pos..line_end]).to_string();
+        let size = usize::from_str_radix(size_line.split(';').next().unwrap_or("0").trim(), 16)?;
+        if size == 0 {
+            return Ok(body);
+        }
+
+        let chunk_start = line_end + 2;
+        while data.len() < chunk_start + size + 2 {
+            let n = stream.read(&mut buf).await?;
+            if n == 0 {
+                return Ok(body);
+            }
+            data.extend_from_slice(&buf[..n]);
+        }
+        body.extend_from_slice(&data[chunk_start..chunk_start + size]);
+        pos = chunk_start + size + 2;
+    }
+}
+
+//////////////////////////////////////////////////////////
+// Fixtures
+//////////////////////////////////////////////////////////
+pub const NEARBY: &str = include_str!("fixtures/nearby.json");
+pub const DEPARTURES: &str = include_str!("fixtures/departures.json");
+pub const ARRIVALS: &str = include_str!("fixtures/arrivals.json");
+pub const TRIP: &str = include_str!("fixtures/trip.json");
+pub const GEOCODE: &str = include_str!("fixtures/geocode.json");
+pub const REVERSE: &str = include_str!("fixtures/reverse.json");
+pub const LOCATIONS: &str = include_str!("fixtures/locations.json");
+
+/// Answers requests of the transport.rest and LocationIQ APIs with the recorded fixtures.
+pub fn api_fixtures(request: &Request) -> Response {
+    let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+    match segments.as_slice() {
+        ["stops", "nearby"] => Response::ok(NEARBY),
+        ["locations"] => Response::ok(LOCATIONS),
+        ["stops", _, "departures"] => Response::ok(DEPARTURES),
+        ["stops", _, "arrivals"] => Response::ok(ARRIVALS),
+        ["trips", _] => Response::ok(TRIP),
+        ["search"] => Response::ok(GEOCODE),
+        ["reverse"] => Response::ok(REVERSE),
+        _ => Response::not_found(),
+    }
+}
+
+/// Starts a mock server of both APIs serving the recorded fixtures.
+pub async fn start_api_mock() -> MockServer {
+    MockServer::start(api_fixtures).await
+}
@@ -0,0 +1,122 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use teloxide::types::UserId;
+use tokio::time::{Duration, Instant};
+
+use crate::config;
+
+//////////////////////////////////////////////////////////
+// Throttling
+//////////////////////////////////////////////////////////
+/// Number of users above which the buckets of users with a full budget are dropped
+const MAX_BUCKETS: usize = 10_000;
+
+/// Budgets of updates per minute of each user and of all users together, which refill
+/// continuously. Updates beyond them are refused before any API is called.
+#[derive(Clone, Debug)]
+pub struct Throttle {
+    user_budget: u32,
+    global_budget: u32,
+    buckets: Arc<Mutex<Buckets>>,
+}
+
+#[derive(Debug)]
+struct Buckets {
+    global: Bucket,
+    users: HashMap<UserId, Bucket>,
+}
+
+/// Update refused by the throttle.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cooldown {
+    /// Time until the user can send the next update
+    pub wait: Duration,
+    /// Whether this is the first refused update, only that one is answered with a message
+    pub notify: bool,
+}
+
+impl Throttle {
+    pub fn new(user_budget: u32, global_budget: u32) -> Self {
+        let now = Instant::now();
+        Throttle {
+            user_budget,
+            global_budget,
+            buckets: Arc::new(Mutex::new(Buckets {
+                global: Bucket::full(global_budget, now),
+                users: HashMap::new(),
+            })),
+        }
+    }
+
+    /// Creates the throttle with the budgets of the configuration.
+    pub fn from_env() -> Self {
+        Throttle::new(config::user_rate_limit(), config::global_rate_limit())
+    }
+
+    /// Takes an update of the user from both budgets, or returns how long the user has to wait.
+    pub fn check(&self, user_id: UserId) -> Result<(), Cooldown> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        let Buckets { global, users } = &mut *buckets;
+
+        global.refill(self.global_budget, now);
+        if users.len() > MAX_BUCKETS {
+            users.retain(|_, b| {
+                b.refill(self.user_budget, now);
+                b.tokens < self.user_budget as f64
+            });
+        }
+        let user = users
+            .entry(user_id)
+            .or_insert_with(|| Bucket::full(self.user_budget, now));
+        user.refill(self.user_budget, now);
+
+        let wait = if user.tokens < 1.0 {
+            user.wait(self.user_budget)
+        } else if global.tokens < 1.0 {
+            global.wait(self.global_budget)
+        } else {
+            user.tokens -= 1.0;
+            global.tokens -= 1.0;
+            user.notified = false;
+            return Ok(());
+        };
+
+        let notify = !user.notified;
+        user.notified = true;
+        Err(Cooldown { wait, notify })
+    }
+}
+
+/// Token bucket holding up to a budget of updates, refilled by the budget every minute.
+#[derive(Clone, Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    /// Whether the user was told about the cooldown since the last accepted update
+    notified: bool,
+}
+
+impl Bucket {
+    fn full(budget: u32, now: Instant) -> Self {
+        Bucket {
+            tokens: budget as f64,
+            updated: now,
+            notified: false,
+        }
+    }
+
+    fn refill(&mut self, budget: u32, now: Instant) {
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * budget as f64 / 60.0).min(budget as f64);
+        self.updated = now;
+    }
+
+    /// Time until the next update fits into the budget.
+    fn wait(&self, budget: u32) -> Duration {
+        Duration::from_secs_f64(((1.0 - self.tokens) * 60.0 / budget.max(1) as f64).max(0.0))
+    }
+}
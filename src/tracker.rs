@@ -0,0 +1,414 @@
+use std::{fmt, future::Future};
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::*;
+
+//////////////////////////////////////////////////////////
+// Clock
+//////////////////////////////////////////////////////////
+/// Source of the current time of a tracking.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// Clock starting at a given date and advancing with tokio's time, so it can be paused and
+/// advanced in tests.
+#[derive(Clone, Debug)]
+pub struct TokioClock {
+    start: DateTime<Utc>,
+    instant: tokio::time::Instant,
+}
+
+impl TokioClock {
+    pub fn starting_at(start: DateTime<Utc>) -> Self {
+        TokioClock {
+            start,
+            instant: tokio::time::Instant::now(),
+        }
+    }
+}
+
+impl Clock for TokioClock {
+    fn now(&self) -> DateTime<Utc> {
+        // Whole seconds like the dates of Telegram messages
+        let elapsed = self.instant.elapsed().as_secs() as i64;
+        self.start + Duration::seconds(elapsed)
+    }
+}
+
+//////////////////////////////////////////////////////////
+// Departure sources
+//////////////////////////////////////////////////////////
+/// Source of the departures (or arrivals) a tracked transit is looked up in.
+pub trait DepartureSource: Send + Sync {
+    fn departures(
+        &self,
+    ) -> impl Future<Output = Result<Vec<TransitDeparture>, Box<dyn Error + Send + Sync>>> + Send;
+
+    /// Trip of a departure with all of its stopovers, to show the route of the transit.
+    fn trip(
+        &self,
+        dep: &TransitDeparture,
+    ) -> impl Future<Output = Result<Trip, Box<dyn Error + Send + Sync>>> + Send;
+}
+
+/// Board of a stop fetched from the transport API.
+#[derive(Clone, Debug)]
+pub struct BoardSource {
+    pub api: Api,
+    pub stop_id: String,
+    pub board: BoardKind,
+    pub products: Products,
+    pub query: BoardQuery,
+}
+
+impl DepartureSource for BoardSource {
+    async fn departures(&self) -> Result<Vec<TransitDeparture>, Box<dyn Error + Send + Sync>> {
+        self.api
+            .get_board(self.stop_id.clone(), self.board, &self.products, &self.query)
+            .await
+    }
+
+    async fn trip(&self, dep: &TransitDeparture) -> Result<Trip, Box<dyn Error + Send + Sync>> {
+        if dep.trip_id.is_empty() {
+            Err("Departure without trip!")?
+        }
+        self.api.get_trip(dep.trip_id.clone(), dep.name.clone()).await
+    }
+}
+
+//////////////////////////////////////////////////////////
+// Notification plans
+//////////////////////////////////////////////////////////
+/// When the countdown of a tracking is sent.
+#[derive(Clone, Debug, PartialEq)]
+pub enum NotifyPlan {
+    /// Every given minutes
+    Every(i64),
+    /// Only once the given minutes before the transit arrives are reached, in descending order
+    At(Vec<i64>),
+}
+
+impl NotifyPlan {
+    pub const MAX_INTERVAL: i64 = 60;
+    pub const MAX_MINUTES: i64 = 120;
+    pub const MAX_THRESHOLDS: usize = 5;
+
+    /// Parses a plan like `2` for every 2 minutes or `10, 5, 2` for 10, 5 and 2 minutes before
+    /// the transit arrives, `None` if it is invalid.
+    pub fn parse(text: &str) -> Option<NotifyPlan> {
+        let minutes = text
+            .split([',', ' '])
+            .filter(|m| !m.is_empty())
+            .map(|m| m.parse::<i64>().ok())
+            .collect::<Option<Vec<i64>>>()?;
+
+        match minutes.as_slice() {
+            [] => None,
+            [interval] => {
+                (1..=NotifyPlan::MAX_INTERVAL).contains(interval).then_some(NotifyPlan::Every(*interval))
+            }
+            _ => {
+                let mut thresholds = minutes.clone();
+                thresholds.sort_unstable_by(|a, b| b.cmp(a));
+                thresholds.dedup();
+                let valid = thresholds.len() <= NotifyPlan::MAX_THRESHOLDS
+                    && thresholds.iter().all(|m| (1..=NotifyPlan::MAX_MINUTES).contains(m));
+                valid.then_some(NotifyPlan::At(thresholds))
+            }
+        }
+    }
+}
+
+impl fmt::Display for NotifyPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NotifyPlan::Every(interval) => write!(f, "{}", interval),
+            NotifyPlan::At(thresholds) => {
+                let minutes: Vec<String> = thresholds.iter().map(|m| m.to_string()).collect();
+                write!(f, "{}", minutes.join(", "))
+            }
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////
+// Tracking engine
+//////////////////////////////////////////////////////////
+/// Event of a tracking, received by notifiers like the Telegram one.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    /// The user has to leave now to reach the stop in time
+    Leave { minutes: i64 },
+    /// The transit arrives in the given minutes, `leave_in` until the user has to leave
+    Countdown { minutes: i64, leave_in: Option<i64> },
+    /// The transit arrives within the current minute
+    ArrivingNow,
+    /// The delay of the transit changed, negative if it is early
+    Delayed { minutes: i64 },
+    /// The transit moved to a new position, `route` are the stops it passes until the tracked
+    /// stop, which is the last one. Empty if the trip of the transit is unknown.
+    PositionUpdate { position: Location, route: Vec<Location> },
+    /// The transit has departed, the tracking is done
+    Departed,
+    /// The transit has been cancelled, the tracking is done
+    Cancelled,
+    /// The transit is not on the board anymore, the tracking is done
+    Vanished,
+}
+
+impl Event {
+    /// Whether the tracking ends with this event.
+    pub fn is_final(&self) -> bool {
+        matches!(self, Event::Departed | Event::Cancelled | Event::Vanished)
+    }
+}
+
+/// Countdown of a tracked transit, updated according to its notification plan.
+pub struct Tracker<C: Clock, S: DepartureSource> {
+    clock: C,
+    source: S,
+    /// Trip of the transit, the same line may be on the board more than once
+    trip_id: String,
+    /// Thresholds of the plan are removed once they have been notified
+    plan: NotifyPlan,
+    /// Time until the next tracking update in minutes
+    update_time: i64,
+    /// Estimated time to walk from the user's address to the stop
+    walk_time: Option<Duration>,
+    leave_alert_sent: bool,
+    polled: bool,
+    /// Delay in minutes of the last update
+    delay: i64,
+    position: Option<Location>,
+    /// Stops of the trip up to the tracked stop, fetched with the first position
+    route: Option<Vec<Location>>,
+}
+
+impl<C: Clock, S: DepartureSource> Tracker<C, S> {
+    pub fn new(
+        clock: C,
+        source: S,
+        trip_id: String,
+        plan: NotifyPlan,
+        walk_time: Option<Duration>,
+    ) -> Self {
+        let update_time = match plan {
+            NotifyPlan::Every(interval) => interval.max(1),
+            NotifyPlan::At(_) => 1,
+        };
+        Tracker {
+            clock,
+            source,
+            trip_id,
+            plan,
+            update_time,
+            walk_time,
+            leave_alert_sent: walk_time.is_none(),
+            polled: false,
+            delay: 0,
+            position: None,
+            route: None,
+        }
+    }
+
+    pub fn update_time(&self) -> i64 {
+        self.update_time
+    }
+
+    /// Stops of the trip of the departure up to the tracked stop, the trip is only fetched once.
+    async fn route(&mut self, dep: &TransitDeparture) -> &[Location] {
+        if self.route.is_none() {
+            let route = match self.source.trip(dep).await {
+                Ok(trip) => {
+                    let stops = trip.stopovers.iter().map(|s| (s.stop.id.as_str(), &s.stop.location));
+                    let stop = stops.clone().position(|(id, _)| id == dep.stop_id);
+                    stops.take(stop.map_or(0, |s| s + 1)).map(|(_, l)| l.clone()).collect()
+                }
+                Err(e) => {
+                    log::warn!("Unable to fetch the trip {}: {}", self.trip_id, e);
+                    vec![]
+                }
+            };
+            self.route = Some(route);
+        }
+        self.route.as_deref().unwrap_or_default()
+    }
+
+    /// Tracks the transit until it is gone, sending all events to `events`.
+    pub async fn run(
+        mut self,
+        events: UnboundedSender<Event>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        loop {
+            tokio::select! {
+                _ = self.wait() => {}
+                // Nobody is listening anymore, e.g. the chat can't be reached
+                _ = events.closed() => return Ok(()),
+            }
+            for event in self.poll().await? {
+                let done = event.is_final();
+                // Nobody is listening anymore
+                if events.send(event).is_err() || done {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Waits until the next update is due, the first one is due immediately.
+    pub async fn wait(&mut self) {
+        if self.polled {
+            tokio::time::sleep(Duration::minutes(self.update_time).to_std().unwrap()).await;
+        }
+    }
+
+    /// Looks up the transit and computes the events of this update.
+    pub async fn poll(&mut self) -> Result<Vec<Event>, Box<dyn Error + Send + Sync>> {
+        let first = !self.polled;
+        self.polled = true;
+        let now = self.clock.now();
+        let deps = self.source.departures().await?;
+
+        // Get the departure of the trip the user has selected
+        let dep = match find_transit(&deps, &self.trip_id) {
+            Some(d) => d.clone(),
+            None => return Ok(vec![Event::Vanished]),
+        };
+        if dep.cancelled {
+            return Ok(vec![Event::Cancelled]);
+        }
+
+        // Planned departure time plus the delay if exists
+        let mut dep_time = match DateTime::parse_from_rfc3339(&dep.planned) {
+            Ok(t) => t.with_timezone(&Utc),
+            Err(e) => return Err(e)?,
+        };
+        if let Some(del) = dep.delay {
+            dep_time += Duration::seconds(del);
+        }
+        // Stop if transit already departured
+        if now > dep_time {
+            return Ok(vec![Event::Departed]);
+        }
+        // Duration till departure
+        let dur = dep_time.signed_duration_since(now);
+
+        // Duration till the user has to leave to reach the stop in time
+        let leave_dur = dur - self.walk_time.unwrap_or_else(Duration::zero);
+
+        // Wake up for the leave alert if it comes before the departure
+        let next_dur = if !self.leave_alert_sent && leave_dur.num_minutes() > 0 {
+            leave_dur
+        } else {
+            dur
+        };
+
+        let minutes = dur.num_minutes();
+        let countdown = match &mut self.plan {
+            NotifyPlan::Every(_) => {
+                // When below update time, update the interval to the duration time
+                if self.update_time != 1
+                    && next_dur.num_minutes() > 0
+                    && next_dur.num_minutes() < self.update_time
+                {
+                    self.update_time = next_dur.num_minutes();
+                }
+                true
+            }
+            NotifyPlan::At(thresholds) => {
+                // Thresholds reached since the last update are notified once together
+                let reached = thresholds.iter().any(|&t| t >= minutes);
+                thresholds.retain(|&t| t < minutes);
+
+                // Wake up at the next threshold or for the leave alert, else at the departure
+                let next = thresholds.first().copied().unwrap_or(0);
+                self.update_time = (minutes - next).min(next_dur.num_minutes()).max(1);
+                first || reached
+            }
+        };
+
+        let mut events = vec![];
+
+        let delay = dep.delay.unwrap_or(0) / 60;
+        if delay != self.delay {
+            self.delay = delay;
+            events.push(Event::Delayed { minutes: delay });
+        }
+
+        if let Some(position) = dep.curr_position.clone() {
+            if self.position.as_ref() != Some(&position) {
+                self.position = Some(position.clone());
+                let route = remaining_route(self.route(&dep).await, &position);
+                events.push(Event::PositionUpdate { position, route });
+            }
+        }
+
+        // Alert the user once it is time to walk to the stop
+        if !self.leave_alert_sent && leave_dur.num_minutes() <= 0 {
+            self.leave_alert_sent = true;
+            events.push(Event::Leave {
+                minutes: dur.num_minutes(),
+            });
+        }
+
+        if minutes == 0 {
+            // Update every minute when duration below 1 minute
+            self.update_time = 1;
+            if countdown {
+                events.push(Event::ArrivingNow);
+            }
+        } else if countdown {
+            let leave_in = (!self.leave_alert_sent).then(|| leave_dur.num_minutes());
+            events.push(Event::Countdown { minutes, leave_in });
+        }
+        Ok(events)
+    }
+}
+
+/// Stops of the route the transit still passes from its position, the closest stop it reached
+/// if it isn't past it yet.
+pub fn remaining_route(route: &[Location], position: &Location) -> Vec<Location> {
+    let distance = |i: usize| route[i].distance_to(position).unwrap_or(f64::INFINITY);
+    let closest = match (0..route.len()).min_by(|&a, &b| distance(a).total_cmp(&distance(b))) {
+        Some(closest) => closest,
+        None => return vec![],
+    };
+    // Past the closest stop if the next one is nearer to the transit than to the closest one
+    let past = closest + 1 < route.len()
+        && route[closest]
+            .distance_to(&route[closest + 1])
+            .is_some_and(|d| distance(closest + 1) < d);
+    route[closest + past as usize..].to_vec()
+}
+
+/// Nearest stopover of the trip that the user at `from` reaches on foot before the transit
+/// leaves it, with the distance of its stop set. `None` if no stopover can be reached in time.
+pub fn nearest_catchable_stop(
+    trip: &Trip,
+    from: &Location,
+    now: DateTime<Utc>,
+    walking_speed: f64,
+) -> Option<Stopover> {
+    trip.stopovers
+        .iter()
+        .filter(|s| !s.cancelled)
+        .filter_map(|s| {
+            let when = DateTime::parse_from_rfc3339(s.when()?).ok()?;
+            let mut stopover = s.clone();
+            stopover.stop.distance = from.meters_to(&s.stop.location)?;
+            let walk = stopover.stop.walking_time(walking_speed)?;
+            (now + walk <= when).then_some(stopover)
+        })
+        .min_by_key(|s| s.stop.distance)
+}
+
+/// Label of a transit on the buttons and in the messages, e.g. `S 5 (Westkreuz)`.
+pub fn transit_label(dep: &TransitDeparture) -> String {
+    format!("{} ({})", dep.name, dep.direction)
+}
+
+/// Finds the transit of a trip in the departures, lines with the same label are told apart.
+pub fn find_transit<'a>(deps: &'a [TransitDeparture], trip_id: &str) -> Option<&'a TransitDeparture> {
+    deps.iter().find(|&x| !x.trip_id.is_empty() && x.trip_id == trip_id)
+}